@@ -0,0 +1,25 @@
+use crate::parser::{parse_proto, validate_proto, ProtoFile, ValidationError};
+
+/// The result of parsing and validating a document's content.
+#[derive(Debug)]
+pub struct Analysis {
+    pub proto_file: anyhow::Result<ProtoFile>,
+    pub validation_errors: Vec<ValidationError>,
+}
+
+impl Analysis {
+    pub fn new(content: &str) -> Self {
+        let _span = tracing::debug_span!("analyze", bytes = content.len()).entered();
+        let proto_file = parse_proto(content);
+        let validation_errors = proto_file.as_ref().map(validate_proto).unwrap_or_default();
+
+        Self {
+            proto_file,
+            validation_errors,
+        }
+    }
+
+    pub fn proto_file(&self) -> Option<&ProtoFile> {
+        self.proto_file.as_ref().ok()
+    }
+}
@@ -1,12 +1,82 @@
+use super::analysis::Analysis;
+use super::handlers;
+use super::settings::Settings;
+use crate::parser::ProtoFile;
 use std::collections::HashMap;
-use tower_lsp::lsp_types::Url;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+use tower_lsp::lsp_types::{Diagnostic, Position, TextDocumentContentChangeEvent, Url};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Document {
     pub content: String,
     pub version: i32,
+    /// Hash of `content`, identifying this exact text.
+    pub content_hash: u64,
+    analysis: OnceLock<Analysis>,
+    /// The last diagnostics computed for this document, keyed by the content
+    /// hash and settings they were computed under. Kept across edits, so
+    /// text that comes back unchanged isn't analyzed again.
+    diagnostics: Mutex<Option<(u64, Settings, Vec<Diagnostic>)>>,
 }
 
+impl Document {
+    fn new(content: String, version: i32) -> Self {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+
+        Self {
+            content_hash: hasher.finish(),
+            content,
+            version,
+            analysis: OnceLock::new(),
+            diagnostics: Mutex::new(None),
+        }
+    }
+
+    /// Replaces the text, keeping the diagnostics cache for reuse.
+    fn replace(&mut self, content: String, version: i32) {
+        let diagnostics = self.diagnostics.get_mut().unwrap().take();
+        *self = Self::new(content, version);
+        *self.diagnostics.get_mut().unwrap() = diagnostics;
+    }
+
+    /// Returns the parse and validation results, computing them on first access.
+    pub fn analysis(&self) -> &Analysis {
+        self.analysis.get_or_init(|| Analysis::new(&self.content))
+    }
+
+    /// Whether the analysis has already been computed.
+    pub fn has_analysis(&self) -> bool {
+        self.analysis.get().is_some()
+    }
+
+    /// Stores an analysis computed elsewhere (e.g. on a blocking thread),
+    /// keeping the existing one if it was computed in the meantime.
+    pub fn set_analysis(&self, analysis: Analysis) -> &Analysis {
+        self.analysis.get_or_init(|| analysis)
+    }
+
+    /// Returns the diagnostics for this document under `settings`, reusing
+    /// the previous result if neither the content nor the settings changed.
+    pub fn diagnostics(&self, uri: &Url, settings: &Settings) -> Vec<Diagnostic> {
+        let mut cached = self.diagnostics.lock().unwrap();
+        if let Some((content_hash, cached_settings, diagnostics)) = cached.as_ref() {
+            if *content_hash == self.content_hash && cached_settings == settings {
+                return diagnostics.clone();
+            }
+        }
+
+        let diagnostics = match handlers::oversized_file_diagnostic(&self.content, settings) {
+            Some(diagnostic) => vec![diagnostic],
+            None => handlers::diagnostics_for_analysis(Some(uri), self.analysis(), settings),
+        };
+        *cached = Some((self.content_hash, settings.clone(), diagnostics.clone()));
+        diagnostics
+    }
+}
+
+#[derive(Default)]
 pub struct DocumentStore {
     documents: HashMap<Url, Document>,
 }
@@ -19,14 +89,40 @@ impl DocumentStore {
     }
 
     pub fn open_document(&mut self, uri: Url, content: String, version: i32) {
-        self.documents.insert(uri, Document { content, version });
+        self.documents.insert(uri, Document::new(content, version));
     }
 
     pub fn update_document(&mut self, uri: Url, content: String, version: i32) {
         if let Some(doc) = self.documents.get_mut(&uri) {
-            doc.content = content;
-            doc.version = version;
+            doc.replace(content, version);
+        }
+    }
+
+    /// Applies `changes` in order: a change without a range replaces the
+    /// whole text, one with a range splices into the result of the previous
+    /// changes.
+    pub fn apply_changes(
+        &mut self,
+        uri: &Url,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) {
+        let Some(doc) = self.documents.get_mut(uri) else {
+            return;
+        };
+
+        let mut content = std::mem::take(&mut doc.content);
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = offset_at(&content, range.start);
+                    let end = offset_at(&content, range.end).max(start);
+                    content.replace_range(start..end, &change.text);
+                }
+                None => content = change.text,
+            }
         }
+        doc.replace(content, version);
     }
 
     pub fn close_document(&mut self, uri: &Url) {
@@ -36,4 +132,182 @@ impl DocumentStore {
     pub fn get_document(&self, uri: &Url) -> Option<&Document> {
         self.documents.get(uri)
     }
+
+    /// Returns the cached AST for a document, parsing it lazily on first access.
+    pub fn get_parsed(&self, uri: &Url) -> Option<&ProtoFile> {
+        self.get_document(uri)?.analysis().proto_file()
+    }
+
+    pub fn documents(&self) -> impl Iterator<Item = (&Url, &Document)> {
+        self.documents.iter()
+    }
+}
+
+/// Converts an LSP position (UTF-16 columns) into a byte offset into
+/// `content`, clamped to the end of its line and of the document.
+fn offset_at(content: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match content[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return content.len(),
+        }
+    }
+
+    let mut column = 0;
+    let line = &content[line_start..];
+    for (offset, ch) in line.char_indices() {
+        // Clamp before a CRLF line break so edits never split it
+        let line_end = ch == '\n' || line[offset..].starts_with("\r\n");
+        if column >= position.character as usize || line_end {
+            return line_start + offset;
+        }
+        column += ch.len_utf16();
+    }
+    content.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Statement;
+
+    fn message_names(proto_file: &ProtoFile) -> Vec<&str> {
+        proto_file
+            .statements
+            .iter()
+            .filter_map(|statement| match &statement.node {
+                Statement::Message(message) => Some(message.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_cache_lifecycle() {
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let mut store = DocumentStore::new();
+
+        store.open_document(uri.clone(), "message First {}".to_string(), 1);
+        assert!(store.get_document(&uri).unwrap().analysis.get().is_none());
+
+        assert_eq!(message_names(store.get_parsed(&uri).unwrap()), ["First"]);
+        assert!(store.get_document(&uri).unwrap().analysis.get().is_some());
+
+        store.update_document(uri.clone(), "message Second {}".to_string(), 2);
+        assert!(store.get_document(&uri).unwrap().analysis.get().is_none());
+        assert_eq!(message_names(store.get_parsed(&uri).unwrap()), ["Second"]);
+    }
+
+    #[test]
+    fn test_diagnostics_cache() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use tracing::span::{Attributes, Id};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        /// Counts how often diagnostics are actually computed.
+        struct DiagnosticsRuns(Arc<AtomicUsize>);
+
+        impl<S: tracing::Subscriber> Layer<S> for DiagnosticsRuns {
+            fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+                if attrs.metadata().name() == "compute_diagnostics" {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = "message Test { int32 a = 0; }";
+        let mut store = DocumentStore::new();
+        store.open_document(uri.clone(), content.to_string(), 1);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry().with(DiagnosticsRuns(runs.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let mut settings = Settings::default();
+            let doc = store.get_document(&uri).unwrap();
+            let first = doc.diagnostics(&uri, &settings);
+            assert!(!first.is_empty());
+            assert_eq!(doc.diagnostics(&uri, &settings), first);
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+            // Toggling a rule recomputes
+            settings
+                .disabled_rules
+                .insert("invalid-field-number".to_string());
+            let toggled = doc.diagnostics(&uri, &settings);
+            assert_ne!(toggled, first);
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+            // Content that comes back unchanged reuses the cached result
+            store.update_document(uri.clone(), content.to_string(), 2);
+            let doc = store.get_document(&uri).unwrap();
+            assert_eq!(doc.diagnostics(&uri, &settings), toggled);
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+            // New content is analyzed again
+            store.update_document(uri.clone(), "message Test { int32 a = 1 }".to_string(), 3);
+            let doc = store.get_document(&uri).unwrap();
+            assert_ne!(doc.diagnostics(&uri, &settings), toggled);
+            assert_eq!(runs.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn test_offset_at_crlf() {
+        let content = "message A {}\r\nmessage B {}\r\n";
+        assert_eq!(offset_at(content, Position::new(1, 8)), 22);
+        // Past the end of a line stops before its `\r\n`
+        assert_eq!(offset_at(content, Position::new(0, 40)), 12);
+    }
+
+    #[test]
+    fn test_apply_changes_in_order() {
+        use tower_lsp::lsp_types::Range;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let mut store = DocumentStore::new();
+        store.open_document(uri.clone(), "message First {}\n".to_string(), 1);
+
+        let splice = |line, start, end, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(line, start),
+                Position::new(line, end),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        };
+
+        // The second change's range refers to the text after the first
+        store.apply_changes(
+            &uri,
+            vec![
+                splice(0, 8, 13, "Second"),
+                splice(0, 16, 16, " int32 id = 1; "),
+            ],
+            2,
+        );
+        let doc = store.get_document(&uri).unwrap();
+        assert_eq!(doc.content, "message Second { int32 id = 1; }\n");
+        assert_eq!(doc.version, 2);
+
+        // Full-text changes replace everything before them
+        store.apply_changes(
+            &uri,
+            vec![
+                splice(0, 0, 0, "// dropped\n"),
+                TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "message Third {}".to_string(),
+                },
+            ],
+            3,
+        );
+        assert_eq!(
+            store.get_document(&uri).unwrap().content,
+            "message Third {}"
+        );
+    }
 }
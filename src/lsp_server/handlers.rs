@@ -1,51 +1,173 @@
-use crate::parser::{parse_proto, validate_proto};
+use super::analysis::Analysis;
+use super::document_store::{Document, DocumentStore};
+use super::settings::{Settings, WellKnownTypeCompletions};
+use super::well_known_types::{self, WELL_KNOWN_TYPES};
+use super::workspace_index::WorkspaceIndex;
+use crate::parser::{
+    self, resolve_field_features, resolve_file_features, Enum, EnumValue, FeatureSet, Field,
+    FieldLabel, Message, Method, Oneof, OptionValue, ProtoFile, Service, Severity, Statement,
+    Token,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
 use tower_lsp::lsp_types::*;
 
 pub fn compute_diagnostics(content: &str) -> Vec<Diagnostic> {
+    compute_diagnostics_with_settings(content, &Settings::default())
+}
+
+pub fn compute_diagnostics_with_settings(content: &str, settings: &Settings) -> Vec<Diagnostic> {
+    if let Some(diagnostic) = oversized_file_diagnostic(content, settings) {
+        return vec![diagnostic];
+    }
+    diagnostics_for_analysis(None, &Analysis::new(content), settings)
+}
+
+/// Like [`compute_diagnostics_with_settings`], but also attaches related
+/// locations (such as the first use of a duplicate) within `uri`.
+pub fn compute_document_diagnostics(
+    uri: &Url,
+    content: &str,
+    settings: &Settings,
+) -> Vec<Diagnostic> {
+    if let Some(diagnostic) = oversized_file_diagnostic(content, settings) {
+        return vec![diagnostic];
+    }
+    diagnostics_for_analysis(Some(uri), &Analysis::new(content), settings)
+}
+
+/// The pull-diagnostics report for `document`. Each report carries a
+/// `result_id` derived from the content and its diagnostics; if the client
+/// already has that result, an `Unchanged` report is returned instead.
+pub fn compute_document_diagnostic_report(
+    uri: &Url,
+    document: &Document,
+    settings: &Settings,
+    previous_result_id: Option<&str>,
+) -> DocumentDiagnosticReport {
+    let diagnostics = document.diagnostics(uri, settings);
+    let result_id = diagnostics_result_id(document, &diagnostics);
+
+    if previous_result_id == Some(result_id.as_str()) {
+        return DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+        });
+    }
+
+    DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+        related_documents: None,
+        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+            result_id: Some(result_id),
+            items: diagnostics,
+        },
+    })
+}
+
+/// Identifies a diagnostics result: the same content can produce different
+/// diagnostics once rules are toggled, so both go into the id.
+fn diagnostics_result_id(document: &Document, diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    document.content_hash.hash(&mut hasher);
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// The single informational diagnostic reported instead of analyzing a
+/// document over the configured `max_file_size`.
+pub(crate) fn oversized_file_diagnostic(content: &str, settings: &Settings) -> Option<Diagnostic> {
+    (content.len() > settings.max_file_size).then(|| Diagnostic {
+        range: Range::default(),
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(NumberOrString::String("file-too-large".to_string())),
+        source: Some("protobuf-edition-lsp".to_string()),
+        message: format!(
+            "File is {} bytes, over the {} byte limit; diagnostics are disabled for it",
+            content.len(),
+            settings.max_file_size
+        ),
+        ..Default::default()
+    })
+}
+
+pub(crate) fn diagnostics_for_analysis(
+    uri: Option<&Url>,
+    analysis: &Analysis,
+    settings: &Settings,
+) -> Vec<Diagnostic> {
+    let span = tracing::debug_span!("compute_diagnostics", diagnostics = tracing::field::Empty);
+    let _entered = span.enter();
     let mut diagnostics = Vec::new();
 
-    // Parse the protocol buffer file
-    match parse_proto(content) {
+    match &analysis.proto_file {
         Ok(proto_file) => {
-            // Validate the parsed file
-            let validation_errors = validate_proto(&proto_file);
+            for error in &analysis.validation_errors {
+                if !settings.is_rule_enabled(error.code) {
+                    continue;
+                }
 
-            for error in validation_errors {
                 diagnostics.push(Diagnostic {
-                    range: Range {
-                        start: Position {
-                            line: error.line as u32,
-                            character: error.column as u32,
-                        },
-                        end: Position {
-                            line: error.line as u32,
-                            character: error.column as u32,
-                        },
-                    },
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    code: None,
+                    range: to_range(error.span),
+                    severity: Some(to_diagnostic_severity(error.severity)),
+                    code: Some(NumberOrString::String(error.code.to_string())),
                     code_description: None,
                     source: Some("protobuf-edition-lsp".to_string()),
-                    message: error.message,
-                    related_information: None,
+                    message: error.message.clone(),
+                    related_information: uri.filter(|_| !error.related.is_empty()).map(|uri| {
+                        error
+                            .related
+                            .iter()
+                            .map(|related| DiagnosticRelatedInformation {
+                                location: Location::new(uri.clone(), to_range(related.span)),
+                                message: related.message.clone(),
+                            })
+                            .collect()
+                    }),
                     tags: None,
                     data: None,
                 });
             }
+
+            if settings.is_rule_enabled(DEPRECATED) {
+                diagnostics.extend(deprecation_hints(proto_file));
+            }
+
+            let require_methods =
+                settings.require_methods && settings.is_rule_enabled(EMPTY_SERVICE);
+            if require_methods {
+                diagnostics.extend(empty_service_warnings(proto_file));
+            }
+
+            if settings.lint_empty_declarations && settings.is_rule_enabled(EMPTY_DECLARATION) {
+                diagnostics.extend(empty_declaration_hints(proto_file, !require_methods));
+            }
+
+            if let Some((syntax, span)) = legacy_syntax(proto_file, settings) {
+                diagnostics.push(Diagnostic {
+                    range: to_range(span),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(PREFER_EDITIONS.to_string())),
+                    source: Some("protobuf-edition-lsp".to_string()),
+                    message: format!(
+                        "'syntax = \"{syntax}\"' is a legacy syntax; consider migrating to 'edition = \"2023\"'"
+                    ),
+                    ..Default::default()
+                });
+            }
         }
         Err(e) => {
-            // Parse error
+            // Parse error, at the offending token when the parser knows it
+            let range = e
+                .downcast_ref::<parser::PositionedParseError>()
+                .map(|error| to_range(error.span))
+                .unwrap_or_default();
             diagnostics.push(Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                },
+                range,
                 severity: Some(DiagnosticSeverity::ERROR),
                 code: None,
                 code_description: None,
@@ -58,16 +180,271 @@ pub fn compute_diagnostics(content: &str) -> Vec<Diagnostic> {
         }
     }
 
+    span.record("diagnostics", diagnostics.len());
     diagnostics
 }
 
+/// Code of the hints marking declarations with `deprecated = true`.
+const DEPRECATED: &str = "deprecated";
+
+/// Hints that let editors strike through the name of every message, field,
+/// enum, enum value, service, and method marked `deprecated = true`.
+fn deprecation_hints(proto_file: &ProtoFile) -> Vec<Diagnostic> {
+    let mut declarations = Vec::new();
+    for statement in &proto_file.statements {
+        match &statement.node {
+            Statement::Message(message) => collect_deprecated_message(message, &mut declarations),
+            Statement::Enum(enum_def) => collect_deprecated_enum(enum_def, &mut declarations),
+            Statement::Service(service) => {
+                if is_deprecated(&service.options) {
+                    declarations.push(("Service", &service.name, service.name_span));
+                }
+                for method in &service.methods {
+                    if is_deprecated(&method.options) {
+                        declarations.push(("Method", &method.name, method.name_span));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    declarations
+        .into_iter()
+        .map(|(kind, name, span)| Diagnostic {
+            range: to_range(span),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(DEPRECATED.to_string())),
+            source: Some("protobuf-edition-lsp".to_string()),
+            message: format!("{kind} '{name}' is deprecated"),
+            tags: Some(vec![DiagnosticTag::DEPRECATED]),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// A deprecated declaration: what kind it is, its name, and its name span.
+type DeprecatedDeclaration<'a> = (&'static str, &'a str, parser::Span);
+
+fn collect_deprecated_message<'a>(
+    message: &'a Message,
+    declarations: &mut Vec<DeprecatedDeclaration<'a>>,
+) {
+    if is_deprecated(&message.options) {
+        declarations.push(("Message", &message.name, message.name_span));
+    }
+
+    let fields = message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()));
+    for field in fields {
+        if is_deprecated(&field.options) {
+            declarations.push(("Field", &field.name, field.name_span));
+        }
+    }
+
+    for nested in &message.nested_messages {
+        collect_deprecated_message(nested, declarations);
+    }
+    for nested in &message.nested_enums {
+        collect_deprecated_enum(nested, declarations);
+    }
+}
+
+fn collect_deprecated_enum<'a>(
+    enum_def: &'a Enum,
+    declarations: &mut Vec<DeprecatedDeclaration<'a>>,
+) {
+    if is_deprecated(&enum_def.options) {
+        declarations.push(("Enum", &enum_def.name, enum_def.name_span));
+    }
+    for value in &enum_def.values {
+        if is_deprecated(&value.options) {
+            declarations.push(("Enum value", &value.name, value.name_span));
+        }
+    }
+}
+
+fn is_deprecated(options: &HashMap<String, OptionValue>) -> bool {
+    options.get("deprecated") == Some(&OptionValue::Bool(true))
+}
+
+/// Code of the opt-in hints enabled by `lint_empty_declarations`.
+const EMPTY_DECLARATION: &str = "empty-declaration";
+
+/// Hints at messages without fields or nested types and, if `services`,
+/// services without methods. Empty enums are a validation error instead,
+/// since they lack a zero value.
+fn empty_declaration_hints(proto_file: &ProtoFile, services: bool) -> Vec<Diagnostic> {
+    fn collect<'a>(message: &'a Message, empty: &mut Vec<(&'static str, &'a str, parser::Span)>) {
+        if message.fields.is_empty()
+            && message.oneofs.is_empty()
+            && message.nested_messages.is_empty()
+            && message.nested_enums.is_empty()
+        {
+            empty.push(("Message", &message.name, message.name_span));
+        }
+        for nested in &message.nested_messages {
+            collect(nested, empty);
+        }
+    }
+
+    let mut empty = Vec::new();
+    for statement in &proto_file.statements {
+        match &statement.node {
+            Statement::Message(message) => collect(message, &mut empty),
+            Statement::Service(service) if services && service.methods.is_empty() => {
+                empty.push(("Service", &service.name, service.name_span));
+            }
+            _ => {}
+        }
+    }
+
+    empty
+        .into_iter()
+        .map(|(kind, name, span)| Diagnostic {
+            range: to_range(span),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(EMPTY_DECLARATION.to_string())),
+            source: Some("protobuf-edition-lsp".to_string()),
+            message: format!("{kind} '{name}' is empty"),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Code of the opt-in warnings enabled by `require_methods`.
+const EMPTY_SERVICE: &str = "empty-service";
+
+/// Warns about services without methods, which are usually unfinished.
+fn empty_service_warnings(proto_file: &ProtoFile) -> Vec<Diagnostic> {
+    proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Service(service) if service.methods.is_empty() => Some(Diagnostic {
+                range: to_range(service.name_span),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(EMPTY_SERVICE.to_string())),
+                source: Some("protobuf-edition-lsp".to_string()),
+                message: format!("Service '{}' declares no methods", service.name),
+                ..Default::default()
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Code of the opt-in diagnostic enabled by `require_editions`.
+const PREFER_EDITIONS: &str = "prefer-editions";
+
+/// The `syntax` declaration of `proto_file`, if `require_editions` is on and
+/// it should be migrated to an edition.
+fn legacy_syntax<'a>(
+    proto_file: &'a ProtoFile,
+    settings: &Settings,
+) -> Option<(&'a str, parser::Span)> {
+    if !settings.require_editions || !settings.is_rule_enabled(PREFER_EDITIONS) {
+        return None;
+    }
+    if proto_file.edition.is_some() {
+        return None;
+    }
+
+    let syntax = proto_file.syntax.as_deref()?;
+    matches!(syntax, "proto2" | "proto3").then_some((syntax, proto_file.syntax_span?))
+}
+
+fn to_diagnostic_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Information => DiagnosticSeverity::INFORMATION,
+        Severity::Hint => DiagnosticSeverity::HINT,
+    }
+}
+
+/// Lookup key stored in a completion item's `data` so documentation can be
+/// attached lazily by `completionItem/resolve`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "name", rename_all = "camelCase")]
+enum CompletionData {
+    Scalar(String),
+    Type(String),
+    WellKnownType(String),
+}
+
 pub fn compute_completions(content: &str, position: Position) -> Vec<CompletionItem> {
+    completions_for_analysis(
+        content,
+        &Analysis::new(content),
+        position,
+        &Settings::default(),
+    )
+}
+
+pub(crate) fn completions_for_analysis(
+    content: &str,
+    analysis: &Analysis,
+    position: Position,
+    settings: &Settings,
+) -> Vec<CompletionItem> {
+    let _span = tracing::debug_span!("compute_completions", bytes = content.len()).entered();
     let mut completions = Vec::new();
 
     // Get the line and determine context
     let lines: Vec<&str> = content.lines().collect();
     if let Some(line) = lines.get(position.line as usize) {
-        let line_before_cursor = &line[..position.character.min(line.len() as u32) as usize];
+        let line_before_cursor = &line[..byte_offset_at(line, position.character as usize)];
+
+        // Values of `option idempotency_level = ...` inside an rpc body
+        if is_idempotency_level_value(line_before_cursor) {
+            return parser::IDEMPOTENCY_LEVELS
+                .iter()
+                .map(|level| CompletionItem {
+                    label: level.to_string(),
+                    kind: Some(CompletionItemKind::ENUM_MEMBER),
+                    detail: Some("Method idempotency level".to_string()),
+                    ..Default::default()
+                })
+                .collect();
+        }
+
+        // Inside the string of `edition = "..."` or `syntax = "..."`
+        if let Some(keyword) = syntax_value_keyword(line_before_cursor) {
+            let (values, detail) = match keyword {
+                "edition" => (parser::SUPPORTED_EDITIONS, "Protocol Buffers edition"),
+                _ => (parser::SUPPORTED_SYNTAXES, "Protocol Buffers syntax"),
+            };
+            return values
+                .iter()
+                .map(|value| CompletionItem {
+                    label: value.to_string(),
+                    kind: Some(CompletionItemKind::VALUE),
+                    detail: Some(detail.to_string()),
+                    ..Default::default()
+                })
+                .collect();
+        }
+
+        // The number of a field declaration: suggest the next free one
+        if is_field_number_position(line_before_cursor) {
+            if let Some((message, number)) = next_field_number(content, position) {
+                return vec![CompletionItem {
+                    label: number.to_string(),
+                    kind: Some(CompletionItemKind::VALUE),
+                    detail: Some(format!("Next free field number in '{message}'")),
+                    insert_text: Some(number.to_string()),
+                    ..Default::default()
+                }];
+            }
+        }
+
+        // Request and response types of an rpc must be messages
+        if let Some(after_stream) = rpc_type_position(line_before_cursor) {
+            return rpc_type_completions(content, after_stream);
+        }
 
         // Check if we're inside a message
         let in_message = is_inside_message(&lines, position.line as usize);
@@ -81,15 +458,60 @@ pub fn compute_completions(content: &str, position: Position) -> Vec<CompletionI
                     .is_some_and(|c| c.is_whitespace())
             {
                 // Scalar types
-                for scalar_type in &[
-                    "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64",
-                    "fixed32", "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
-                ] {
+                for scalar_type in parser::SCALAR_TYPES {
                     completions.push(CompletionItem {
                         label: scalar_type.to_string(),
                         kind: Some(CompletionItemKind::KEYWORD),
                         detail: Some("Protocol Buffers scalar type".to_string()),
                         documentation: None,
+                        data: completion_data(CompletionData::Scalar(scalar_type.to_string())),
+                        ..Default::default()
+                    });
+                }
+
+                // Messages and enums declared in this file
+                if let Some(proto_file) = analysis.proto_file() {
+                    let mut type_names = HashSet::new();
+                    for statement in &proto_file.statements {
+                        match &statement.node {
+                            Statement::Message(message) => {
+                                collect_type_names(message, &mut type_names)
+                            }
+                            Statement::Enum(enum_def) => {
+                                type_names.insert(enum_def.name.as_str());
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    let mut type_names: Vec<_> = type_names.into_iter().collect();
+                    type_names.sort_unstable();
+                    for name in type_names {
+                        completions.push(CompletionItem {
+                            label: name.to_string(),
+                            kind: Some(CompletionItemKind::CLASS),
+                            detail: Some("User-defined type".to_string()),
+                            data: completion_data(CompletionData::Type(name.to_string())),
+                            ..Default::default()
+                        });
+                    }
+                }
+
+                // google.protobuf types, optionally only those already imported
+                for wkt in WELL_KNOWN_TYPES {
+                    // Checked textually so it works while the file doesn't parse
+                    let imported = content.contains(&format!("\"{}\"", wkt.import_path));
+                    if settings.well_known_type_completions == WellKnownTypeCompletions::Imported
+                        && !imported
+                    {
+                        continue;
+                    }
+
+                    completions.push(CompletionItem {
+                        label: wkt.full_name(),
+                        kind: Some(CompletionItemKind::CLASS),
+                        detail: Some(format!("Well-known type ({})", wkt.import_path)),
+                        data: completion_data(CompletionData::WellKnownType(wkt.name.to_string())),
                         ..Default::default()
                     });
                 }
@@ -115,6 +537,22 @@ pub fn compute_completions(content: &str, position: Position) -> Vec<CompletionI
                     detail: Some("Define a oneof field".to_string()),
                     ..Default::default()
                 });
+
+                // Other declarations allowed in a message body
+                for (keyword, detail) in [
+                    ("map", "Define a map field"),
+                    ("reserved", "Reserve field numbers or names"),
+                    ("extensions", "Declare a range of extension field numbers"),
+                    ("message", "Define a nested message"),
+                    ("enum", "Define a nested enum"),
+                ] {
+                    completions.push(CompletionItem {
+                        label: keyword.to_string(),
+                        kind: Some(CompletionItemKind::KEYWORD),
+                        detail: Some(detail.to_string()),
+                        ..Default::default()
+                    });
+                }
             }
         } else {
             // Top-level keywords
@@ -137,39 +575,474 @@ pub fn compute_completions(content: &str, position: Position) -> Vec<CompletionI
     completions
 }
 
+/// Whether the text before the cursor is `option idempotency_level =`,
+/// optionally followed by part of a value.
+fn is_idempotency_level_value(line_before_cursor: &str) -> bool {
+    line_before_cursor
+        .trim_end_matches(|ch: char| ch.is_ascii_alphanumeric() || ch == '_')
+        .trim_end()
+        .strip_suffix('=')
+        .and_then(|rest| rest.trim_end().strip_suffix("idempotency_level"))
+        .is_some_and(|rest| rest.trim() == "option")
+}
+
+/// Returns `edition` or `syntax` if the text before the cursor ends inside
+/// the string literal of that declaration.
+fn syntax_value_keyword(line_before_cursor: &str) -> Option<&'static str> {
+    let (before_quote, value) = line_before_cursor.rsplit_once('"')?;
+    if value.contains('"') {
+        return None;
+    }
+
+    let keyword = before_quote.trim_end().strip_suffix('=')?.trim();
+    ["edition", "syntax"]
+        .into_iter()
+        .find(|candidate| *candidate == keyword)
+}
+
+/// Whether the text before the cursor is a field declaration up to its
+/// `=`, optionally followed by part of the number, e.g. `string name = `.
+fn is_field_number_position(line_before_cursor: &str) -> bool {
+    let Some(declaration) = line_before_cursor
+        .trim_end_matches(|ch: char| ch.is_ascii_digit())
+        .trim_end()
+        .strip_suffix('=')
+    else {
+        return false;
+    };
+
+    let words: Vec<&str> = declaration.split_whitespace().collect();
+    match words.as_slice() {
+        [first, .., name] if words.len() >= 2 => {
+            !matches!(*first, "option" | "syntax" | "edition") && parser::is_valid_identifier(name)
+        }
+        _ => false,
+    }
+}
+
+/// Finds the message whose body contains `position` and returns its name
+/// with the number after the highest one used by its fields (oneofs
+/// included) or reserved, skipping extension ranges and the numbers
+/// reserved for the implementation. Returns `None` if no number is left.
+/// Works from tokens so the declaration being typed doesn't need to parse.
+fn next_field_number(content: &str, position: Position) -> Option<(String, i64)> {
+    /// What a `{` opened, so oneofs can share their message's numbers.
+    #[derive(PartialEq)]
+    enum Block {
+        Message,
+        Oneof,
+        Other,
+    }
+
+    let tokens = parser::tokenize(content).ok()?;
+    let cursor = parser::Position {
+        line: position.line as usize,
+        column: position.character as usize,
+    };
+
+    // Blocks open at the cursor, with the index of their `{`
+    let mut open: Vec<(Block, usize)> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.span.start >= cursor {
+            break;
+        }
+        match token.node {
+            Token::LeftBrace => {
+                let keyword = i.checked_sub(2).map(|keyword| &tokens[keyword].node);
+                let block = match keyword {
+                    Some(Token::Message) => Block::Message,
+                    Some(Token::Oneof) => Block::Oneof,
+                    _ => Block::Other,
+                };
+                open.push((block, i));
+            }
+            Token::RightBrace => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let (block, brace) = open
+        .iter()
+        .rev()
+        .find(|(block, _)| *block != Block::Oneof)?;
+    if *block != Block::Message {
+        return None;
+    }
+    let brace = *brace;
+    let Token::Identifier(message) = &tokens[brace - 1].node else {
+        return None;
+    };
+
+    let number = |token: Option<&parser::Spanned<Token>>| match token.map(|token| &token.node) {
+        Some(Token::NumberLiteral(literal)) => literal.parse::<i64>().ok(),
+        Some(Token::Identifier(max)) if max == "max" => Some(parser::MAX_FIELD_NUMBER),
+        _ => None,
+    };
+    // `reserved 5, 9 to 11;` or `extensions 100 to max;`, from the index of
+    // one of its numbers
+    let range_at = |i: usize| {
+        let start = number(tokens.get(i))?;
+        let end = match tokens.get(i + 1).map(|token| &token.node) {
+            Some(Token::Identifier(to)) if to == "to" => number(tokens.get(i + 2))?,
+            _ => start,
+        };
+        Some((start, end))
+    };
+
+    let mut highest = 0;
+    // Numbers no field may use: extension ranges and the range reserved for
+    // the protobuf implementation
+    let mut off_limits = vec![(19_000, 19_999)];
+    let mut nested: Vec<Block> = Vec::new();
+    let mut in_reserved = false;
+    let mut in_extensions = false;
+    for i in brace + 1..tokens.len() {
+        let in_body = nested.iter().all(|block| *block == Block::Oneof);
+        let at_statement_start = matches!(
+            tokens[i - 1].node,
+            Token::LeftBrace | Token::RightBrace | Token::Semicolon
+        );
+        match &tokens[i].node {
+            Token::LeftBrace => {
+                let block = match &tokens[i - 2].node {
+                    Token::Oneof => Block::Oneof,
+                    _ => Block::Other,
+                };
+                nested.push(block);
+            }
+            Token::RightBrace => {
+                // The message's own closing brace
+                let Some(_) = nested.pop() else {
+                    break;
+                };
+            }
+            Token::Identifier(_) if in_body && starts_reserved(&tokens, i) => in_reserved = true,
+            Token::Identifier(keyword)
+                if in_body
+                    && at_statement_start
+                    && keyword == "extensions"
+                    && matches!(
+                        tokens.get(i + 1).map(|token| &token.node),
+                        Some(Token::NumberLiteral(_))
+                    ) =>
+            {
+                in_extensions = true;
+            }
+            Token::Semicolon => {
+                in_reserved = false;
+                in_extensions = false;
+            }
+            // Reserved numbers count as used
+            Token::NumberLiteral(_) if in_body && in_reserved => {
+                if let Some((_, end)) = range_at(i) {
+                    highest = highest.max(end);
+                }
+            }
+            Token::NumberLiteral(_) if in_body && in_extensions => {
+                off_limits.extend(range_at(i));
+            }
+            // `type name = 5`, but not `option name = 5` or `[default = 5]`
+            Token::Equals if in_body && i >= 2 => {
+                let is_field = matches!(tokens[i - 1].node, Token::Identifier(_))
+                    && matches!(tokens[i - 2].node, Token::Identifier(_) | Token::RightAngle);
+                if is_field {
+                    highest = highest.max(number(tokens.get(i + 1)).unwrap_or(0));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Step past any off-limits range the candidate lands in
+    let mut next = highest + 1;
+    while let Some(&(_, end)) = off_limits
+        .iter()
+        .find(|&&(start, end)| (start..=end).contains(&next))
+    {
+        next = end + 1;
+    }
+    (next <= parser::MAX_FIELD_NUMBER).then(|| (message.clone(), next))
+}
+
+/// If the cursor is inside the parentheses of `rpc Name(` or `returns (`,
+/// optionally after part of a type name, returns whether `stream` has
+/// already been written there.
+fn rpc_type_position(line_before_cursor: &str) -> Option<bool> {
+    let before_type = line_before_cursor
+        .trim_end_matches(|ch: char| ch.is_ascii_alphanumeric() || ch == '_' || ch == '.')
+        .trim_end();
+    let (before_paren, after_stream) = match before_type.strip_suffix("stream") {
+        Some(rest) if rest.ends_with(|ch: char| ch.is_whitespace() || ch == '(') => {
+            (rest.trim_end(), true)
+        }
+        _ => (before_type, false),
+    };
+    let before_paren = before_paren.strip_suffix('(')?.trim_end();
+
+    let is_returns = before_paren
+        .strip_suffix("returns")
+        .is_some_and(|rest| rest.ends_with([' ', ')']));
+    let is_rpc_name = before_paren
+        .trim_end_matches(|ch: char| ch.is_ascii_alphanumeric() || ch == '_')
+        .trim_end()
+        .ends_with("rpc");
+    (is_returns || is_rpc_name).then_some(after_stream)
+}
+
+/// Message names declared in `content`, found from its tokens so they're
+/// available while the rpc being written doesn't parse yet.
+fn rpc_type_completions(content: &str, after_stream: bool) -> Vec<CompletionItem> {
+    let tokens = parser::tokenize(content).unwrap_or_default();
+    let mut names: Vec<&str> = tokens
+        .windows(2)
+        .filter_map(|pair| match (&pair[0].node, &pair[1].node) {
+            (Token::Message, Token::Identifier(name)) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let mut completions: Vec<CompletionItem> = names
+        .into_iter()
+        .map(|name| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::CLASS),
+            detail: Some("Message type".to_string()),
+            data: completion_data(CompletionData::Type(name.to_string())),
+            ..Default::default()
+        })
+        .collect();
+
+    if !after_stream {
+        completions.push(CompletionItem {
+            label: "stream".to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some("Stream messages of this type".to_string()),
+            insert_text: Some("stream ".to_string()),
+            ..Default::default()
+        });
+    }
+    completions
+}
+
+fn completion_data(data: CompletionData) -> Option<serde_json::Value> {
+    serde_json::to_value(data).ok()
+}
+
+/// Fills in the documentation of a completion item produced by
+/// [`compute_completions`]. Type documentation is looked up in the open
+/// documents of `store`.
+pub fn compute_completion_resolve(
+    store: &DocumentStore,
+    mut item: CompletionItem,
+) -> CompletionItem {
+    let data = item
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<CompletionData>(data).ok());
+
+    let documentation = match data {
+        Some(CompletionData::Scalar(name)) => {
+            scalar_type_description(&name).map(|description| format!("**{name}**\n\n{description}"))
+        }
+        Some(CompletionData::WellKnownType(name)) => well_known_types::lookup(&name)
+            .map(|wkt| format!("**{}**\n\n{}", wkt.full_name(), wkt.description)),
+        Some(CompletionData::Type(name)) => store
+            .documents()
+            .filter_map(|(_, document)| document.analysis().proto_file())
+            .find_map(|proto_file| describe_type(proto_file, &name)),
+        None => None,
+    };
+
+    if let Some(documentation) = documentation {
+        item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: documentation,
+        }));
+    }
+
+    item
+}
+
+/// Describes the message or enum called `name` in `proto_file`, listing its
+/// fields or values.
+fn describe_type(proto_file: &ProtoFile, name: &str) -> Option<String> {
+    fn find_message<'a>(message: &'a Message, name: &str) -> Option<&'a Message> {
+        if message.name == name {
+            return Some(message);
+        }
+        message
+            .nested_messages
+            .iter()
+            .find_map(|nested| find_message(nested, name))
+    }
+
+    fn find_enum<'a>(message: &'a Message, name: &str) -> Option<&'a Enum> {
+        message
+            .nested_enums
+            .iter()
+            .find(|nested| nested.name == name)
+            .or_else(|| {
+                message
+                    .nested_messages
+                    .iter()
+                    .find_map(|nested| find_enum(nested, name))
+            })
+    }
+
+    for statement in &proto_file.statements {
+        let (message, enum_def) = match &statement.node {
+            Statement::Message(message) => (find_message(message, name), find_enum(message, name)),
+            Statement::Enum(enum_def) if enum_def.name == name => (None, Some(enum_def)),
+            _ => continue,
+        };
+
+        if let Some(message) = message {
+            let fields: Vec<String> = message
+                .fields
+                .iter()
+                .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()))
+                .map(|field| format!("- `{} {} = {}`", field.field_type, field.name, field.number))
+                .collect();
+            return Some(format!("**message {name}**\n\n{}", fields.join("\n")));
+        }
+
+        if let Some(enum_def) = enum_def {
+            let values: Vec<String> = enum_def
+                .values
+                .iter()
+                .map(|value| format!("- `{} = {}`", value.name, value.number))
+                .collect();
+            return Some(format!("**enum {name}**\n\n{}", values.join("\n")));
+        }
+    }
+
+    None
+}
+
+fn scalar_type_description(name: &str) -> Option<&'static str> {
+    match name {
+        "double" => Some("64-bit floating point number"),
+        "float" => Some("32-bit floating point number"),
+        "int32" => Some("32-bit signed integer using variable-length encoding"),
+        "int64" => Some("64-bit signed integer using variable-length encoding"),
+        "uint32" => Some("32-bit unsigned integer using variable-length encoding"),
+        "uint64" => Some("64-bit unsigned integer using variable-length encoding"),
+        "sint32" => Some("32-bit signed integer using zigzag encoding"),
+        "sint64" => Some("64-bit signed integer using zigzag encoding"),
+        "fixed32" => Some("32-bit unsigned integer using fixed-width encoding"),
+        "fixed64" => Some("64-bit unsigned integer using fixed-width encoding"),
+        "sfixed32" => Some("32-bit signed integer using fixed-width encoding"),
+        "sfixed64" => Some("64-bit signed integer using fixed-width encoding"),
+        "bool" => Some("Boolean value (true or false)"),
+        "string" => Some("UTF-8 encoded string"),
+        "bytes" => Some("Arbitrary sequence of bytes"),
+        _ => None,
+    }
+}
+
+/// The expected value and meaning of a built-in field option.
+fn field_option_description(name: &str) -> Option<(&'static str, &'static str)> {
+    match name {
+        "deprecated" => Some((
+            "bool",
+            "Marks the field as deprecated; generated code may warn when it is used",
+        )),
+        "packed" => Some((
+            "bool",
+            "Encodes a repeated scalar numeric field as a single length-delimited record",
+        )),
+        "json_name" => Some((
+            "string",
+            "Overrides the field's name in the JSON mapping (lowerCamelCase by default)",
+        )),
+        "default" => Some((
+            "the field's type",
+            "The value reported when the field is unset (proto2 and explicit presence only)",
+        )),
+        "lazy" => Some((
+            "bool",
+            "Allows the message field to be parsed lazily, on first access",
+        )),
+        "ctype" => Some((
+            "`STRING`, `CORD`, or `STRING_PIECE`",
+            "Selects the C++ representation of a string or bytes field",
+        )),
+        "jstype" => Some((
+            "`JS_NORMAL`, `JS_STRING`, or `JS_NUMBER`",
+            "Selects the JavaScript representation of a 64-bit integer field",
+        )),
+        "debug_redact" => Some(("bool", "Redacts the field's value from debug output")),
+        "features" => Some((
+            "feature values",
+            "Overrides edition features, such as `field_presence`, for this field",
+        )),
+        _ => None,
+    }
+}
+
+/// Whether `position` is inside the `[...]` options of a field.
+fn is_in_field_options(proto_file: &ProtoFile, position: parser::Position) -> bool {
+    proto_file
+        .statements
+        .iter()
+        .any(|statement| match &statement.node {
+            Statement::Message(message) => find_field_at(message, position, &mut Vec::new())
+                .and_then(|field| field.options_span)
+                .is_some_and(|span| span.contains(position)),
+            _ => false,
+        })
+}
+
 pub fn compute_hover(content: &str, position: Position) -> Option<Hover> {
+    hover_for_analysis(content, &Analysis::new(content), position)
+}
+
+pub(crate) fn hover_for_analysis(
+    content: &str,
+    analysis: &Analysis,
+    position: Position,
+) -> Option<Hover> {
+    let _span = tracing::debug_span!("compute_hover", bytes = content.len()).entered();
     let lines: Vec<&str> = content.lines().collect();
 
     if let Some(line) = lines.get(position.line as usize) {
-        let word = get_word_at_position(line, position.character as usize);
-
-        // Provide hover information for scalar types
-        let scalar_type_info = match word.as_str() {
-            "double" => Some("64-bit floating point number"),
-            "float" => Some("32-bit floating point number"),
-            "int32" => Some("32-bit signed integer using variable-length encoding"),
-            "int64" => Some("64-bit signed integer using variable-length encoding"),
-            "uint32" => Some("32-bit unsigned integer using variable-length encoding"),
-            "uint64" => Some("64-bit unsigned integer using variable-length encoding"),
-            "sint32" => Some("32-bit signed integer using zigzag encoding"),
-            "sint64" => Some("64-bit signed integer using zigzag encoding"),
-            "fixed32" => Some("32-bit unsigned integer using fixed-width encoding"),
-            "fixed64" => Some("64-bit unsigned integer using fixed-width encoding"),
-            "sfixed32" => Some("32-bit signed integer using fixed-width encoding"),
-            "sfixed64" => Some("64-bit signed integer using fixed-width encoding"),
-            "bool" => Some("Boolean value (true or false)"),
-            "string" => Some("UTF-8 encoded string"),
-            "bytes" => Some("Arbitrary sequence of bytes"),
-            _ => None,
+        let (word, word_columns) = get_word_at_position(line, position.character as usize);
+        let line_range = |columns: std::ops::Range<usize>| {
+            Some(Range::new(
+                Position::new(position.line, columns.start as u32),
+                Position::new(position.line, columns.end as u32),
+            ))
         };
 
-        if let Some(info) = scalar_type_info {
+        // Built-in options inside a field's `[...]`
+        if let Some((value_type, info)) = field_option_description(&word) {
+            if analysis.proto_file().is_some_and(|proto_file| {
+                is_in_field_options(proto_file, to_parser_position(position))
+            }) {
+                return Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: format!(
+                            "**{word}** (field option)\n\n{info}\n\nValue: {value_type}"
+                        ),
+                    }),
+                    range: line_range(word_columns.clone()),
+                });
+            }
+        }
+
+        // Provide hover information for scalar types
+        if let Some(info) = scalar_type_description(&word) {
             return Some(Hover {
                 contents: HoverContents::Markup(MarkupContent {
                     kind: MarkupKind::Markdown,
                     value: format!("**{word}**\n\n{info}"),
                 }),
-                range: None,
+                range: line_range(word_columns.clone()),
             });
         }
 
@@ -194,60 +1067,2496 @@ pub fn compute_hover(content: &str, position: Position) -> Option<Hover> {
                     kind: MarkupKind::Markdown,
                     value: format!("**{word}**\n\n{info}"),
                 }),
-                range: None,
+                range: line_range(word_columns),
+            });
+        }
+
+        let (qualified_name, name_columns) =
+            get_qualified_name_at_position(line, position.character as usize);
+        if let Some(wkt) = qualified_name
+            .strip_prefix("google.protobuf.")
+            .and_then(well_known_types::lookup)
+        {
+            return Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!(
+                        "**{}**\n\n{}\n\nImport: `{}`",
+                        wkt.full_name(),
+                        wkt.description,
+                        wkt.import_path
+                    ),
+                }),
+                range: line_range(name_columns),
             });
         }
+
+        if let Some(proto_file) = analysis.proto_file() {
+            let mut hover = compute_declaration_hover(proto_file, to_parser_position(position))?;
+            // Underline the name under the cursor, if the cursor is on one
+            if hover.range.is_none() && !name_columns.is_empty() {
+                hover.range = line_range(name_columns);
+            }
+            return Some(hover);
+        }
     }
 
     None
 }
 
-fn is_inside_message(lines: &[&str], current_line: usize) -> bool {
-    let mut brace_count = 0;
-    let mut in_message = false;
-
-    for (i, line) in lines.iter().enumerate() {
-        if i > current_line {
-            break;
-        }
-
-        let trimmed = line.trim();
-        if trimmed.starts_with("message ") {
-            in_message = true;
-        }
+fn compute_declaration_hover(proto_file: &ProtoFile, position: parser::Position) -> Option<Hover> {
+    let mut sections = Vec::new();
+    let mut range = None;
 
-        for ch in line.chars() {
-            if ch == '{' {
-                brace_count += 1;
-            } else if ch == '}' {
-                brace_count -= 1;
-                if brace_count == 0 {
-                    in_message = false;
+    if let Some(span) = proto_file
+        .syntax_value_span
+        .filter(|span| span.contains(position))
+    {
+        sections.push(syntax_value_note(proto_file));
+        sections
+            .extend(resolve_file_features(proto_file).map(|features| format_features(&features)));
+        range = Some(to_range(span));
+    } else if proto_file
+        .syntax_span
+        .is_some_and(|span| span.contains(position))
+    {
+        let edition = proto_file.edition.as_deref()?;
+        sections.push(format!("**edition {edition}**"));
+        sections.push(format_features(&resolve_file_features(proto_file)?));
+    } else if let Some(declaration) = find_declaration_named_at(proto_file, position) {
+        sections.push(format!("**{} {}**", declaration.keyword, declaration.name));
+        sections.extend(declaration.leading_comments.map(doc_comment_markdown));
+        range = Some(to_range(declaration.name_span));
+    } else if let Some(method) = find_method_at(proto_file, position) {
+        sections.push(format!("**rpc {}**", method.name));
+        sections.extend(method.leading_comments.as_deref().map(doc_comment_markdown));
+        sections.extend(method.trailing_comments.clone());
+        sections.push(format!(
+            "{}: `{}` → `{}`",
+            streaming_kind(method),
+            method.request_type,
+            method.response_type
+        ));
+    } else if let Some(value) = find_enum_value_at(proto_file, position) {
+        sections.push(format!("**{}**", value.name));
+        sections.extend(value.leading_comments.as_deref().map(doc_comment_markdown));
+        sections.extend(value.trailing_comments.clone());
+        sections.push(format!("Value: `{}`", value.number));
+    } else {
+        let mut enclosing_messages = Vec::new();
+        let field = proto_file
+            .statements
+            .iter()
+            .find_map(|statement| match &statement.node {
+                Statement::Message(message) => {
+                    find_field_at(message, position, &mut enclosing_messages)
                 }
-            }
+                _ => None,
+            })?;
+
+        sections.push(format!("**{}**", field.name));
+        sections.extend(field.leading_comments.as_deref().map(doc_comment_markdown));
+        sections.extend(field.trailing_comments.clone());
+        sections.push(format!("JSON name: `{}`", field.json_name()));
+        if let Some(features) = resolve_field_features(proto_file, &enclosing_messages, field) {
+            sections.push(format_features(&features));
         }
     }
 
-    in_message && brace_count > 0
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n"),
+        }),
+        range,
+    })
 }
 
-fn get_word_at_position(line: &str, position: usize) -> String {
-    let chars: Vec<char> = line.chars().collect();
-    let mut start = position;
-    let mut end = position;
-
-    // Find word boundaries
-    while start > 0 && start <= chars.len() && is_word_char(chars.get(start.saturating_sub(1))) {
-        start -= 1;
-    }
+/// Renders a leading comment block as markdown: consecutive lines are
+/// joined into one paragraph, and empty comment lines separate paragraphs.
+fn doc_comment_markdown(comments: &str) -> String {
+    let lines: Vec<&str> = comments.lines().collect();
+    let paragraphs: Vec<String> = lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join(" "))
+        .collect();
+    paragraphs.join("\n\n")
+}
 
-    while end < chars.len() && is_word_char(chars.get(end)) {
-        end += 1;
-    }
+/// Describes the value of the file's `edition` or `syntax` declaration,
+/// listing the supported values if it isn't one of them.
+fn syntax_value_note(proto_file: &ProtoFile) -> String {
+    let (title, note, supported) = match (&proto_file.edition, &proto_file.syntax) {
+        (Some(edition), _) => (
+            format!("**Edition {edition}**"),
+            match edition.as_str() {
+                "2023" => Some(
+                    "The first protobuf edition. Field presence, enum openness, and encodings \
+                     are controlled by `features` options instead of the syntax level.",
+                ),
+                _ => None,
+            },
+            parser::SUPPORTED_EDITIONS,
+        ),
+        (None, Some(syntax)) => (
+            format!("**Syntax {syntax}**"),
+            match syntax.as_str() {
+                "proto2" => Some(
+                    "Fields have explicit presence and may be `required`; enums are closed \
+                     and groups are allowed.",
+                ),
+                "proto3" => Some(
+                    "Singular fields have implicit presence unless marked `optional`; enums \
+                     are open and must start at zero.",
+                ),
+                _ => None,
+            },
+            parser::SUPPORTED_SYNTAXES,
+        ),
+        (None, None) => return String::new(),
+    };
 
-    chars[start..end].iter().collect()
+    let note = note.map(str::to_string).unwrap_or_else(|| {
+        let supported: Vec<String> = supported.iter().map(|value| format!("`{value}`")).collect();
+        format!("Unsupported. Supported values: {}", supported.join(", "))
+    });
+    format!("{title}\n\n{note}")
 }
 
-fn is_word_char(ch: Option<&char>) -> bool {
-    ch.is_some_and(|c| c.is_alphanumeric() || *c == '_')
+fn find_method_at(proto_file: &ProtoFile, position: parser::Position) -> Option<&Method> {
+    proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Service(service) => Some(service),
+            _ => None,
+        })
+        .flat_map(|service| service.methods.iter())
+        .find(|method| method.span.contains(position))
+}
+
+/// A message, enum, service or oneof, as shown when hovering its name.
+struct NamedDeclaration<'a> {
+    keyword: &'static str,
+    name: &'a str,
+    name_span: parser::Span,
+    leading_comments: Option<&'a str>,
+}
+
+/// The declaration, at any depth, whose name contains `position`.
+fn find_declaration_named_at(
+    proto_file: &ProtoFile,
+    position: parser::Position,
+) -> Option<NamedDeclaration<'_>> {
+    fn named<'a>(
+        keyword: &'static str,
+        name: &'a str,
+        name_span: parser::Span,
+        leading_comments: &'a Option<String>,
+        position: parser::Position,
+    ) -> Option<NamedDeclaration<'a>> {
+        name_span.contains(position).then_some(NamedDeclaration {
+            keyword,
+            name,
+            name_span,
+            leading_comments: leading_comments.as_deref(),
+        })
+    }
+
+    fn in_message(message: &Message, position: parser::Position) -> Option<NamedDeclaration<'_>> {
+        if let Some(declaration) = named(
+            "message",
+            &message.name,
+            message.name_span,
+            &message.leading_comments,
+            position,
+        ) {
+            return Some(declaration);
+        }
+        if !message.span.contains(position) {
+            return None;
+        }
+        message
+            .oneofs
+            .iter()
+            .find_map(|oneof| {
+                named(
+                    "oneof",
+                    &oneof.name,
+                    oneof.name_span,
+                    &oneof.leading_comments,
+                    position,
+                )
+            })
+            .or_else(|| {
+                message.nested_enums.iter().find_map(|enum_def| {
+                    named(
+                        "enum",
+                        &enum_def.name,
+                        enum_def.name_span,
+                        &enum_def.leading_comments,
+                        position,
+                    )
+                })
+            })
+            .or_else(|| {
+                message
+                    .nested_messages
+                    .iter()
+                    .find_map(|nested| in_message(nested, position))
+            })
+    }
+
+    proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Message(message) => in_message(message, position),
+            Statement::Enum(enum_def) => named(
+                "enum",
+                &enum_def.name,
+                enum_def.name_span,
+                &enum_def.leading_comments,
+                position,
+            ),
+            Statement::Service(service) => named(
+                "service",
+                &service.name,
+                service.name_span,
+                &service.leading_comments,
+                position,
+            ),
+            _ => None,
+        })
+}
+
+/// The enum value, in an enum at any depth, declared at `position`.
+fn find_enum_value_at(proto_file: &ProtoFile, position: parser::Position) -> Option<&EnumValue> {
+    fn in_enums(enums: &[Enum], position: parser::Position) -> Option<&EnumValue> {
+        enums
+            .iter()
+            .filter(|enum_def| enum_def.span.contains(position))
+            .flat_map(|enum_def| enum_def.values.iter())
+            .find(|value| value.span.contains(position))
+    }
+
+    fn in_message(message: &Message, position: parser::Position) -> Option<&EnumValue> {
+        if !message.span.contains(position) {
+            return None;
+        }
+        in_enums(&message.nested_enums, position).or_else(|| {
+            message
+                .nested_messages
+                .iter()
+                .find_map(|nested| in_message(nested, position))
+        })
+    }
+
+    proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Enum(enum_def) => in_enums(std::slice::from_ref(enum_def), position),
+            Statement::Message(message) => in_message(message, position),
+            _ => None,
+        })
+}
+
+fn streaming_kind(method: &Method) -> &'static str {
+    match (method.client_streaming, method.server_streaming) {
+        (false, false) => "Unary",
+        (true, false) => "Client streaming",
+        (false, true) => "Server streaming",
+        (true, true) => "Bidirectional streaming",
+    }
+}
+
+fn format_features(features: &FeatureSet) -> String {
+    let entries: Vec<String> = features
+        .entries()
+        .iter()
+        .map(|(name, value)| format!("- `{name}`: `{value}`"))
+        .collect();
+
+    format!("Resolved features:\n\n{}", entries.join("\n"))
+}
+
+/// Finds the field containing `position`, recording the messages enclosing it
+/// (outermost first) in `enclosing_messages`.
+fn find_field_at<'a>(
+    message: &'a Message,
+    position: parser::Position,
+    enclosing_messages: &mut Vec<&'a Message>,
+) -> Option<&'a Field> {
+    if !message.span.contains(position) {
+        return None;
+    }
+    enclosing_messages.push(message);
+
+    let field = message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()))
+        .find(|field| field.span.contains(position));
+    if field.is_some() {
+        return field;
+    }
+
+    for nested in &message.nested_messages {
+        if let Some(field) = find_field_at(nested, position, enclosing_messages) {
+            return Some(field);
+        }
+    }
+
+    enclosing_messages.pop();
+    None
+}
+
+pub fn compute_selection_ranges(content: &str, positions: &[Position]) -> Vec<SelectionRange> {
+    selection_ranges_for_analysis(content, &Analysis::new(content), positions)
+}
+
+pub(crate) fn selection_ranges_for_analysis(
+    content: &str,
+    analysis: &Analysis,
+    positions: &[Position],
+) -> Vec<SelectionRange> {
+    let proto_file = analysis.proto_file();
+    let document = parser::Span {
+        start: parser::Position::default(),
+        end: document_end(content),
+    };
+
+    positions
+        .iter()
+        .map(|&position| {
+            // Spans are collected from the outermost node inwards
+            let mut spans = vec![document];
+            if let Some(proto_file) = proto_file {
+                collect_enclosing_spans(proto_file, to_parser_position(position), &mut spans);
+            }
+            spans.dedup();
+
+            spans
+                .into_iter()
+                .fold(None, |parent, span| {
+                    Some(SelectionRange {
+                        range: to_range(span),
+                        parent: parent.map(Box::new),
+                    })
+                })
+                .expect("the document span is always present")
+        })
+        .collect()
+}
+
+fn collect_enclosing_spans(
+    proto_file: &ProtoFile,
+    position: parser::Position,
+    spans: &mut Vec<parser::Span>,
+) {
+    for statement in &proto_file.statements {
+        match &statement.node {
+            Statement::Message(message) => collect_message_spans(message, position, spans),
+            Statement::Enum(enum_def) => collect_enum_spans(enum_def, position, spans),
+            Statement::Service(service) => collect_service_spans(service, position, spans),
+            _ => {}
+        }
+    }
+}
+
+fn collect_message_spans(
+    message: &Message,
+    position: parser::Position,
+    spans: &mut Vec<parser::Span>,
+) {
+    if !message.span.contains(position) {
+        return;
+    }
+    spans.push(message.span);
+
+    if message.name_span.contains(position) {
+        spans.push(message.name_span);
+        return;
+    }
+
+    for oneof in &message.oneofs {
+        if oneof.span.contains(position) {
+            spans.push(oneof.span);
+            if oneof.name_span.contains(position) {
+                spans.push(oneof.name_span);
+            }
+            collect_field_spans(&oneof.fields, position, spans);
+            return;
+        }
+    }
+
+    collect_field_spans(&message.fields, position, spans);
+
+    for nested in &message.nested_messages {
+        collect_message_spans(nested, position, spans);
+    }
+
+    for nested in &message.nested_enums {
+        collect_enum_spans(nested, position, spans);
+    }
+}
+
+fn collect_field_spans(
+    fields: &[Field],
+    position: parser::Position,
+    spans: &mut Vec<parser::Span>,
+) {
+    if let Some(field) = fields.iter().find(|field| field.span.contains(position)) {
+        spans.push(field.span);
+        if field.name_span.contains(position) {
+            spans.push(field.name_span);
+        }
+    }
+}
+
+fn collect_enum_spans(enum_def: &Enum, position: parser::Position, spans: &mut Vec<parser::Span>) {
+    if !enum_def.span.contains(position) {
+        return;
+    }
+    spans.push(enum_def.span);
+
+    if enum_def.name_span.contains(position) {
+        spans.push(enum_def.name_span);
+        return;
+    }
+
+    if let Some(value) = enum_def
+        .values
+        .iter()
+        .find(|value| value.span.contains(position))
+    {
+        spans.push(value.span);
+        if value.name_span.contains(position) {
+            spans.push(value.name_span);
+        }
+    }
+}
+
+fn collect_service_spans(
+    service: &Service,
+    position: parser::Position,
+    spans: &mut Vec<parser::Span>,
+) {
+    if !service.span.contains(position) {
+        return;
+    }
+    spans.push(service.span);
+
+    if service.name_span.contains(position) {
+        spans.push(service.name_span);
+        return;
+    }
+
+    if let Some(method) = service
+        .methods
+        .iter()
+        .find(|method| method.span.contains(position))
+    {
+        spans.push(method.span);
+        if method.name_span.contains(position) {
+            spans.push(method.name_span);
+        }
+    }
+}
+
+pub fn compute_prepare_rename(content: &str, position: Position) -> Option<PrepareRenameResponse> {
+    prepare_rename_for_analysis(&Analysis::new(content), position)
+}
+
+pub(crate) fn prepare_rename_for_analysis(
+    analysis: &Analysis,
+    position: Position,
+) -> Option<PrepareRenameResponse> {
+    let occurrences = collect_occurrences(analysis.proto_file()?);
+    let position = to_parser_position(position);
+    let occurrence = occurrences
+        .iter()
+        .find(|occurrence| occurrence.span.contains(position))?;
+
+    Some(PrepareRenameResponse::RangeWithPlaceholder {
+        range: to_range(occurrence.span),
+        placeholder: occurrence.name.to_string(),
+    })
+}
+
+pub fn compute_rename(
+    uri: &Url,
+    content: &str,
+    position: Position,
+    new_name: &str,
+) -> Option<WorkspaceEdit> {
+    rename_for_analysis(uri, &Analysis::new(content), position, new_name)
+}
+
+pub(crate) fn rename_for_analysis(
+    uri: &Url,
+    analysis: &Analysis,
+    position: Position,
+    new_name: &str,
+) -> Option<WorkspaceEdit> {
+    let occurrences = collect_occurrences(analysis.proto_file()?);
+    let position = to_parser_position(position);
+    let target = occurrences
+        .iter()
+        .find(|occurrence| occurrence.span.contains(position))?;
+
+    // Type names are referenced from fields and methods; other names are
+    // only renamed at their declaration
+    let edits = occurrences
+        .iter()
+        .filter(|occurrence| occurrence.declaration == target.declaration)
+        .map(|occurrence| TextEdit {
+            range: to_range(occurrence.span),
+            new_text: new_name.to_string(),
+        })
+        .collect();
+
+    Some(WorkspaceEdit {
+        changes: Some([(uri.clone(), edits)].into_iter().collect()),
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OccurrenceKind {
+    Type,
+    Field,
+    EnumValue,
+}
+
+/// A user-defined name appearing in the document, either where it is
+/// declared or where a type is referenced.
+#[derive(Debug, Clone)]
+struct Occurrence<'a> {
+    name: &'a str,
+    span: parser::Span,
+    kind: OccurrenceKind,
+    /// The name span of the declaration this occurrence refers to; for a
+    /// declaration, its own name span.
+    declaration: parser::Span,
+}
+
+fn collect_occurrences(proto_file: &ProtoFile) -> Vec<Occurrence<'_>> {
+    let mut occurrences = Vec::new();
+    for statement in &proto_file.statements {
+        match &statement.node {
+            Statement::Message(message) => {
+                collect_message_occurrences(proto_file, message, &mut Vec::new(), &mut occurrences)
+            }
+            Statement::Enum(enum_def) => collect_enum_occurrences(enum_def, &mut occurrences),
+            Statement::Service(service) => {
+                for method in &service.methods {
+                    for (type_name, span) in [
+                        (&method.request_type, method.request_type_span),
+                        (&method.response_type, method.response_type_span),
+                    ] {
+                        type_references(proto_file, &[], type_name, span, &mut occurrences);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    occurrences
+}
+
+fn collect_type_names<'a>(message: &'a Message, names: &mut HashSet<&'a str>) {
+    names.insert(&message.name);
+    for nested in &message.nested_messages {
+        collect_type_names(nested, names);
+    }
+    for nested in &message.nested_enums {
+        names.insert(&nested.name);
+    }
+}
+
+fn collect_message_occurrences<'a>(
+    proto_file: &ProtoFile,
+    message: &'a Message,
+    enclosing_messages: &mut Vec<&'a Message>,
+    occurrences: &mut Vec<Occurrence<'a>>,
+) {
+    occurrences.push(Occurrence {
+        name: &message.name,
+        span: message.name_span,
+        kind: OccurrenceKind::Type,
+        declaration: message.name_span,
+    });
+    enclosing_messages.push(message);
+
+    let fields = message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()));
+    for field in fields {
+        // Group fields share their name span with the group's message
+        if field.is_group {
+            continue;
+        }
+        occurrences.push(Occurrence {
+            name: &field.name,
+            span: field.name_span,
+            kind: OccurrenceKind::Field,
+            declaration: field.name_span,
+        });
+        match &field.map_type {
+            Some(map_type) => {
+                for (type_name, span) in [
+                    (&map_type.key_type, map_type.key_span),
+                    (&map_type.value_type, map_type.value_span),
+                ] {
+                    type_references(proto_file, enclosing_messages, type_name, span, occurrences);
+                }
+            }
+            None => type_references(
+                proto_file,
+                enclosing_messages,
+                &field.field_type,
+                field.type_span,
+                occurrences,
+            ),
+        }
+    }
+
+    for nested in &message.nested_messages {
+        collect_message_occurrences(proto_file, nested, enclosing_messages, occurrences);
+    }
+    for nested in &message.nested_enums {
+        collect_enum_occurrences(nested, occurrences);
+    }
+    enclosing_messages.pop();
+}
+
+fn collect_enum_occurrences<'a>(enum_def: &'a Enum, occurrences: &mut Vec<Occurrence<'a>>) {
+    occurrences.push(Occurrence {
+        name: &enum_def.name,
+        span: enum_def.name_span,
+        kind: OccurrenceKind::Type,
+        declaration: enum_def.name_span,
+    });
+    for value in &enum_def.values {
+        occurrences.push(Occurrence {
+            name: &value.name,
+            span: value.name_span,
+            kind: OccurrenceKind::EnumValue,
+            declaration: value.name_span,
+        });
+    }
+}
+
+/// Builds an occurrence for each segment of a (possibly qualified) type
+/// reference that resolves to a type declared in this file, so `Outer` in
+/// `Outer.Inner` is renamed along with `Outer` itself.
+fn type_references<'a>(
+    proto_file: &ProtoFile,
+    enclosing_messages: &[&Message],
+    type_name: &'a str,
+    span: parser::Span,
+    occurrences: &mut Vec<Occurrence<'a>>,
+) {
+    let mut end = type_name.len();
+    let mut end_column = span.end.column;
+    while end > 0 {
+        let segment_start = type_name[..end].rfind('.').map_or(0, |dot| dot + 1);
+        let name = &type_name[segment_start..end];
+        let width: usize = name.chars().map(char::len_utf16).sum();
+        let start_column = end_column.saturating_sub(width);
+
+        if let Some(declaration) = resolve_type(proto_file, enclosing_messages, &type_name[..end]) {
+            occurrences.push(Occurrence {
+                name,
+                span: parser::Span {
+                    start: parser::Position {
+                        line: span.end.line,
+                        column: start_column,
+                    },
+                    end: parser::Position {
+                        line: span.end.line,
+                        column: end_column,
+                    },
+                },
+                kind: OccurrenceKind::Type,
+                declaration,
+            });
+        }
+
+        // Step over the segment and the dot before it
+        end = segment_start.saturating_sub(1);
+        end_column = start_column.saturating_sub(1);
+    }
+}
+
+pub fn compute_prepare_call_hierarchy(
+    uri: &Url,
+    content: &str,
+    position: Position,
+) -> Option<Vec<CallHierarchyItem>> {
+    prepare_call_hierarchy_for_analysis(uri, &Analysis::new(content), position)
+}
+
+/// Identifies a call hierarchy item's message across documents, as its
+/// fully qualified name.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallHierarchyData {
+    full_name: String,
+}
+
+/// Starts a call hierarchy at the message declared or referenced at
+/// `position`, so its incoming calls are the RPCs that take or return it.
+pub(crate) fn prepare_call_hierarchy_for_analysis(
+    uri: &Url,
+    analysis: &Analysis,
+    position: Position,
+) -> Option<Vec<CallHierarchyItem>> {
+    let proto_file = analysis.proto_file()?;
+    let position = to_parser_position(position);
+    let declaration = collect_occurrences(proto_file)
+        .into_iter()
+        .find(|occurrence| {
+            occurrence.kind == OccurrenceKind::Type && occurrence.span.contains(position)
+        })?
+        .declaration;
+
+    let (message, full_name) = message_declared_at(proto_file, declaration)?;
+
+    Some(vec![CallHierarchyItem {
+        name: message.name.clone(),
+        kind: SymbolKind::STRUCT,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: to_range(message.span),
+        selection_range: to_range(message.name_span),
+        data: serde_json::to_value(CallHierarchyData { full_name }).ok(),
+    }])
+}
+
+/// The message whose name is at `name_span`, with its fully qualified name.
+fn message_declared_at(
+    proto_file: &ProtoFile,
+    name_span: parser::Span,
+) -> Option<(&Message, String)> {
+    fn find<'a>(
+        message: &'a Message,
+        name_span: parser::Span,
+        scope: &str,
+    ) -> Option<(&'a Message, String)> {
+        let full_name = if scope.is_empty() {
+            message.name.clone()
+        } else {
+            format!("{scope}.{}", message.name)
+        };
+        if message.name_span == name_span {
+            return Some((message, full_name));
+        }
+        message
+            .nested_messages
+            .iter()
+            .find_map(|nested| find(nested, name_span, &full_name))
+    }
+
+    let package = package_name(proto_file).unwrap_or_default();
+    proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Message(message) => find(message, name_span, package),
+            _ => None,
+        })
+}
+
+/// The RPC methods in open documents whose request or response type is the
+/// message of `item`. References are resolved in their own file first;
+/// types declared elsewhere match when the package scopes allow it.
+pub fn compute_incoming_calls(
+    store: &DocumentStore,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyIncomingCall> {
+    let full_name = item
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<CallHierarchyData>(data).ok())
+        .map_or_else(|| item.name.clone(), |data| data.full_name);
+
+    let mut calls = Vec::new();
+    for (uri, document) in store.documents() {
+        let Some(proto_file) = document.analysis().proto_file() else {
+            continue;
+        };
+        // References resolve to a local declaration if there is one
+        let refers_to_item = |type_name: &str| match resolve_type(proto_file, &[], type_name) {
+            Some(declaration) => message_declared_at(proto_file, declaration)
+                .is_some_and(|(_, local)| local == full_name),
+            None => scoped_names(package_name(proto_file), type_name).contains(&full_name),
+        };
+
+        for statement in &proto_file.statements {
+            let Statement::Service(service) = &statement.node else {
+                continue;
+            };
+            for method in &service.methods {
+                let from_ranges: Vec<Range> = [
+                    (&method.request_type, method.request_type_span),
+                    (&method.response_type, method.response_type_span),
+                ]
+                .into_iter()
+                .filter(|(type_name, _)| refers_to_item(type_name))
+                .map(|(_, span)| to_range(span))
+                .collect();
+                if from_ranges.is_empty() {
+                    continue;
+                }
+
+                calls.push(CallHierarchyIncomingCall {
+                    from: CallHierarchyItem {
+                        name: method.name.clone(),
+                        kind: SymbolKind::METHOD,
+                        tags: None,
+                        detail: Some(service.name.clone()),
+                        uri: uri.clone(),
+                        range: to_range(method.span),
+                        selection_range: to_range(method.name_span),
+                        data: None,
+                    },
+                    from_ranges,
+                });
+            }
+        }
+    }
+
+    calls.sort_by(|a, b| {
+        (a.from.uri.as_str(), a.from.range.start).cmp(&(b.from.uri.as_str(), b.from.range.start))
+    });
+    calls
+}
+
+/// Code actions for `range`, including quick fixes for the client-supplied
+/// `diagnostics` (the request's `context.diagnostics`).
+pub fn compute_code_actions(
+    uri: &Url,
+    content: &str,
+    range: Range,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    compute_code_actions_with_settings(uri, content, range, diagnostics, &Settings::default())
+}
+
+pub fn compute_code_actions_with_settings(
+    uri: &Url,
+    content: &str,
+    range: Range,
+    diagnostics: &[Diagnostic],
+    settings: &Settings,
+) -> Vec<CodeActionOrCommand> {
+    code_actions_for_analysis(
+        uri,
+        content,
+        &Analysis::new(content),
+        range,
+        diagnostics,
+        settings,
+    )
+}
+
+pub(crate) fn code_actions_for_analysis(
+    uri: &Url,
+    content: &str,
+    analysis: &Analysis,
+    range: Range,
+    diagnostics: &[Diagnostic],
+    settings: &Settings,
+) -> Vec<CodeActionOrCommand> {
+    let Some(proto_file) = analysis.proto_file() else {
+        return Vec::new();
+    };
+    let overlaps = |span: parser::Span| {
+        let span_range = to_range(span);
+        span_range.start <= range.end && range.start <= span_range.end
+    };
+
+    // Quick fixes suggested by the validator
+    let mut actions: Vec<CodeActionOrCommand> = analysis
+        .validation_errors
+        .iter()
+        .filter_map(|error| {
+            let replacement = error.replacement.as_ref()?;
+            overlaps(replacement.span).then(|| {
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Change to '{}'", replacement.text),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(
+                            [(
+                                uri.clone(),
+                                vec![TextEdit {
+                                    range: to_range(replacement.span),
+                                    new_text: replacement.text.clone(),
+                                }],
+                            )]
+                            .into_iter()
+                            .collect(),
+                        ),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                })
+            })
+        })
+        .collect();
+
+    for diagnostic in diagnostics {
+        if diagnostic.code == Some(NumberOrString::String("missing-enum-zero".to_string())) {
+            actions.extend(
+                find_enum_named_at(proto_file, to_parser_position(diagnostic.range.start))
+                    .and_then(|enum_def| enum_zero_action(uri, content, enum_def, diagnostic))
+                    .map(CodeActionOrCommand::CodeAction),
+            );
+        }
+    }
+
+    if let Some((syntax, span)) = legacy_syntax(proto_file, settings) {
+        if overlaps(span) {
+            actions.push(CodeActionOrCommand::CodeAction(edition_migration_action(
+                uri, content, syntax, span,
+            )));
+        }
+    }
+
+    if proto_file.edition.is_some() {
+        let mut fields = Vec::new();
+        for statement in &proto_file.statements {
+            if let Statement::Message(message) = &statement.node {
+                collect_labeled_fields(message, &mut fields);
+            }
+        }
+
+        actions.extend(
+            fields
+                .into_iter()
+                .filter(|field| overlaps(field.span))
+                .filter_map(|field| field_presence_action(uri, field))
+                .map(CodeActionOrCommand::CodeAction),
+        );
+    }
+
+    actions.extend(
+        find_message_at(proto_file, to_parser_position(range.start))
+            .and_then(|message| sort_fields_action(uri, content, message))
+            .map(CodeActionOrCommand::CodeAction),
+    );
+
+    actions
+}
+
+/// The innermost message whose body contains `position`.
+fn find_message_at(proto_file: &ProtoFile, position: parser::Position) -> Option<&Message> {
+    fn innermost(message: &Message, position: parser::Position) -> Option<&Message> {
+        if !message.span.contains(position) {
+            return None;
+        }
+        message
+            .nested_messages
+            .iter()
+            .find_map(|nested| innermost(nested, position))
+            .or(Some(message))
+    }
+
+    proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Message(message) => innermost(message, position),
+            _ => None,
+        })
+}
+
+/// Reorders the message's own fields by number. Each field moves together
+/// with its leading `//` comments and trailing comment, into the line slots
+/// the fields occupied before, so oneofs, nested types, options and blank
+/// lines stay where they are. Not offered if the fields are already sorted
+/// or a field shares its lines with other declarations.
+fn sort_fields_action(uri: &Url, content: &str, message: &Message) -> Option<CodeAction> {
+    if message.fields.iter().any(|field| field.is_group)
+        || message
+            .fields
+            .windows(2)
+            .all(|pair| pair[0].number <= pair[1].number)
+    {
+        return None;
+    }
+
+    // The inclusive line range of each field and its leading comments
+    let lines: Vec<&str> = content.lines().collect();
+    let mut slots: Vec<(usize, usize)> = Vec::new();
+    for field in &message.fields {
+        let first = lines.get(field.span.start.line)?;
+        let last = lines.get(field.span.end.line)?;
+        let before = &first[..byte_offset_at(first, field.span.start.column)];
+        let after = last[byte_offset_at(last, field.span.end.column)..].trim();
+        if !before.trim().is_empty() || !(after.is_empty() || after.starts_with("//")) {
+            return None;
+        }
+
+        let floor = slots
+            .last()
+            .map_or(message.name_span.start.line, |&(_, end)| end);
+        let mut start = field.span.start.line;
+        while start > floor + 1 && lines[start - 1].trim_start().starts_with("//") {
+            start -= 1;
+        }
+        slots.push((start, field.span.end.line));
+    }
+
+    let mut order: Vec<usize> = (0..slots.len()).collect();
+    order.sort_by_key(|&i| message.fields[i].number);
+
+    let (first_line, _) = slots[0];
+    let (_, last_line) = slots[slots.len() - 1];
+    let mut new_lines: Vec<&str> = Vec::new();
+    let mut line = first_line;
+    let mut slot = 0;
+    while line <= last_line {
+        if slots.get(slot).is_some_and(|&(start, _)| start == line) {
+            let (start, end) = slots[order[slot]];
+            new_lines.extend(&lines[start..=end]);
+            line = slots[slot].1 + 1;
+            slot += 1;
+        } else {
+            new_lines.push(lines[line]);
+            line += 1;
+        }
+    }
+
+    let range = Range::new(
+        Position::new(first_line as u32, 0),
+        Position::new(
+            last_line as u32,
+            lines[last_line].encode_utf16().count() as u32,
+        ),
+    );
+    Some(CodeAction {
+        title: "Sort fields by number".to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(
+                [(
+                    uri.clone(),
+                    vec![TextEdit {
+                        range,
+                        new_text: new_lines.join(parser::line_break(content)),
+                    }],
+                )]
+                .into_iter()
+                .collect(),
+            ),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The enum, at any depth, whose name starts at `position`.
+fn find_enum_named_at(proto_file: &ProtoFile, position: parser::Position) -> Option<&Enum> {
+    proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Enum(enum_def) if enum_def.name_span.start == position => Some(enum_def),
+            Statement::Message(message) => find_nested_enum_named_at(message, position),
+            _ => None,
+        })
+}
+
+fn find_nested_enum_named_at(message: &Message, position: parser::Position) -> Option<&Enum> {
+    message
+        .nested_enums
+        .iter()
+        .find(|enum_def| enum_def.name_span.start == position)
+        .or_else(|| {
+            message
+                .nested_messages
+                .iter()
+                .find_map(|nested| find_nested_enum_named_at(nested, position))
+        })
+}
+
+/// Inserts a `<ENUM_NAME>_UNSPECIFIED = 0;` value ahead of the enum's first
+/// value, indented like it.
+fn enum_zero_action(
+    uri: &Url,
+    content: &str,
+    enum_def: &Enum,
+    diagnostic: &Diagnostic,
+) -> Option<CodeAction> {
+    let first = enum_def.values.first()?;
+    let name = format!("{}_UNSPECIFIED", upper_snake_case(&enum_def.name));
+    let position = to_lsp_position(first.span.start);
+    let indent: String = content
+        .lines()
+        .nth(first.span.start.line)
+        .unwrap_or_default()
+        .chars()
+        .take_while(|ch| ch.is_whitespace())
+        .collect();
+
+    Some(CodeAction {
+        title: format!("Add '{name} = 0'"),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(
+                [(
+                    uri.clone(),
+                    vec![TextEdit {
+                        range: Range::new(position, position),
+                        new_text: format!("{name} = 0;{}{indent}", parser::line_break(content)),
+                    }],
+                )]
+                .into_iter()
+                .collect(),
+            ),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// `HttpStatus` -> `HTTP_STATUS`, `HTTPStatus` -> `HTTP_STATUS`.
+fn upper_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (i, &ch) in chars.iter().enumerate() {
+        if i > 0 && ch.is_uppercase() {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_is_lower)
+            {
+                result.push('_');
+            }
+        }
+        result.extend(ch.to_uppercase());
+    }
+    result
+}
+
+/// Replaces a `syntax` declaration with `edition = "2023"`, plus the
+/// file-level features that keep the old syntax's defaults where edition
+/// 2023 differs from it.
+fn edition_migration_action(
+    uri: &Url,
+    content: &str,
+    syntax: &str,
+    span: parser::Span,
+) -> CodeAction {
+    let features: &[&str] = match syntax {
+        "proto2" => &[
+            "features.enum_type = CLOSED",
+            "features.repeated_field_encoding = EXPANDED",
+            "features.utf8_validation = NONE",
+        ],
+        _ => &["features.field_presence = IMPLICIT"],
+    };
+
+    let line_break = parser::line_break(content);
+    let mut new_text = format!("edition = \"2023\";{line_break}");
+    for feature in features {
+        new_text.push_str(&format!("{line_break}option {feature};"));
+    }
+
+    CodeAction {
+        title: "Migrate to edition 2023".to_string(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            changes: Some(
+                [(
+                    uri.clone(),
+                    vec![TextEdit {
+                        range: to_range(span),
+                        new_text,
+                    }],
+                )]
+                .into_iter()
+                .collect(),
+            ),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn collect_labeled_fields<'a>(message: &'a Message, fields: &mut Vec<&'a Field>) {
+    fields.extend(message.fields.iter().filter(|field| field.label.is_some()));
+    for nested in &message.nested_messages {
+        collect_labeled_fields(nested, fields);
+    }
+}
+
+/// Rewrites a proto2/proto3 `optional` or `required` label into the
+/// equivalent edition 2023 `field_presence` feature.
+fn field_presence_action(uri: &Url, field: &Field) -> Option<CodeAction> {
+    let (label, presence) = match field.label.as_ref()? {
+        FieldLabel::Optional => ("optional", "EXPLICIT"),
+        FieldLabel::Required => ("required", "LEGACY_REQUIRED"),
+        FieldLabel::Repeated => return None,
+    };
+    if field.is_group {
+        return None;
+    }
+
+    let mut edits = vec![TextEdit {
+        range: Range::new(
+            to_lsp_position(field.label_span?.start),
+            to_lsp_position(field.type_span.start),
+        ),
+        new_text: String::new(),
+    }];
+
+    let feature = format!("features.field_presence = {presence}");
+    if !field.options.contains_key("features.field_presence") {
+        let edit = match field.options_span {
+            Some(options_span) => {
+                let mut position = to_lsp_position(options_span.start);
+                position.character += 1;
+                TextEdit {
+                    range: Range::new(position, position),
+                    new_text: format!("{feature}, "),
+                }
+            }
+            None => {
+                // Insert just before the terminating semicolon
+                let mut position = to_lsp_position(field.span.end);
+                position.character -= 1;
+                TextEdit {
+                    range: Range::new(position, position),
+                    new_text: format!(" [{feature}]"),
+                }
+            }
+        };
+        edits.push(edit);
+    }
+
+    Some(CodeAction {
+        title: format!("Replace '{label}' with '{feature}'"),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some([(uri.clone(), edits)].into_iter().collect()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+pub fn compute_document_links(uri: &Url, content: &str, settings: &Settings) -> Vec<DocumentLink> {
+    document_links_for_analysis(uri, &Analysis::new(content), settings)
+}
+
+pub(crate) fn document_links_for_analysis(
+    uri: &Url,
+    analysis: &Analysis,
+    settings: &Settings,
+) -> Vec<DocumentLink> {
+    let Some(proto_file) = analysis.proto_file() else {
+        return Vec::new();
+    };
+
+    proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Import {
+                path, path_span, ..
+            } => {
+                let target = resolve_import(uri, path, settings)?;
+                // Link the path itself, without the surrounding quotes
+                let mut range = to_range(*path_span);
+                range.start.character += 1;
+                range.end.character = range.end.character.saturating_sub(1);
+                Some(DocumentLink {
+                    range,
+                    target: Some(target),
+                    tooltip: None,
+                    data: None,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Resolves an import path to a file, trying the importing document's
+/// directory first and then each of [`Settings::search_roots`]. This checks
+/// the filesystem, so async callers should run it on a blocking thread.
+pub(crate) fn resolve_import(document_uri: &Url, path: &str, settings: &Settings) -> Option<Url> {
+    let document_dir = document_uri
+        .to_file_path()
+        .ok()
+        .and_then(|file| file.parent().map(Path::to_path_buf));
+
+    document_dir
+        .into_iter()
+        .chain(settings.search_roots())
+        .map(|root| root.join(path))
+        .find(|candidate| candidate.is_file())
+        .and_then(|candidate| Url::from_file_path(candidate.canonicalize().ok()?).ok())
+}
+
+pub fn compute_definition(
+    uri: &Url,
+    content: &str,
+    position: Position,
+) -> Option<GotoDefinitionResponse> {
+    definition_for_analysis(
+        uri,
+        &Analysis::new(content),
+        position,
+        &WorkspaceIndex::default(),
+        &Settings::default(),
+    )
+}
+
+/// Jumps from a type reference (a field type or an RPC request/response
+/// type) to the declaration of that type, in this file or an imported one.
+pub(crate) fn definition_for_analysis(
+    uri: &Url,
+    analysis: &Analysis,
+    position: Position,
+    index: &WorkspaceIndex,
+    settings: &Settings,
+) -> Option<GotoDefinitionResponse> {
+    let proto_file = analysis.proto_file()?;
+    let position = to_parser_position(position);
+
+    let mut enclosing_messages = Vec::new();
+    let field = proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Message(message) => {
+                find_field_at(message, position, &mut enclosing_messages)
+            }
+            _ => None,
+        });
+
+    let type_name = match field {
+        Some(field) if field.type_span.contains(position) => field_value_type(field),
+        Some(_) => return None,
+        None => {
+            let method = find_method_at(proto_file, position)?;
+            if method.request_type_span.contains(position) {
+                &method.request_type
+            } else if method.response_type_span.contains(position) {
+                &method.response_type
+            } else {
+                return None;
+            }
+        }
+    };
+
+    locate_type(
+        uri,
+        proto_file,
+        &enclosing_messages,
+        type_name,
+        index,
+        settings,
+    )
+    .map(GotoDefinitionResponse::Scalar)
+}
+
+pub fn compute_type_definition(
+    uri: &Url,
+    content: &str,
+    position: Position,
+) -> Option<GotoDefinitionResponse> {
+    type_definition_for_analysis(
+        uri,
+        &Analysis::new(content),
+        position,
+        &WorkspaceIndex::default(),
+        &Settings::default(),
+    )
+}
+
+/// Jumps from anywhere on a field declaration to the declaration of the
+/// field's message or enum type. Scalar fields have no type definition.
+pub(crate) fn type_definition_for_analysis(
+    uri: &Url,
+    analysis: &Analysis,
+    position: Position,
+    index: &WorkspaceIndex,
+    settings: &Settings,
+) -> Option<GotoDefinitionResponse> {
+    let proto_file = analysis.proto_file()?;
+    let position = to_parser_position(position);
+
+    let mut enclosing_messages = Vec::new();
+    let field = proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Message(message) => {
+                find_field_at(message, position, &mut enclosing_messages)
+            }
+            _ => None,
+        })?;
+
+    locate_type(
+        uri,
+        proto_file,
+        &enclosing_messages,
+        field_value_type(field),
+        index,
+        settings,
+    )
+    .map(GotoDefinitionResponse::Scalar)
+}
+
+/// Finds the declaration of `type_name`, referenced from `proto_file` at
+/// `uri`. Types not declared locally are looked up in the files `proto_file`
+/// imports, whose package must match the reference's prefix.
+fn locate_type(
+    uri: &Url,
+    proto_file: &ProtoFile,
+    enclosing_messages: &[&Message],
+    type_name: &str,
+    index: &WorkspaceIndex,
+    settings: &Settings,
+) -> Option<Location> {
+    if let Some(span) = resolve_type(proto_file, enclosing_messages, type_name) {
+        return Some(Location::new(uri.clone(), to_range(span)));
+    }
+
+    let candidates = scoped_names(package_name(proto_file), type_name);
+    proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Import { path, .. } => resolve_import(uri, path, settings),
+            _ => None,
+        })
+        .find_map(|import_uri| {
+            let imported = load_proto_file(&import_uri, index)?;
+            let package = package_name(&imported);
+            let span = candidates.iter().find_map(|candidate| {
+                let path = match package {
+                    Some(package) => candidate.strip_prefix(package)?.strip_prefix('.')?,
+                    None => candidate,
+                };
+                resolve_top_level_type(&imported, path)
+            })?;
+            Some(Location::new(import_uri, to_range(span)))
+        })
+}
+
+/// The fully qualified names a reference may denote, innermost package
+/// scope first: `Foo` in package `a.b` may be `a.b.Foo`, `a.Foo` or `Foo`.
+fn scoped_names(package: Option<&str>, type_name: &str) -> Vec<String> {
+    if let Some(name) = type_name.strip_prefix('.') {
+        return vec![name.to_string()];
+    }
+
+    let mut scope = package.unwrap_or_default();
+    let mut names = Vec::new();
+    loop {
+        if scope.is_empty() {
+            names.push(type_name.to_string());
+            return names;
+        }
+        names.push(format!("{scope}.{type_name}"));
+        scope = scope.rsplit_once('.').map_or("", |(parent, _)| parent);
+    }
+}
+
+/// An imported file, preferring the watched-files index over reading it
+/// from disk. Like [`resolve_import`], this may block on the filesystem.
+fn load_proto_file<'a>(uri: &Url, index: &'a WorkspaceIndex) -> Option<Cow<'a, ProtoFile>> {
+    if let Some(proto_file) = index.get_parsed(uri) {
+        return Some(Cow::Borrowed(proto_file));
+    }
+
+    let content = std::fs::read_to_string(uri.to_file_path().ok()?).ok()?;
+    parser::parse_proto(&content).ok().map(Cow::Owned)
+}
+
+fn package_name(proto_file: &ProtoFile) -> Option<&str> {
+    proto_file
+        .statements
+        .iter()
+        .find_map(|statement| match &statement.node {
+            Statement::Package(package) => Some(package.as_str()),
+            _ => None,
+        })
+}
+
+/// The type a field holds; for maps this is the value type.
+fn field_value_type(field: &Field) -> &str {
+    match &field.map_type {
+        Some(map_type) => &map_type.value_type,
+        None => &field.field_type,
+    }
+}
+
+/// Resolves a type reference the way protoc does: relative names are looked
+/// up from the innermost enclosing message outwards, then at the top level.
+/// Returns the span of the declaration's name.
+fn resolve_type(
+    proto_file: &ProtoFile,
+    enclosing_messages: &[&Message],
+    type_name: &str,
+) -> Option<parser::Span> {
+    let fully_qualified = type_name.starts_with('.');
+    let mut name = type_name.trim_start_matches('.');
+
+    if let Some(rest) = package_name(proto_file).and_then(|package| {
+        name.strip_prefix(package)
+            .and_then(|rest| rest.strip_prefix('.'))
+    }) {
+        name = rest;
+    }
+
+    if !fully_qualified {
+        for message in enclosing_messages.iter().rev() {
+            let span = resolve_nested_type(
+                message.nested_messages.iter(),
+                message.nested_enums.iter(),
+                name,
+            );
+            if span.is_some() {
+                return span;
+            }
+        }
+    }
+
+    resolve_top_level_type(proto_file, name)
+}
+
+/// Resolves a dotted path such as `Outer.Inner` from the top of the file.
+fn resolve_top_level_type(proto_file: &ProtoFile, path: &str) -> Option<parser::Span> {
+    let messages = proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Message(message) => Some(message),
+            _ => None,
+        });
+    let enums = proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Enum(enum_def) => Some(enum_def),
+            _ => None,
+        });
+    resolve_nested_type(messages, enums, path)
+}
+
+fn resolve_nested_type<'a>(
+    mut messages: impl Iterator<Item = &'a Message>,
+    mut enums: impl Iterator<Item = &'a Enum>,
+    path: &str,
+) -> Option<parser::Span> {
+    match path.split_once('.') {
+        Some((first, rest)) => {
+            let message = messages.find(|message| message.name == first)?;
+            resolve_nested_type(
+                message.nested_messages.iter(),
+                message.nested_enums.iter(),
+                rest,
+            )
+        }
+        None => messages
+            .find(|message| message.name == path)
+            .map(|message| message.name_span)
+            .or_else(|| {
+                enums
+                    .find(|enum_def| enum_def.name == path)
+                    .map(|enum_def| enum_def.name_span)
+            }),
+    }
+}
+
+/// Searches every document in `store` for messages, enums, services,
+/// methods and fields whose names contain `query`, ignoring case.
+pub fn compute_workspace_symbols(store: &DocumentStore, query: &str) -> Vec<SymbolInformation> {
+    let query = query.to_lowercase();
+    let mut symbols = Vec::new();
+
+    for (uri, document) in store.documents() {
+        let Some(proto_file) = document.analysis().proto_file() else {
+            continue;
+        };
+
+        let mut push = |name: &str, kind, span, container: Option<&str>| {
+            if name.to_lowercase().contains(&query) {
+                #[allow(deprecated)] // `deprecated` is superseded by `tags`
+                symbols.push(SymbolInformation {
+                    name: name.to_string(),
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(uri.clone(), to_range(span)),
+                    container_name: container.map(str::to_string),
+                });
+            }
+        };
+
+        for statement in &proto_file.statements {
+            match &statement.node {
+                Statement::Message(message) => {
+                    collect_message_symbols(message, None, &mut push);
+                }
+                Statement::Enum(enum_def) => {
+                    push(&enum_def.name, SymbolKind::ENUM, enum_def.name_span, None);
+                }
+                Statement::Service(service) => {
+                    push(
+                        &service.name,
+                        SymbolKind::INTERFACE,
+                        service.name_span,
+                        None,
+                    );
+                    for method in &service.methods {
+                        push(
+                            &method.name,
+                            SymbolKind::METHOD,
+                            method.name_span,
+                            Some(&service.name),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    symbols
+}
+
+fn collect_message_symbols(
+    message: &Message,
+    container: Option<&str>,
+    push: &mut impl FnMut(&str, SymbolKind, parser::Span, Option<&str>),
+) {
+    push(
+        &message.name,
+        SymbolKind::STRUCT,
+        message.name_span,
+        container,
+    );
+
+    let fields = message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()));
+    for field in fields {
+        push(
+            &field.name,
+            SymbolKind::FIELD,
+            field.name_span,
+            Some(&message.name),
+        );
+    }
+
+    for nested in &message.nested_messages {
+        collect_message_symbols(nested, Some(&message.name), push);
+    }
+    for nested in &message.nested_enums {
+        push(
+            &nested.name,
+            SymbolKind::ENUM,
+            nested.name_span,
+            Some(&message.name),
+        );
+    }
+}
+
+pub fn compute_document_symbols(content: &str) -> Vec<DocumentSymbol> {
+    document_symbols_for_analysis(&Analysis::new(content))
+}
+
+pub(crate) fn document_symbols_for_analysis(analysis: &Analysis) -> Vec<DocumentSymbol> {
+    let Some(proto_file) = analysis.proto_file() else {
+        return Vec::new();
+    };
+
+    proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Message(message) => Some(message_symbol(message)),
+            Statement::Enum(enum_def) => Some(enum_symbol(enum_def)),
+            Statement::Service(service) => Some(service_symbol(service)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn message_symbol(message: &Message) -> DocumentSymbol {
+    let mut children: Vec<DocumentSymbol> = message
+        .fields
+        .iter()
+        .map(field_symbol)
+        .chain(message.oneofs.iter().map(oneof_symbol))
+        .chain(message.nested_messages.iter().map(message_symbol))
+        .chain(message.nested_enums.iter().map(enum_symbol))
+        .collect();
+    // Keep the outline in source order
+    children.sort_by_key(|child| child.range.start);
+
+    document_symbol(
+        &message.name,
+        None,
+        SymbolKind::STRUCT,
+        message.span,
+        message.name_span,
+        children,
+    )
+}
+
+fn oneof_symbol(oneof: &Oneof) -> DocumentSymbol {
+    document_symbol(
+        &oneof.name,
+        Some("oneof".to_string()),
+        SymbolKind::STRUCT,
+        oneof.span,
+        oneof.name_span,
+        oneof.fields.iter().map(field_symbol).collect(),
+    )
+}
+
+fn field_symbol(field: &Field) -> DocumentSymbol {
+    document_symbol(
+        &field.name,
+        Some(field.field_type.clone()),
+        SymbolKind::FIELD,
+        field.span,
+        field.name_span,
+        Vec::new(),
+    )
+}
+
+fn enum_symbol(enum_def: &Enum) -> DocumentSymbol {
+    let children = enum_def
+        .values
+        .iter()
+        .map(|value| {
+            document_symbol(
+                &value.name,
+                Some(value.number.to_string()),
+                SymbolKind::ENUM_MEMBER,
+                value.span,
+                value.name_span,
+                Vec::new(),
+            )
+        })
+        .collect();
+
+    document_symbol(
+        &enum_def.name,
+        None,
+        SymbolKind::ENUM,
+        enum_def.span,
+        enum_def.name_span,
+        children,
+    )
+}
+
+fn service_symbol(service: &Service) -> DocumentSymbol {
+    let children = service
+        .methods
+        .iter()
+        .map(|method| {
+            document_symbol(
+                &method.name,
+                Some(format!(
+                    "{} → {}",
+                    method.request_type, method.response_type
+                )),
+                SymbolKind::METHOD,
+                method.span,
+                method.name_span,
+                Vec::new(),
+            )
+        })
+        .collect();
+
+    document_symbol(
+        &service.name,
+        None,
+        SymbolKind::INTERFACE,
+        service.span,
+        service.name_span,
+        children,
+    )
+}
+
+fn document_symbol(
+    name: &str,
+    detail: Option<String>,
+    kind: SymbolKind,
+    span: parser::Span,
+    name_span: parser::Span,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    #[allow(deprecated)] // `deprecated` is superseded by `tags`
+    DocumentSymbol {
+        name: name.to_string(),
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: to_range(span),
+        selection_range: to_range(name_span),
+        children: (!children.is_empty()).then_some(children),
+    }
+}
+
+pub fn compute_folding_ranges(content: &str) -> Vec<FoldingRange> {
+    folding_ranges_for_analysis(&Analysis::new(content))
+}
+
+/// Folds every multi-line message, enum, service, and oneof body.
+pub(crate) fn folding_ranges_for_analysis(analysis: &Analysis) -> Vec<FoldingRange> {
+    let mut spans = Vec::new();
+    if let Some(proto_file) = analysis.proto_file() {
+        for statement in &proto_file.statements {
+            match &statement.node {
+                Statement::Message(message) => collect_block_spans(message, &mut spans),
+                Statement::Enum(enum_def) => spans.push(enum_def.span),
+                Statement::Service(service) => spans.push(service.span),
+                _ => {}
+            }
+        }
+    }
+
+    spans
+        .into_iter()
+        .filter(|span| span.end.line > span.start.line)
+        .map(|span| FoldingRange {
+            start_line: span.start.line as u32,
+            start_character: None,
+            end_line: span.end.line as u32,
+            end_character: None,
+            kind: None,
+            collapsed_text: None,
+        })
+        .collect()
+}
+
+fn collect_block_spans(message: &Message, spans: &mut Vec<parser::Span>) {
+    spans.push(message.span);
+    spans.extend(message.oneofs.iter().map(|oneof| oneof.span));
+    spans.extend(message.nested_enums.iter().map(|enum_def| enum_def.span));
+    for nested in &message.nested_messages {
+        collect_block_spans(nested, spans);
+    }
+}
+
+pub fn compute_formatting(content: &str, options: &FormattingOptions) -> Vec<TextEdit> {
+    formatting_for_analysis(content, &Analysis::new(content), options)
+}
+
+/// Reformats the whole document as a single edit. Documents that don't parse
+/// are left alone.
+pub(crate) fn formatting_for_analysis(
+    content: &str,
+    analysis: &Analysis,
+    options: &FormattingOptions,
+) -> Vec<TextEdit> {
+    if analysis.proto_file().is_none() {
+        return Vec::new();
+    }
+
+    match parser::format_proto(content, &indent_unit(options)) {
+        Ok(formatted) if formatted != content => vec![TextEdit {
+            range: to_range(parser::Span {
+                start: parser::Position::default(),
+                end: document_end(content),
+            }),
+            new_text: formatted,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+pub fn compute_range_formatting(
+    content: &str,
+    range: Range,
+    options: &FormattingOptions,
+) -> Vec<TextEdit> {
+    range_formatting_for_analysis(content, &Analysis::new(content), range, options)
+}
+
+/// Reformats each top-level declaration overlapping `range`, leaving the
+/// text between them untouched.
+pub(crate) fn range_formatting_for_analysis(
+    content: &str,
+    analysis: &Analysis,
+    range: Range,
+    options: &FormattingOptions,
+) -> Vec<TextEdit> {
+    let Some(proto_file) = analysis.proto_file() else {
+        return Vec::new();
+    };
+
+    let start = to_parser_position(range.start);
+    let end = to_parser_position(range.end);
+    let indent = indent_unit(options);
+
+    proto_file
+        .syntax_span
+        .into_iter()
+        .chain(proto_file.statements.iter().map(|statement| statement.span))
+        .filter(|span| span.start <= end && start <= span.end)
+        .filter_map(|span| {
+            let formatted = parser::format_declaration(content, span, &indent).ok()?;
+            Some(TextEdit {
+                range: to_range(span),
+                new_text: formatted,
+            })
+        })
+        .collect()
+}
+
+fn indent_unit(options: &FormattingOptions) -> String {
+    if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_string()
+    }
+}
+
+/// Lookup key stored in an inlay hint's `data` so its tooltip can be filled
+/// in lazily by `inlayHint/resolve`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InlayHintData {
+    message: String,
+    field: String,
+    /// Whether the JSON name comes from an explicit `json_name` option.
+    explicit: bool,
+}
+
+pub fn compute_inlay_hints(content: &str, range: Range) -> Vec<InlayHint> {
+    inlay_hints_for_analysis(&Analysis::new(content), range)
+}
+
+/// Shows each field's JSON name after the field name when it differs from
+/// it. Tooltips are left to [`compute_inlay_hint_resolve`].
+pub(crate) fn inlay_hints_for_analysis(analysis: &Analysis, range: Range) -> Vec<InlayHint> {
+    fn collect(message: &Message, range: Range, hints: &mut Vec<InlayHint>) {
+        let fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()));
+        for field in fields {
+            let position = to_lsp_position(field.name_span.end);
+            let json_name = field.json_name();
+            if json_name == field.name || position < range.start || range.end < position {
+                continue;
+            }
+
+            hints.push(InlayHint {
+                position,
+                label: InlayHintLabel::String(format!("json: {json_name}")),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: serde_json::to_value(InlayHintData {
+                    message: message.name.clone(),
+                    field: field.name.clone(),
+                    explicit: field.options.contains_key("json_name"),
+                })
+                .ok(),
+            });
+        }
+
+        for nested in &message.nested_messages {
+            collect(nested, range, hints);
+        }
+    }
+
+    let mut hints = Vec::new();
+    if let Some(proto_file) = analysis.proto_file() {
+        for statement in &proto_file.statements {
+            if let Statement::Message(message) = &statement.node {
+                collect(message, range, &mut hints);
+            }
+        }
+    }
+    hints.sort_by_key(|hint| (hint.position.line, hint.position.character));
+    hints
+}
+
+/// Fills in the tooltip of a hint from [`compute_inlay_hints`]. Hints
+/// without recognizable `data` are returned unchanged.
+pub fn compute_inlay_hint_resolve(mut hint: InlayHint) -> InlayHint {
+    let Some(data) = hint
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<InlayHintData>(data).ok())
+    else {
+        return hint;
+    };
+
+    let source = if data.explicit {
+        "set by its `json_name` option"
+    } else {
+        "derived from the field name. Set `json_name` to override it"
+    };
+    hint.tooltip = Some(InlayHintTooltip::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: format!(
+            "JSON name of field `{}` in message `{}`, {source}.",
+            data.field, data.message
+        ),
+    }));
+    hint
+}
+
+/// Token types reported by `compute_semantic_tokens`, in legend order.
+pub const SEMANTIC_TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::PROPERTY,
+    // Custom options such as `(my.option)`, so they stand out from built-in ones
+    SemanticTokenType::DECORATOR,
+];
+
+/// Classifies keywords, scalar types, literals, comments and option names
+/// from the token stream, so highlighting works on files that don't parse.
+/// Files that can't be tokenized get no tokens.
+pub fn compute_semantic_tokens(content: &str) -> Vec<SemanticToken> {
+    let Ok((tokens, comments)) = parser::tokenize_with_comments(content) else {
+        return Vec::new();
+    };
+
+    let mut classified: Vec<(parser::Span, SemanticTokenType)> = Vec::new();
+    // Tokens up to this index were classified as part of an option name
+    let mut option_name_end = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        if i < option_name_end {
+            continue;
+        }
+        if matches!(
+            token.node,
+            Token::Option | Token::LeftBracket | Token::Comma
+        ) {
+            option_name_end = i + 1 + classify_option_name(&tokens[i + 1..], &mut classified);
+        }
+
+        let token_type = match &token.node {
+            Token::Identifier(name)
+                if parser::SCALAR_TYPES.contains(&name.as_str())
+                    || (name == "map"
+                        && tokens.get(i + 1).map(|next| &next.node) == Some(&Token::LeftAngle)) =>
+            {
+                SemanticTokenType::TYPE
+            }
+            // `group` is only a keyword when it declares a group
+            Token::Identifier(name)
+                if name == "group"
+                    && matches!(
+                        tokens.get(i + 1).map(|next| &next.node),
+                        Some(Token::Identifier(_))
+                    ) =>
+            {
+                SemanticTokenType::KEYWORD
+            }
+            Token::Identifier(_) if starts_reserved(&tokens, i) => SemanticTokenType::KEYWORD,
+            Token::StringLiteral(_) => SemanticTokenType::STRING,
+            Token::NumberLiteral(_) => SemanticTokenType::NUMBER,
+            node if is_keyword_token(node) => SemanticTokenType::KEYWORD,
+            _ => continue,
+        };
+        classified.push((token.span, token_type));
+    }
+
+    let lines: Vec<&str> = content.lines().collect();
+    for comment in &comments {
+        // Tokens can't span lines, so multi-line comments are split
+        for line in comment.span.start.line..=comment.span.end.line {
+            let start = if line == comment.span.start.line {
+                comment.span.start.column
+            } else {
+                0
+            };
+            let end = if line == comment.span.end.line {
+                comment.span.end.column
+            } else {
+                lines
+                    .get(line)
+                    .map_or(0, |text| text.encode_utf16().count())
+            };
+            let span = parser::Span {
+                start: parser::Position {
+                    line,
+                    column: start,
+                },
+                end: parser::Position { line, column: end },
+            };
+            classified.push((span, SemanticTokenType::COMMENT));
+        }
+    }
+    classified.sort_by_key(|(span, _)| span.start);
+
+    let mut previous = parser::Position::default();
+    classified
+        .into_iter()
+        .filter(|(span, _)| span.end.column > span.start.column)
+        .map(|(span, token_type)| {
+            let delta_line = span.start.line - previous.line;
+            let delta_start = if delta_line == 0 {
+                span.start.column - previous.column
+            } else {
+                span.start.column
+            };
+            previous = span.start;
+            SemanticToken {
+                delta_line: delta_line as u32,
+                delta_start: delta_start as u32,
+                length: (span.end.column - span.start.column) as u32,
+                token_type: SEMANTIC_TOKEN_TYPES
+                    .iter()
+                    .position(|known| *known == token_type)
+                    .unwrap_or_default() as u32,
+                token_modifiers_bitset: 0,
+            }
+        })
+        .collect()
+}
+
+/// If `tokens` start with an option name followed by `=`, classifies its
+/// segments (`(custom.name)` as a decorator, plain identifiers as
+/// properties) and returns the number of tokens it covers; otherwise 0.
+fn classify_option_name(
+    tokens: &[parser::Spanned<Token>],
+    classified: &mut Vec<(parser::Span, SemanticTokenType)>,
+) -> usize {
+    let length = tokens
+        .iter()
+        .take_while(|token| {
+            matches!(
+                token.node,
+                Token::Identifier(_) | Token::Dot | Token::LeftParen | Token::RightParen
+            )
+        })
+        .count();
+    if length == 0 || tokens.get(length).map(|token| &token.node) != Some(&Token::Equals) {
+        return 0;
+    }
+
+    let mut custom_start = None;
+    for token in &tokens[..length] {
+        match token.node {
+            Token::LeftParen => custom_start = Some(token.span.start),
+            Token::RightParen => {
+                if let Some(start) = custom_start.take() {
+                    let span = parser::Span {
+                        start,
+                        end: token.span.end,
+                    };
+                    classified.push((span, SemanticTokenType::DECORATOR));
+                }
+            }
+            Token::Identifier(_) if custom_start.is_none() => {
+                classified.push((token.span, SemanticTokenType::PROPERTY));
+            }
+            _ => {}
+        }
+    }
+    length
+}
+
+/// Whether `tokens[i]` is the contextual keyword `reserved` starting a
+/// reserved statement, rather than a type or value named `reserved`.
+fn starts_reserved(tokens: &[parser::Spanned<Token>], i: usize) -> bool {
+    if !matches!(&tokens[i].node, Token::Identifier(id) if id == "reserved") {
+        return false;
+    }
+    match tokens.get(i + 1).map(|token| &token.node) {
+        Some(Token::NumberLiteral(_) | Token::StringLiteral(_)) => true,
+        Some(Token::Identifier(_)) => {
+            tokens.get(i + 2).map(|token| &token.node) != Some(&Token::Equals)
+        }
+        _ => false,
+    }
+}
+
+fn is_keyword_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Syntax
+            | Token::Edition
+            | Token::Package
+            | Token::Import
+            | Token::Public
+            | Token::Weak
+            | Token::Message
+            | Token::Enum
+            | Token::Service
+            | Token::Rpc
+            | Token::Returns
+            | Token::Stream
+            | Token::Optional
+            | Token::Required
+            | Token::Repeated
+            | Token::Oneof
+            | Token::Option
+            | Token::True
+            | Token::False
+    )
+}
+
+/// Command that sorts and dedupes a document's imports. Its single argument
+/// is the document URI.
+pub const ORGANIZE_IMPORTS_COMMAND: &str = "protobuf.organizeImports";
+
+pub fn compute_organize_imports(uri: &Url, content: &str) -> Option<WorkspaceEdit> {
+    organize_imports_for_analysis(uri, content, &Analysis::new(content))
+}
+
+/// Sorts the document's imports by path and drops repeated ones, gathering
+/// them where the first import is. Returns `None` if they're already
+/// organized or the document doesn't parse.
+pub(crate) fn organize_imports_for_analysis(
+    uri: &Url,
+    content: &str,
+    analysis: &Analysis,
+) -> Option<WorkspaceEdit> {
+    let proto_file = analysis.proto_file()?;
+    let imports: Vec<(&str, bool, bool, parser::Span)> = proto_file
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.node {
+            Statement::Import {
+                path, public, weak, ..
+            } => Some((path.as_str(), *public, *weak, statement.span)),
+            _ => None,
+        })
+        .collect();
+    let (_, _, _, first_span) = *imports.first()?;
+
+    // A path imported twice keeps its strongest modifier
+    let mut organized: Vec<(&str, bool, bool)> = Vec::new();
+    for &(path, public, weak, _) in &imports {
+        match organized
+            .iter_mut()
+            .find(|(existing, _, _)| *existing == path)
+        {
+            Some(existing) => {
+                existing.1 |= public;
+                existing.2 &= weak;
+            }
+            None => organized.push((path, public, weak)),
+        }
+    }
+    organized.sort_by_key(|(path, _, _)| *path);
+
+    let contiguous = imports.windows(2).all(|pair| {
+        proto_file.statements.iter().all(|statement| {
+            statement.span.start <= pair[0].3.start || statement.span.start >= pair[1].3.start
+        })
+    });
+    let unchanged = contiguous
+        && organized.len() == imports.len()
+        && organized
+            .iter()
+            .zip(&imports)
+            .all(|(organized, import)| organized.0 == import.0);
+    if unchanged {
+        return None;
+    }
+
+    let block: Vec<String> = organized
+        .iter()
+        .map(|(path, public, weak)| {
+            let modifier = if *public {
+                "public "
+            } else if *weak {
+                "weak "
+            } else {
+                ""
+            };
+            format!("import {modifier}\"{path}\";")
+        })
+        .collect();
+
+    let lines: Vec<&str> = content.lines().collect();
+    let mut edits = vec![TextEdit {
+        range: to_range(first_span),
+        new_text: block.join(parser::line_break(content)),
+    }];
+    edits.extend(imports[1..].iter().map(|&(_, _, _, span)| TextEdit {
+        range: statement_line_range(&lines, span),
+        new_text: String::new(),
+    }));
+
+    Some(WorkspaceEdit {
+        changes: Some([(uri.clone(), edits)].into_iter().collect()),
+        ..Default::default()
+    })
+}
+
+/// The range of the whole line(s) holding `span` if nothing else is on
+/// them, so removing it doesn't leave a blank line behind; otherwise just
+/// the span.
+fn statement_line_range(lines: &[&str], span: parser::Span) -> Range {
+    let (Some(first), Some(last)) = (lines.get(span.start.line), lines.get(span.end.line)) else {
+        return to_range(span);
+    };
+
+    let before = &first[..byte_offset_at(first, span.start.column)];
+    let after = &last[byte_offset_at(last, span.end.column)..];
+    if !before.trim().is_empty() || !after.trim().is_empty() {
+        return to_range(span);
+    }
+
+    Range::new(
+        Position::new(span.start.line as u32, 0),
+        Position::new(span.end.line as u32 + 1, 0),
+    )
+}
+
+fn document_end(content: &str) -> parser::Position {
+    let mut end = parser::Position::default();
+    for ch in content.chars() {
+        if ch == '\n' {
+            end.line += 1;
+            end.column = 0;
+        } else {
+            end.column += ch.len_utf16();
+        }
+    }
+    end
+}
+
+fn to_parser_position(position: Position) -> parser::Position {
+    parser::Position {
+        line: position.line as usize,
+        column: position.character as usize,
+    }
+}
+
+fn to_lsp_position(position: parser::Position) -> Position {
+    Position {
+        line: position.line as u32,
+        character: position.column as u32,
+    }
+}
+
+fn to_range(span: parser::Span) -> Range {
+    Range {
+        start: to_lsp_position(span.start),
+        end: to_lsp_position(span.end),
+    }
+}
+
+fn is_inside_message(lines: &[&str], current_line: usize) -> bool {
+    let mut brace_count = 0;
+    let mut in_message = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        if i > current_line {
+            break;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.starts_with("message ") {
+            in_message = true;
+        }
+
+        for ch in line.chars() {
+            if ch == '{' {
+                brace_count += 1;
+            } else if ch == '}' {
+                brace_count -= 1;
+                if brace_count == 0 {
+                    in_message = false;
+                }
+            }
+        }
+    }
+
+    in_message && brace_count > 0
+}
+
+/// Returns the identifier around the UTF-16 column `position` of `line`,
+/// with the UTF-16 columns it spans.
+fn get_word_at_position(line: &str, position: usize) -> (String, std::ops::Range<usize>) {
+    text_around(line, position, |ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Like `get_word_at_position`, but expands across `.` so a cursor on any
+/// segment of `google.protobuf.Timestamp` returns the whole path.
+fn get_qualified_name_at_position(line: &str, position: usize) -> (String, std::ops::Range<usize>) {
+    let (text, columns) = text_around(line, position, |ch| {
+        ch.is_alphanumeric() || ch == '_' || ch == '.'
+    });
+    let leading_dots = text.len() - text.trim_start_matches('.').len();
+    let trailing_dots = text.len() - text.trim_end_matches('.').len();
+    let name = text.trim_matches('.').to_string();
+    let start = (columns.start + leading_dots).min(columns.end);
+    (
+        name,
+        start..columns.end.saturating_sub(trailing_dots).max(start),
+    )
+}
+
+fn text_around(
+    line: &str,
+    position: usize,
+    is_part: impl Fn(char) -> bool,
+) -> (String, std::ops::Range<usize>) {
+    let chars: Vec<char> = line.chars().collect();
+    let position = chars
+        .len()
+        .min(line[..byte_offset_at(line, position)].chars().count());
+    let mut start = position;
+    let mut end = position;
+
+    // Find word boundaries
+    while start > 0 && is_part(chars[start - 1]) {
+        start -= 1;
+    }
+
+    while end < chars.len() && is_part(chars[end]) {
+        end += 1;
+    }
+
+    let utf16_column = |index: usize| chars[..index].iter().map(|ch| ch.len_utf16()).sum();
+    (
+        chars[start..end].iter().collect(),
+        utf16_column(start)..utf16_column(end),
+    )
+}
+
+/// Converts a UTF-16 column into a byte offset into `line`, clamped to the
+/// end of the line and rounded down to a char boundary.
+fn byte_offset_at(line: &str, utf16_column: usize) -> usize {
+    let mut column = 0;
+    for (offset, ch) in line.char_indices() {
+        column += ch.len_utf16();
+        if column > utf16_column {
+            return offset;
+        }
+    }
+    line.len()
 }
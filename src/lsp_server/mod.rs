@@ -1,31 +1,275 @@
-use std::sync::Arc;
+use crate::parser::is_valid_identifier;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::RwLock;
-use tower_lsp::jsonrpc::Result as JsonRpcResult;
+use tokio::task::JoinHandle;
+use tower_lsp::jsonrpc::{Error as JsonRpcError, Result as JsonRpcResult};
+use tower_lsp::lsp_types::request::{GotoTypeDefinitionParams, GotoTypeDefinitionResponse};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
-mod document_store;
+pub mod analysis;
+pub mod document_store;
 pub mod handlers;
+pub mod progress;
+pub mod settings;
+pub mod well_known_types;
+pub mod workspace_index;
 
-use document_store::DocumentStore;
+use analysis::Analysis;
+use document_store::{Document, DocumentStore};
+use progress::WorkDone;
+use settings::{Settings, CONFIGURATION_SECTION};
+use workspace_index::{find_proto_files, WorkspaceIndex};
 
 pub struct ProtobufLanguageServer {
     client: Client,
     documents: Arc<RwLock<DocumentStore>>,
+    settings: Arc<RwLock<Settings>>,
+    /// Debounced diagnostics runs, at most one per document.
+    pending_diagnostics: Mutex<HashMap<Url, JoinHandle<()>>>,
+    /// `.proto` files on disk, for cross-file lookups.
+    index: Arc<RwLock<WorkspaceIndex>>,
+    /// Whether the client lets us register a `.proto` file watcher.
+    can_watch_files: AtomicBool,
+    /// Whether the client shows server-initiated `$/progress`.
+    can_show_progress: AtomicBool,
 }
 
+/// How long to wait after an edit before re-analyzing the document.
+const DIAGNOSTICS_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Watched-file batches at least this large (e.g. after a branch switch)
+/// report progress while they are re-indexed.
+const BULK_REINDEX_THRESHOLD: usize = 20;
+
 impl ProtobufLanguageServer {
     pub fn new(client: Client) -> Self {
         Self {
             client,
             documents: Arc::new(RwLock::new(DocumentStore::new())),
+            settings: Arc::new(RwLock::new(Settings::default())),
+            pending_diagnostics: Mutex::new(HashMap::new()),
+            index: Arc::new(RwLock::new(WorkspaceIndex::new())),
+            can_watch_files: AtomicBool::new(false),
+            can_show_progress: AtomicBool::new(false),
+        }
+    }
+
+    /// Indexes every `.proto` file under `folders`, reporting progress as it
+    /// goes.
+    async fn index_folders(&self, folders: Vec<PathBuf>) {
+        let files = tokio::task::spawn_blocking(move || {
+            folders
+                .iter()
+                .flat_map(|root| find_proto_files(root))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+        if files.is_empty() {
+            return;
+        }
+
+        let changes = files
+            .into_iter()
+            .filter_map(|path| Url::from_file_path(path).ok())
+            .map(|uri| FileEvent::new(uri, FileChangeType::CREATED))
+            .collect();
+        self.reindex("indexing", "Indexing protobuf files", changes)
+            .await;
+    }
+
+    /// Applies file events to the index, with a progress bar titled `title`.
+    async fn reindex(&self, id: &str, title: &str, changes: Vec<FileEvent>) {
+        let total = changes.len();
+        let mut progress = WorkDone::begin(
+            &self.client,
+            self.can_show_progress.load(Ordering::Relaxed),
+            id,
+            title,
+            total,
+        )
+        .await;
+
+        for (done, change) in changes.into_iter().enumerate() {
+            self.index_file(change).await;
+            progress.report(done + 1).await;
+        }
+        progress.end(format!("Indexed {total} files")).await;
+    }
+
+    /// Runs `handler` against an open document on a blocking thread, for
+    /// requests that resolve imports and so may read files from disk.
+    async fn with_document_blocking<T: Send + 'static>(
+        &self,
+        uri: Url,
+        handler: impl FnOnce(&Url, &Document, &WorkspaceIndex, &Settings) -> Option<T> + Send + 'static,
+    ) -> Option<T> {
+        let store = Arc::clone(&self.documents).read_owned().await;
+        let index = Arc::clone(&self.index).read_owned().await;
+        let settings = self.settings.read().await.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let document = store.get_document(&uri)?;
+            handler(&uri, document, &index, &settings)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Re-reads (or drops) a single file in the index.
+    async fn index_file(&self, change: FileEvent) {
+        if !change.uri.path().ends_with(".proto") {
+            return;
+        }
+
+        let content = if change.typ == FileChangeType::DELETED {
+            None
+        } else {
+            match change.uri.to_file_path() {
+                Ok(path) => tokio::fs::read_to_string(path).await.ok(),
+                Err(()) => None,
+            }
+        };
+
+        let mut index = self.index.write().await;
+        match content {
+            Some(content) => index.update(change.uri, &content),
+            None => index.remove(&change.uri),
+        }
+    }
+
+    /// Computes diagnostics for `version` of a document, moving the parse of
+    /// large documents onto a blocking thread. Returns `None` if the document
+    /// was closed or changed in the meantime.
+    async fn analyze(
+        documents: &RwLock<DocumentStore>,
+        settings: &RwLock<Settings>,
+        uri: &Url,
+        version: i32,
+    ) -> Option<Vec<Diagnostic>> {
+        let settings = settings.read().await.clone();
+
+        let content = {
+            let store = documents.read().await;
+            let document = store.get_document(uri)?;
+            if document.version != version {
+                return None;
+            }
+            if document.has_analysis()
+                || document.content.len() <= settings.background_analysis_threshold
+                || handlers::oversized_file_diagnostic(&document.content, &settings).is_some()
+            {
+                return Some(document.diagnostics(uri, &settings));
+            }
+            document.content.clone()
+        };
+
+        let analysis = tokio::task::spawn_blocking(move || Analysis::new(&content))
+            .await
+            .ok()?;
+
+        let store = documents.read().await;
+        let document = store.get_document(uri)?;
+        if document.version != version {
+            return None;
+        }
+        document.set_analysis(analysis);
+        Some(document.diagnostics(uri, &settings))
+    }
+
+    /// Publishes diagnostics for `uri` once edits have settled, replacing any
+    /// run still waiting for an earlier version.
+    fn schedule_diagnostics(&self, uri: Url, version: i32) {
+        let client = self.client.clone();
+        let documents = Arc::clone(&self.documents);
+        let settings = Arc::clone(&self.settings);
+
+        let task_uri = uri.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(DIAGNOSTICS_DEBOUNCE).await;
+
+            // A newer version has its own run scheduled
+            let Some(diagnostics) = Self::analyze(&documents, &settings, &task_uri, version).await
+            else {
+                return;
+            };
+
+            client
+                .publish_diagnostics(task_uri, diagnostics, Some(version))
+                .await;
+        });
+
+        let mut pending = self.pending_diagnostics.lock().unwrap();
+        if let Some(previous) = pending.insert(uri, task) {
+            previous.abort();
+        }
+    }
+
+    async fn update_settings(&self, mut settings: Settings) {
+        {
+            // Workspace folders come from the client, not the configuration
+            let mut current = self.settings.write().await;
+            settings.workspace_folders = std::mem::take(&mut current.workspace_folders);
+            *current = settings;
+        }
+
+        // Re-publish diagnostics so toggled rules take effect immediately
+        let mut reports = Vec::new();
+        {
+            let store = self.documents.read().await;
+            for (uri, doc) in store.documents() {
+                reports.push((uri.clone(), self.diagnostics_for(uri, doc).await));
+            }
         }
+
+        for (uri, diagnostics) in reports {
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
+
+    async fn diagnostics_for(&self, uri: &Url, document: &Document) -> Vec<Diagnostic> {
+        let settings = self.settings.read().await;
+        document.diagnostics(uri, &settings)
     }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for ProtobufLanguageServer {
-    async fn initialize(&self, _: InitializeParams) -> JsonRpcResult<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> JsonRpcResult<InitializeResult> {
+        let can_watch_files = params
+            .capabilities
+            .workspace
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
+        self.can_watch_files
+            .store(can_watch_files, Ordering::Relaxed);
+
+        let can_show_progress = params
+            .capabilities
+            .window
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.can_show_progress
+            .store(can_show_progress, Ordering::Relaxed);
+
+        let roots = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        self.settings.write().await.workspace_folders = roots
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
@@ -35,7 +279,7 @@ impl LanguageServer for ProtobufLanguageServer {
                     DiagnosticOptions {
                         identifier: Some("protobuf-edition-lsp".to_string()),
                         inter_file_dependencies: false,
-                        workspace_diagnostics: false,
+                        workspace_diagnostics: true,
                         work_done_progress_options: WorkDoneProgressOptions::default(),
                     },
                 )),
@@ -44,13 +288,62 @@ impl LanguageServer for ProtobufLanguageServer {
                         ".".to_string(),
                         " ".to_string(),
                         "=".to_string(),
+                        "\"".to_string(),
                     ]),
                     work_done_progress_options: WorkDoneProgressOptions::default(),
                     all_commit_characters: None,
-                    resolve_provider: None,
+                    resolve_provider: Some(true),
                     completion_item: None,
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: SemanticTokensLegend {
+                                token_types: handlers::SEMANTIC_TOKEN_TYPES.to_vec(),
+                                token_modifiers: Vec::new(),
+                            },
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            range: None,
+                            work_done_progress_options: WorkDoneProgressOptions::default(),
+                        },
+                    ),
+                ),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![handlers::ORGANIZE_IMPORTS_COMMAND.to_string()],
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                inlay_hint_provider: Some(OneOf::Right(InlayHintServerCapabilities::Options(
+                    InlayHintOptions {
+                        resolve_provider: Some(true),
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                ))),
                 ..Default::default()
             },
             ..Default::default()
@@ -61,47 +354,145 @@ impl LanguageServer for ProtobufLanguageServer {
         self.client
             .log_message(MessageType::INFO, "Protobuf LSP server initialized")
             .await;
+
+        let items = vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some(CONFIGURATION_SECTION.to_string()),
+        }];
+        if let Ok(mut values) = self.client.configuration(items).await {
+            if let Some(value) = values.pop() {
+                self.update_settings(Settings::from_value(value)).await;
+            }
+        }
+
+        // File watchers can only be registered dynamically
+        if self.can_watch_files.load(Ordering::Relaxed) {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![FileSystemWatcher {
+                    glob_pattern: GlobPattern::String("**/*.proto".to_string()),
+                    kind: None,
+                }],
+            };
+            let registration = Registration {
+                id: "proto-file-watcher".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Failed to register file watcher: {e}"),
+                    )
+                    .await;
+            }
+        }
+
+        let folders = self.settings.read().await.workspace_folders.clone();
+        self.index_folders(folders).await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let to_paths = |folders: Vec<WorkspaceFolder>| -> Vec<PathBuf> {
+            folders
+                .into_iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .collect()
+        };
+        let removed = to_paths(params.event.removed);
+        let added = to_paths(params.event.added);
+
+        {
+            let mut settings = self.settings.write().await;
+            settings
+                .workspace_folders
+                .retain(|folder| !removed.contains(folder));
+            settings.workspace_folders.extend(added.iter().cloned());
+        }
+
+        {
+            let mut index = self.index.write().await;
+            let stale: Vec<Url> = index
+                .files()
+                .map(|(uri, _)| uri)
+                .filter(|uri| {
+                    uri.to_file_path()
+                        .is_ok_and(|path| removed.iter().any(|folder| path.starts_with(folder)))
+                })
+                .cloned()
+                .collect();
+            for uri in &stale {
+                index.remove(uri);
+            }
+        }
+
+        self.index_folders(added).await;
     }
 
     async fn shutdown(&self) -> JsonRpcResult<()> {
         Ok(())
     }
 
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        if params.changes.len() >= BULK_REINDEX_THRESHOLD {
+            self.reindex("reindexing", "Re-indexing protobuf files", params.changes)
+                .await;
+            return;
+        }
+
+        for change in params.changes {
+            self.index_file(change).await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.update_settings(Settings::from_value(params.settings))
+            .await;
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let mut store = self.documents.write().await;
-        store.open_document(
-            params.text_document.uri.clone(),
-            params.text_document.text.clone(),
-            params.text_document.version,
-        );
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        self.documents
+            .write()
+            .await
+            .open_document(uri.clone(), params.text_document.text, version);
 
         // Trigger diagnostics
-        let diagnostics = handlers::compute_diagnostics(&params.text_document.text);
+        let Some(diagnostics) = Self::analyze(&self.documents, &self.settings, &uri, version).await
+        else {
+            return;
+        };
         self.client
-            .publish_diagnostics(params.text_document.uri, diagnostics, None)
+            .publish_diagnostics(uri, diagnostics, Some(version))
             .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let mut store = self.documents.write().await;
+        if params.content_changes.is_empty() {
+            return;
+        }
 
-        // We use full text sync, so there should be exactly one change
-        if let Some(change) = params.content_changes.into_iter().next() {
-            store.update_document(
-                params.text_document.uri.clone(),
-                change.text.clone(),
-                params.text_document.version,
-            );
+        let mut store = self.documents.write().await;
+        store.apply_changes(
+            &params.text_document.uri,
+            params.content_changes,
+            params.text_document.version,
+        );
 
-            // Trigger diagnostics
-            let diagnostics = handlers::compute_diagnostics(&change.text);
-            self.client
-                .publish_diagnostics(params.text_document.uri, diagnostics, None)
-                .await;
-        }
+        self.schedule_diagnostics(params.text_document.uri, params.text_document.version);
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        if let Some(pending) = self
+            .pending_diagnostics
+            .lock()
+            .unwrap()
+            .remove(&params.text_document.uri)
+        {
+            pending.abort();
+        }
+
         let mut store = self.documents.write().await;
         store.close_document(&params.text_document.uri);
     }
@@ -114,21 +505,32 @@ impl LanguageServer for ProtobufLanguageServer {
         let document = store.get_document(&params.text_document_position.text_document.uri);
 
         if let Some(doc) = document {
-            let completions =
-                handlers::compute_completions(&doc.content, params.text_document_position.position);
+            let settings = self.settings.read().await;
+            let completions = handlers::completions_for_analysis(
+                &doc.content,
+                doc.analysis(),
+                params.text_document_position.position,
+                &settings,
+            );
             Ok(Some(CompletionResponse::Array(completions)))
         } else {
             Ok(None)
         }
     }
 
+    async fn completion_resolve(&self, item: CompletionItem) -> JsonRpcResult<CompletionItem> {
+        let store = self.documents.read().await;
+        Ok(handlers::compute_completion_resolve(&store, item))
+    }
+
     async fn hover(&self, params: HoverParams) -> JsonRpcResult<Option<Hover>> {
         let store = self.documents.read().await;
         let document = store.get_document(&params.text_document_position_params.text_document.uri);
 
         if let Some(doc) = document {
-            Ok(handlers::compute_hover(
+            Ok(handlers::hover_for_analysis(
                 &doc.content,
+                doc.analysis(),
                 params.text_document_position_params.position,
             ))
         } else {
@@ -136,6 +538,275 @@ impl LanguageServer for ProtobufLanguageServer {
         }
     }
 
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> JsonRpcResult<Option<Vec<SelectionRange>>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+
+        Ok(document.map(|doc| {
+            handlers::selection_ranges_for_analysis(&doc.content, doc.analysis(), &params.positions)
+        }))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> JsonRpcResult<Option<DocumentSymbolResponse>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+
+        Ok(document.map(|doc| {
+            DocumentSymbolResponse::Nested(handlers::document_symbols_for_analysis(doc.analysis()))
+        }))
+    }
+
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> JsonRpcResult<Option<Vec<FoldingRange>>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+
+        Ok(document.map(|doc| handlers::folding_ranges_for_analysis(doc.analysis())))
+    }
+
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> JsonRpcResult<Option<Vec<TextEdit>>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+
+        Ok(document.map(|doc| {
+            handlers::formatting_for_analysis(&doc.content, doc.analysis(), &params.options)
+        }))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> JsonRpcResult<Option<Vec<TextEdit>>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+
+        Ok(document.map(|doc| {
+            handlers::range_formatting_for_analysis(
+                &doc.content,
+                doc.analysis(),
+                params.range,
+                &params.options,
+            )
+        }))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> JsonRpcResult<Option<PrepareRenameResponse>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+
+        Ok(document
+            .and_then(|doc| handlers::prepare_rename_for_analysis(doc.analysis(), params.position)))
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> JsonRpcResult<Option<Vec<CallHierarchyItem>>> {
+        let position = params.text_document_position_params;
+        let store = self.documents.read().await;
+        let document = store.get_document(&position.text_document.uri);
+
+        Ok(document.and_then(|doc| {
+            handlers::prepare_call_hierarchy_for_analysis(
+                &position.text_document.uri,
+                doc.analysis(),
+                position.position,
+            )
+        }))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> JsonRpcResult<Option<Vec<CallHierarchyIncomingCall>>> {
+        let store = self.documents.read().await;
+        Ok(Some(handlers::compute_incoming_calls(&store, &params.item)))
+    }
+
+    async fn rename(&self, params: RenameParams) -> JsonRpcResult<Option<WorkspaceEdit>> {
+        if !is_valid_identifier(&params.new_name) {
+            return Err(JsonRpcError::invalid_params(format!(
+                "'{}' is not a valid identifier",
+                params.new_name
+            )));
+        }
+
+        let uri = params.text_document_position.text_document.uri;
+        let store = self.documents.read().await;
+        let document = store.get_document(&uri);
+
+        Ok(document.and_then(|doc| {
+            handlers::rename_for_analysis(
+                &uri,
+                doc.analysis(),
+                params.text_document_position.position,
+                &params.new_name,
+            )
+        }))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> JsonRpcResult<Option<CodeActionResponse>> {
+        let store = self.documents.read().await;
+        let document = store.get_document(&params.text_document.uri);
+        let settings = self.settings.read().await;
+
+        Ok(document.map(|doc| {
+            handlers::code_actions_for_analysis(
+                &params.text_document.uri,
+                &doc.content,
+                doc.analysis(),
+                params.range,
+                &params.context.diagnostics,
+                &settings,
+            )
+        }))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> JsonRpcResult<Option<SemanticTokensResult>> {
+        let store = self.documents.read().await;
+        Ok(store.get_document(&params.text_document.uri).map(|doc| {
+            SemanticTokensResult::Tokens(SemanticTokens {
+                result_id: None,
+                data: handlers::compute_semantic_tokens(&doc.content),
+            })
+        }))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> JsonRpcResult<Option<Vec<InlayHint>>> {
+        let store = self.documents.read().await;
+        Ok(store
+            .get_document(&params.text_document.uri)
+            .map(|doc| handlers::inlay_hints_for_analysis(doc.analysis(), params.range)))
+    }
+
+    async fn inlay_hint_resolve(&self, params: InlayHint) -> JsonRpcResult<InlayHint> {
+        Ok(handlers::compute_inlay_hint_resolve(params))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> JsonRpcResult<Option<serde_json::Value>> {
+        if params.command != handlers::ORGANIZE_IMPORTS_COMMAND {
+            return Err(JsonRpcError::invalid_params(format!(
+                "unknown command '{}'",
+                params.command
+            )));
+        }
+
+        let uri = params
+            .arguments
+            .first()
+            .and_then(|argument| serde_json::from_value::<Url>(argument.clone()).ok())
+            .ok_or_else(|| JsonRpcError::invalid_params("expected a document URI"))?;
+
+        let edit = {
+            let store = self.documents.read().await;
+            store.get_document(&uri).and_then(|doc| {
+                handlers::organize_imports_for_analysis(&uri, &doc.content, doc.analysis())
+            })
+        };
+        if let Some(edit) = edit {
+            if let Err(e) = self.client.apply_edit(edit).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Failed to organize imports: {e}"),
+                    )
+                    .await;
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> JsonRpcResult<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        Ok(self
+            .with_document_blocking(
+                position.text_document.uri,
+                move |uri, doc, index, settings| {
+                    handlers::definition_for_analysis(
+                        uri,
+                        doc.analysis(),
+                        position.position,
+                        index,
+                        settings,
+                    )
+                },
+            )
+            .await)
+    }
+
+    async fn goto_type_definition(
+        &self,
+        params: GotoTypeDefinitionParams,
+    ) -> JsonRpcResult<Option<GotoTypeDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        Ok(self
+            .with_document_blocking(
+                position.text_document.uri,
+                move |uri, doc, index, settings| {
+                    handlers::type_definition_for_analysis(
+                        uri,
+                        doc.analysis(),
+                        position.position,
+                        index,
+                        settings,
+                    )
+                },
+            )
+            .await)
+    }
+
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> JsonRpcResult<Option<Vec<DocumentLink>>> {
+        Ok(self
+            .with_document_blocking(params.text_document.uri, |uri, doc, _, settings| {
+                Some(handlers::document_links_for_analysis(
+                    uri,
+                    doc.analysis(),
+                    settings,
+                ))
+            })
+            .await)
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> JsonRpcResult<Option<Vec<SymbolInformation>>> {
+        let store = self.documents.read().await;
+        let symbols = handlers::compute_workspace_symbols(&store, &params.query);
+
+        Ok(Some(symbols))
+    }
+
     async fn diagnostic(
         &self,
         params: DocumentDiagnosticParams,
@@ -144,15 +815,14 @@ impl LanguageServer for ProtobufLanguageServer {
         let document = store.get_document(&params.text_document.uri);
 
         if let Some(doc) = document {
-            let diagnostics = handlers::compute_diagnostics(&doc.content);
+            let settings = self.settings.read().await;
             Ok(DocumentDiagnosticReportResult::Report(
-                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
-                    related_documents: None,
-                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
-                        result_id: None,
-                        items: diagnostics,
-                    },
-                }),
+                handlers::compute_document_diagnostic_report(
+                    &params.text_document.uri,
+                    doc,
+                    &settings,
+                    params.previous_result_id.as_deref(),
+                ),
             ))
         } else {
             Ok(DocumentDiagnosticReportResult::Report(
@@ -166,4 +836,31 @@ impl LanguageServer for ProtobufLanguageServer {
             ))
         }
     }
+
+    async fn workspace_diagnostic(
+        &self,
+        _: WorkspaceDiagnosticParams,
+    ) -> JsonRpcResult<WorkspaceDiagnosticReportResult> {
+        let store = self.documents.read().await;
+        let mut documents: Vec<_> = store.documents().collect();
+        documents.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+        let mut items = Vec::with_capacity(documents.len());
+        for (uri, document) in documents {
+            items.push(WorkspaceDocumentDiagnosticReport::Full(
+                WorkspaceFullDocumentDiagnosticReport {
+                    uri: uri.clone(),
+                    version: Some(i64::from(document.version)),
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: self.diagnostics_for(uri, document).await,
+                    },
+                },
+            ));
+        }
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
 }
@@ -0,0 +1,92 @@
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
+};
+use tower_lsp::Client;
+
+/// A server-initiated `$/progress` bar, e.g. "Indexing protobuf files".
+/// Reports nothing if the client can't show progress or rejected the token.
+pub struct WorkDone {
+    client: Client,
+    token: Option<ProgressToken>,
+    total: usize,
+    percentage: u32,
+}
+
+impl WorkDone {
+    /// Creates a progress token and sends the `begin` notification for
+    /// `total` files. `supported` is the client's
+    /// `window.workDoneProgress` capability.
+    pub async fn begin(
+        client: &Client,
+        supported: bool,
+        id: &str,
+        title: &str,
+        total: usize,
+    ) -> Self {
+        let token = NumberOrString::String(format!("protobuf-edition-lsp/{id}"));
+        let created = supported
+            && client
+                .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                    token: token.clone(),
+                })
+                .await
+                .is_ok();
+
+        let progress = Self {
+            client: client.clone(),
+            token: created.then_some(token),
+            total,
+            percentage: 0,
+        };
+        progress
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                message: Some(format!("0/{total} files")),
+                percentage: Some(0),
+            }))
+            .await;
+        progress
+    }
+
+    /// Reports that `done` files are finished. Only sends a notification
+    /// when the percentage changes, so large workspaces don't flood the client.
+    pub async fn report(&mut self, done: usize) {
+        let percentage = (done * 100 / self.total.max(1)) as u32;
+        if percentage == self.percentage {
+            return;
+        }
+
+        self.percentage = percentage;
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(format!("{done}/{} files", self.total)),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: String) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message),
+        }))
+        .await;
+    }
+
+    async fn send(&self, value: WorkDoneProgress) {
+        let Some(token) = &self.token else {
+            return;
+        };
+
+        self.client
+            .send_notification::<Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+}
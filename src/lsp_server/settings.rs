@@ -0,0 +1,115 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Configuration section requested from the client.
+pub const CONFIGURATION_SECTION: &str = "protobuf";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Rule codes (e.g. `duplicate-field-number`) whose diagnostics are suppressed.
+    pub disabled_rules: HashSet<String>,
+    /// Directories searched when resolving `import` paths.
+    pub import_roots: Vec<PathBuf>,
+    /// When `google.protobuf` well-known types are offered as completions.
+    pub well_known_type_completions: WellKnownTypeCompletions,
+    /// Documents larger than this many bytes are analyzed on a blocking
+    /// thread so they don't stall other requests.
+    pub background_analysis_threshold: usize,
+    /// Documents larger than this many bytes get no diagnostics at all.
+    pub max_file_size: usize,
+    /// Warn on `syntax = "proto2"`/`"proto3"` files and offer to migrate them
+    /// to `edition = "2023"`.
+    pub require_editions: bool,
+    /// Hint at messages and services that declare nothing.
+    pub lint_empty_declarations: bool,
+    /// Warn on services that declare no methods.
+    pub require_methods: bool,
+    /// Workspace folders reported by the client rather than configured.
+    /// Imports are resolved against them after `import_roots`.
+    #[serde(skip)]
+    pub workspace_folders: Vec<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            disabled_rules: HashSet::new(),
+            import_roots: Vec::new(),
+            well_known_type_completions: WellKnownTypeCompletions::default(),
+            background_analysis_threshold: 256 * 1024,
+            max_file_size: 8 * 1024 * 1024,
+            require_editions: false,
+            lint_empty_declarations: false,
+            require_methods: false,
+            workspace_folders: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WellKnownTypeCompletions {
+    /// Only types whose defining file is imported.
+    #[default]
+    Imported,
+    Always,
+}
+
+impl Settings {
+    /// Builds settings from a configuration payload, accepting either the
+    /// `protobuf` section itself or an object wrapping it. Keys with invalid
+    /// values are logged and ignored; the remaining keys still apply.
+    pub fn from_value(value: Value) -> Self {
+        let section = match value {
+            Value::Object(mut map) if map.contains_key(CONFIGURATION_SECTION) => {
+                map.remove(CONFIGURATION_SECTION).unwrap_or_default()
+            }
+            other => other,
+        };
+
+        let error = match serde_json::from_value(section.clone()) {
+            Ok(settings) => return settings,
+            Err(error) => error,
+        };
+        let Value::Object(entries) = section else {
+            tracing::warn!("Ignoring invalid `{CONFIGURATION_SECTION}` settings: {error}");
+            return Self::default();
+        };
+
+        let mut valid = serde_json::Map::new();
+        for (key, value) in entries {
+            valid.insert(key.clone(), value);
+            if let Err(error) = serde_json::from_value::<Self>(Value::Object(valid.clone())) {
+                tracing::warn!("Ignoring invalid `{CONFIGURATION_SECTION}.{key}` setting: {error}");
+                valid.remove(&key);
+            }
+        }
+        serde_json::from_value(Value::Object(valid)).unwrap_or_default()
+    }
+
+    /// Directories searched for imports, in priority order. Relative import
+    /// roots are taken relative to each workspace folder, or to the working
+    /// directory when there are none.
+    pub fn search_roots(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.import_roots
+            .iter()
+            .flat_map(|root| {
+                if root.is_absolute() || self.workspace_folders.is_empty() {
+                    vec![root.clone()]
+                } else {
+                    self.workspace_folders
+                        .iter()
+                        .map(|folder| folder.join(root))
+                        .collect()
+                }
+            })
+            .chain(self.workspace_folders.iter().cloned())
+    }
+
+    pub fn is_rule_enabled(&self, code: &str) -> bool {
+        !self.disabled_rules.contains(code)
+    }
+}
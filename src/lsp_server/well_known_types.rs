@@ -0,0 +1,82 @@
+/// A type from the `google.protobuf` package shipped with protoc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WellKnownType {
+    pub name: &'static str,
+    pub import_path: &'static str,
+    pub description: &'static str,
+}
+
+impl WellKnownType {
+    pub fn full_name(&self) -> String {
+        format!("google.protobuf.{}", self.name)
+    }
+}
+
+const fn wkt(
+    name: &'static str,
+    import_path: &'static str,
+    description: &'static str,
+) -> WellKnownType {
+    WellKnownType {
+        name,
+        import_path,
+        description,
+    }
+}
+
+const WRAPPERS: &str = "google/protobuf/wrappers.proto";
+const STRUCT: &str = "google/protobuf/struct.proto";
+
+pub const WELL_KNOWN_TYPES: &[WellKnownType] = &[
+    wkt(
+        "Any",
+        "google/protobuf/any.proto",
+        "An arbitrary serialized message along with a `type_url` that identifies its type",
+    ),
+    wkt(
+        "Duration",
+        "google/protobuf/duration.proto",
+        "A signed, fixed-length span of time represented as `seconds` and `nanos` (nanosecond resolution)",
+    ),
+    wkt(
+        "Timestamp",
+        "google/protobuf/timestamp.proto",
+        "A point in time independent of any time zone, represented as `seconds` and `nanos` since the Unix epoch in UTC",
+    ),
+    wkt(
+        "Empty",
+        "google/protobuf/empty.proto",
+        "An empty message, typically used as the request or response of an RPC",
+    ),
+    wkt(
+        "FieldMask",
+        "google/protobuf/field_mask.proto",
+        "A set of symbolic field `paths`, used to select a subset of fields for reads or updates",
+    ),
+    wkt(
+        "Struct",
+        STRUCT,
+        "A structured value mapping field names to dynamically typed `Value`s, like a JSON object",
+    ),
+    wkt(
+        "Value",
+        STRUCT,
+        "A dynamically typed value: null, number, string, bool, `Struct`, or `ListValue`",
+    ),
+    wkt("ListValue", STRUCT, "A repeated field of dynamically typed `Value`s, like a JSON array"),
+    wkt("NullValue", STRUCT, "A singleton enum representing the JSON `null` value"),
+    wkt("DoubleValue", WRAPPERS, "Wrapper message for `double`"),
+    wkt("FloatValue", WRAPPERS, "Wrapper message for `float`"),
+    wkt("Int64Value", WRAPPERS, "Wrapper message for `int64`"),
+    wkt("UInt64Value", WRAPPERS, "Wrapper message for `uint64`"),
+    wkt("Int32Value", WRAPPERS, "Wrapper message for `int32`"),
+    wkt("UInt32Value", WRAPPERS, "Wrapper message for `uint32`"),
+    wkt("BoolValue", WRAPPERS, "Wrapper message for `bool`"),
+    wkt("StringValue", WRAPPERS, "Wrapper message for `string`"),
+    wkt("BytesValue", WRAPPERS, "Wrapper message for `bytes`"),
+];
+
+/// Looks up a well-known type by its simple name, e.g. `Timestamp`.
+pub fn lookup(name: &str) -> Option<&'static WellKnownType> {
+    WELL_KNOWN_TYPES.iter().find(|wkt| wkt.name == name)
+}
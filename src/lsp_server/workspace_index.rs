@@ -0,0 +1,113 @@
+use crate::parser::{parse_proto, ProtoFile};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::Url;
+
+/// Parsed `.proto` files from the workspace, including ones that aren't
+/// open in the editor. Kept up to date from `workspace/didChangeWatchedFiles`.
+#[derive(Default)]
+pub struct WorkspaceIndex {
+    files: HashMap<Url, ProtoFile>,
+}
+
+impl WorkspaceIndex {
+    pub fn new() -> Self {
+        Self {
+            files: HashMap::new(),
+        }
+    }
+
+    /// Re-parses `content` for `uri`. Files that no longer parse are dropped
+    /// until they are fixed.
+    pub fn update(&mut self, uri: Url, content: &str) {
+        match parse_proto(content) {
+            Ok(proto_file) => {
+                self.files.insert(uri, proto_file);
+            }
+            Err(_) => self.remove(&uri),
+        }
+    }
+
+    pub fn remove(&mut self, uri: &Url) {
+        self.files.remove(uri);
+    }
+
+    pub fn get_parsed(&self, uri: &Url) -> Option<&ProtoFile> {
+        self.files.get(uri)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&Url, &ProtoFile)> {
+        self.files.iter()
+    }
+}
+
+/// Finds the `.proto` files under `root`, skipping hidden directories such
+/// as `.git`. Unreadable directories are skipped too.
+pub fn find_proto_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(path);
+                }
+            } else if path.extension().is_some_and(|ext| ext == "proto") {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_lifecycle() {
+        let uri = Url::parse("file:///types.proto").unwrap();
+        let mut index = WorkspaceIndex::new();
+
+        index.update(uri.clone(), "message Address {}");
+        assert!(index.get_parsed(&uri).is_some());
+
+        // A file that stops parsing is dropped rather than kept stale
+        index.update(uri.clone(), "message Address {");
+        assert!(index.get_parsed(&uri).is_none());
+
+        index.update(uri.clone(), "message Address {}");
+        index.remove(&uri);
+        assert_eq!(index.files().count(), 0);
+    }
+
+    #[test]
+    fn test_find_proto_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("api/v1")).unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join("api/v1/service.proto"), "").unwrap();
+        std::fs::write(dir.path().join("types.proto"), "").unwrap();
+        std::fs::write(dir.path().join("README.md"), "").unwrap();
+        std::fs::write(dir.path().join(".git/stale.proto"), "").unwrap();
+
+        assert_eq!(
+            find_proto_files(dir.path()),
+            [
+                dir.path().join("api/v1/service.proto"),
+                dir.path().join("types.proto"),
+            ]
+        );
+    }
+}
@@ -1,19 +1,249 @@
+use protobuf_edition_lsp::lsp_server::handlers::compute_diagnostics;
 use protobuf_edition_lsp::lsp_server::ProtobufLanguageServer;
+use protobuf_edition_lsp::parser::format_proto;
+use serde::Serialize;
+use std::io::Read;
+use std::process::ExitCode;
+use tokio::net::TcpListener;
+use tower_lsp::lsp_types::{DiagnosticSeverity, NumberOrString};
 use tower_lsp::{LspService, Server};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+const USAGE: &str = "Usage: protobuf-edition-lsp [--stdio | --socket <port> | --check <file|-> [--format text|json] | --format <file> [--stdout]]";
+
+/// Indentation used by `--format`, matching the editor default of two spaces.
+const FORMAT_INDENT: &str = "  ";
+
+enum Transport {
+    Stdio,
+    /// Listen on `127.0.0.1:<port>` and serve the first client that connects.
+    Socket(u16),
+}
+
+/// How `--check` prints diagnostics.
+enum CheckOutput {
+    /// `path:line:column: severity: message` lines on stderr.
+    Text,
+    /// A JSON array of diagnostics on stdout.
+    Json,
+}
+
+enum Command {
+    Serve(Transport),
+    Check { path: String, output: CheckOutput },
+    Format { path: String, to_stdout: bool },
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args {
+        [] => Ok(Command::Serve(Transport::Stdio)),
+        [flag] if flag == "--stdio" => Ok(Command::Serve(Transport::Stdio)),
+        [flag, port] if flag == "--socket" => port
+            .parse()
+            .map(|port| Command::Serve(Transport::Socket(port)))
+            .map_err(|_| format!("invalid port '{port}'\n{USAGE}")),
+        [flag, path] if flag == "--check" => Ok(Command::Check {
+            path: path.clone(),
+            output: CheckOutput::Text,
+        }),
+        [flag, path, format, output] if flag == "--check" && format == "--format" => {
+            let output = match output.as_str() {
+                "text" => CheckOutput::Text,
+                "json" => CheckOutput::Json,
+                _ => return Err(format!("unknown output format '{output}'\n{USAGE}")),
+            };
+            Ok(Command::Check {
+                path: path.clone(),
+                output,
+            })
+        }
+        [flag, path] if flag == "--format" => Ok(Command::Format {
+            path: path.clone(),
+            to_stdout: false,
+        }),
+        [flag, path, stdout] if flag == "--format" && stdout == "--stdout" => Ok(Command::Format {
+            path: path.clone(),
+            to_stdout: true,
+        }),
+        _ => Err(USAGE.to_string()),
+    }
+}
 
 #[tokio::main]
-async fn main() {
-    // Initialize logging
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match parse_args(&args) {
+        Ok(Command::Serve(transport)) => match serve(transport).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        },
+        Ok(Command::Check { path, output }) => check(&path, output),
+        Ok(Command::Format { path, to_stdout }) => format(&path, to_stdout),
+        Err(usage) => {
+            eprintln!("{usage}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+async fn serve(transport: Transport) -> std::io::Result<()> {
+    // Initialize logging. `RUST_LOG=protobuf_edition_lsp=debug` also logs
+    // how long each analysis and request took when its span closes.
     tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_span_events(FmtSpan::CLOSE)
         .with_ansi(false)
         .with_writer(std::io::stderr)
         .init();
 
     // Create the LSP service
-    let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
     let (service, socket) = LspService::new(ProtobufLanguageServer::new);
 
     // Run the server
-    Server::new(stdin, stdout, socket).serve(service).await;
+    match transport {
+        Transport::Stdio => {
+            let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+            Server::new(stdin, stdout, socket).serve(service).await;
+        }
+        Transport::Socket(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+            tracing::info!("Listening on {}", listener.local_addr()?);
+
+            let (stream, peer) = listener.accept().await?;
+            tracing::info!("Accepted connection from {peer}");
+
+            let (read, write) = tokio::io::split(stream);
+            Server::new(read, write, socket).serve(service).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// A diagnostic as printed by `--check --format json`, with 1-based
+/// positions like the text output.
+#[derive(Serialize)]
+struct CheckDiagnostic<'a> {
+    file: &'a str,
+    line: u32,
+    column: u32,
+    severity: &'static str,
+    code: Option<&'a str>,
+    message: &'a str,
+}
+
+/// Lints a single file (or stdin for `-`), printing diagnostics as
+/// `path:line:column: severity: message` or as JSON. Exits with 1 if any
+/// error was found.
+fn check(path: &str, output: CheckOutput) -> ExitCode {
+    let content = if path == "-" {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .map(|_| content)
+    } else {
+        std::fs::read_to_string(path)
+    };
+
+    let content = match content {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let diagnostics = compute_diagnostics(&content);
+    let mut has_errors = false;
+    let mut reported = Vec::new();
+
+    for diagnostic in &diagnostics {
+        let severity = match diagnostic.severity {
+            Some(DiagnosticSeverity::WARNING) => "warning",
+            Some(DiagnosticSeverity::INFORMATION) => "info",
+            Some(DiagnosticSeverity::HINT) => "hint",
+            _ => {
+                has_errors = true;
+                "error"
+            }
+        };
+
+        reported.push(CheckDiagnostic {
+            file: path,
+            line: diagnostic.range.start.line + 1,
+            column: diagnostic.range.start.character + 1,
+            severity,
+            code: match &diagnostic.code {
+                Some(NumberOrString::String(code)) => Some(code),
+                _ => None,
+            },
+            message: &diagnostic.message,
+        });
+    }
+
+    match output {
+        CheckOutput::Text => {
+            for diagnostic in &reported {
+                eprintln!(
+                    "{}:{}:{}: {}: {}",
+                    diagnostic.file,
+                    diagnostic.line,
+                    diagnostic.column,
+                    diagnostic.severity,
+                    diagnostic.message
+                );
+            }
+        }
+        CheckOutput::Json => match serde_json::to_string_pretty(&reported) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::from(2);
+            }
+        },
+    }
+
+    if has_errors {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Formats a file in place, or prints the result with `--stdout`. Files that
+/// don't parse are left untouched and exit with 1.
+fn format(path: &str, to_stdout: bool) -> ExitCode {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let formatted = match format_proto(&content, FORMAT_INDENT) {
+        Ok(formatted) => formatted,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if to_stdout {
+        print!("{formatted}");
+    } else if formatted != content {
+        if let Err(e) = std::fs::write(path, formatted) {
+            eprintln!("{path}: {e}");
+            return ExitCode::from(2);
+        }
+    }
+
+    ExitCode::SUCCESS
 }
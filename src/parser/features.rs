@@ -0,0 +1,112 @@
+use super::*;
+
+const FEATURES_PREFIX: &str = "features.";
+
+/// The edition features this parser knows, with the values each accepts.
+pub const FEATURE_VALUES: &[(&str, &[&str])] = &[
+    (
+        "field_presence",
+        &["EXPLICIT", "IMPLICIT", "LEGACY_REQUIRED"],
+    ),
+    ("enum_type", &["OPEN", "CLOSED"]),
+    ("repeated_field_encoding", &["PACKED", "EXPANDED"]),
+    ("utf8_validation", &["VERIFY", "NONE"]),
+    ("message_encoding", &["LENGTH_PREFIXED", "DELIMITED"]),
+    ("json_format", &["ALLOW", "LEGACY_BEST_EFFORT"]),
+];
+
+/// The effective edition feature values for a file, message, or field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureSet {
+    pub field_presence: String,
+    pub enum_type: String,
+    pub repeated_field_encoding: String,
+    pub utf8_validation: String,
+    pub message_encoding: String,
+    pub json_format: String,
+}
+
+impl FeatureSet {
+    /// Returns the defaults defined by the given edition, if it is known.
+    pub fn edition_defaults(edition: &str) -> Option<Self> {
+        match edition {
+            "2023" => Some(Self {
+                field_presence: "EXPLICIT".to_string(),
+                enum_type: "OPEN".to_string(),
+                repeated_field_encoding: "PACKED".to_string(),
+                utf8_validation: "VERIFY".to_string(),
+                message_encoding: "LENGTH_PREFIXED".to_string(),
+                json_format: "ALLOW".to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Overrides features with any `features.*` entries in `options`.
+    pub fn apply_options(&mut self, options: &HashMap<String, OptionValue>) {
+        for (name, value) in options {
+            self.apply_option(name, value);
+        }
+    }
+
+    fn apply_option(&mut self, name: &str, value: &OptionValue) {
+        let (Some(feature), OptionValue::Identifier(value)) =
+            (name.strip_prefix(FEATURES_PREFIX), value)
+        else {
+            return;
+        };
+
+        let slot = match feature {
+            "field_presence" => &mut self.field_presence,
+            "enum_type" => &mut self.enum_type,
+            "repeated_field_encoding" => &mut self.repeated_field_encoding,
+            "utf8_validation" => &mut self.utf8_validation,
+            "message_encoding" => &mut self.message_encoding,
+            "json_format" => &mut self.json_format,
+            _ => return,
+        };
+        *slot = value.clone();
+    }
+
+    /// Feature names paired with their values, in declaration order.
+    pub fn entries(&self) -> [(&'static str, &str); 6] {
+        [
+            ("field_presence", &self.field_presence),
+            ("enum_type", &self.enum_type),
+            ("repeated_field_encoding", &self.repeated_field_encoding),
+            ("utf8_validation", &self.utf8_validation),
+            ("message_encoding", &self.message_encoding),
+            ("json_format", &self.json_format),
+        ]
+    }
+}
+
+/// Resolves the file-level feature set: edition defaults plus file options.
+pub fn resolve_file_features(proto_file: &ProtoFile) -> Option<FeatureSet> {
+    let mut features = FeatureSet::edition_defaults(proto_file.edition.as_deref()?)?;
+
+    for statement in &proto_file.statements {
+        if let Statement::Option { name, value } = &statement.node {
+            features.apply_option(name, value);
+        }
+    }
+
+    Some(features)
+}
+
+/// Resolves the feature set of `field`, applying overrides from the file,
+/// each enclosing message (outermost first), and the field itself.
+pub fn resolve_field_features(
+    proto_file: &ProtoFile,
+    enclosing_messages: &[&Message],
+    field: &Field,
+) -> Option<FeatureSet> {
+    let mut features = resolve_file_features(proto_file)?;
+
+    for message in enclosing_messages {
+        features.apply_options(&message.options);
+    }
+    features.apply_options(&field.options);
+
+    Some(features)
+}
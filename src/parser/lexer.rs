@@ -1,4 +1,4 @@
-use super::ParseError;
+use super::{ParseError, Position, Span};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -35,31 +35,106 @@ pub enum Token {
     RightParen,
     LeftBracket,
     RightBracket,
+    LeftAngle,
+    RightAngle,
     Semicolon,
     Comma,
     Equals,
     Dot,
+    Colon,
 
     // End of file
     Eof,
 }
 
-pub struct Lexer {
-    input: Vec<char>,
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Spanned<T> {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub node: T,
+    pub span: Span,
+}
+
+/// Lexes the whole input, returning every token up to (but not including) `Eof`.
+pub fn tokenize(input: &str) -> Result<Vec<Spanned<Token>>, ParseError> {
+    tokenize_with_comments(input).map(|(tokens, _)| tokens)
+}
+
+/// Like [`tokenize`], but also returns the comments between tokens.
+pub fn tokenize_with_comments(
+    input: &str,
+) -> Result<(Vec<Spanned<Token>>, Vec<Comment>), ParseError> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+
+    loop {
+        let token = lexer.next_spanned_token()?;
+        if token.node == Token::Eof {
+            return Ok((tokens, lexer.comments));
+        }
+        tokens.push(token);
+    }
+}
+
+/// A `//` or `/* */` comment, with its delimiters stripped from `text`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Clone)]
+pub struct Lexer<'a> {
+    input: &'a str,
+    /// Byte offset of the next unread char.
     position: usize,
+    line: usize,
+    column: usize,
+    /// Comments skipped so far, in source order.
+    comments: Vec<Comment>,
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
         Self {
-            input: input.chars().collect(),
+            input,
             position: 0,
+            line: 0,
+            column: 0,
+            comments: Vec::new(),
         }
     }
 
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    #[cfg(test)]
     pub fn next_token(&mut self) -> Result<Token, ParseError> {
-        self.skip_whitespace_and_comments();
+        self.next_spanned_token().map(|spanned| spanned.node)
+    }
+
+    pub fn next_spanned_token(&mut self) -> Result<Spanned<Token>, ParseError> {
+        self.skip_whitespace_and_comments()?;
+
+        let start = self.current_position();
+        let node = self.read_token()?;
+        let end = self.current_position();
+
+        Ok(Spanned {
+            node,
+            span: Span { start, end },
+        })
+    }
+
+    pub(crate) fn current_position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
 
+    fn read_token(&mut self) -> Result<Token, ParseError> {
         if self.position >= self.input.len() {
             return Ok(Token::Eof);
         }
@@ -92,6 +167,14 @@ impl Lexer {
                 self.advance();
                 Ok(Token::RightBracket)
             }
+            '<' => {
+                self.advance();
+                Ok(Token::LeftAngle)
+            }
+            '>' => {
+                self.advance();
+                Ok(Token::RightAngle)
+            }
             ';' => {
                 self.advance();
                 Ok(Token::Semicolon)
@@ -108,29 +191,39 @@ impl Lexer {
                 self.advance();
                 Ok(Token::Dot)
             }
+            ':' => {
+                self.advance();
+                Ok(Token::Colon)
+            }
             _ if ch.is_alphabetic() || ch == '_' => self.read_identifier(),
-            _ if ch.is_numeric() || ch == '-' => self.read_number(),
+            _ if ch.is_numeric() || ch == '-' || ch == '+' => self.read_number(),
             _ => Err(ParseError::UnexpectedToken(ch.to_string())),
         }
     }
 
+    /// The char at the cursor; callers check for the end of input first.
     fn current_char(&self) -> char {
-        self.input[self.position]
+        self.input[self.position..].chars().next().unwrap_or('\0')
     }
 
     fn peek_char(&self) -> Option<char> {
-        if self.position + 1 < self.input.len() {
-            Some(self.input[self.position + 1])
-        } else {
-            None
-        }
+        self.input[self.position..].chars().nth(1)
     }
 
     fn advance(&mut self) {
-        self.position += 1;
+        if let Some(ch) = self.input[self.position..].chars().next() {
+            // Columns are counted in UTF-16 code units to match LSP positions
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += ch.len_utf16();
+            }
+            self.position += ch.len_utf8();
+        }
     }
 
-    fn skip_whitespace_and_comments(&mut self) {
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), ParseError> {
         while self.position < self.input.len() {
             let ch = self.current_char();
 
@@ -143,20 +236,36 @@ impl Lexer {
                 if let Some(next_ch) = self.peek_char() {
                     if next_ch == '/' {
                         // Single-line comment
+                        let start = self.current_position();
                         self.advance();
                         self.advance();
-                        while self.position < self.input.len() && self.current_char() != '\n' {
+                        let text_start = self.position;
+                        // The comment ends before a CRLF line break, not on its `\r`
+                        while self.position < self.input.len()
+                            && !self.input[self.position..].starts_with(['\n', '\r'])
+                        {
                             self.advance();
                         }
+                        self.push_comment(start, text_start, self.position);
                         continue;
                     } else if next_ch == '*' {
                         // Multi-line comment
+                        let start = self.current_position();
                         self.advance();
                         self.advance();
-                        while self.position + 1 < self.input.len() {
+                        let text_start = self.position;
+                        loop {
+                            if self.position + 1 >= self.input.len() {
+                                return Err(ParseError::UnterminatedComment {
+                                    line: start.line + 1,
+                                    column: start.column + 1,
+                                });
+                            }
                             if self.current_char() == '*' && self.peek_char() == Some('/') {
+                                let text_end = self.position;
                                 self.advance();
                                 self.advance();
+                                self.push_comment(start, text_start, text_end);
                                 break;
                             }
                             self.advance();
@@ -168,6 +277,22 @@ impl Lexer {
 
             break;
         }
+
+        Ok(())
+    }
+
+    /// Records a comment ending at the current position whose text (without
+    /// delimiters) spans `input[text_start..text_end]`.
+    fn push_comment(&mut self, start: Position, text_start: usize, text_end: usize) {
+        self.comments.push(Comment {
+            text: self.input[text_start..text_end]
+                .trim()
+                .replace("\r\n", "\n"),
+            span: Span {
+                start,
+                end: self.current_position(),
+            },
+        });
     }
 
     fn read_string(&mut self) -> Result<Token, ParseError> {
@@ -208,19 +333,19 @@ impl Lexer {
     }
 
     fn read_identifier(&mut self) -> Result<Token, ParseError> {
-        let mut identifier = String::new();
+        let start = self.position;
 
         while self.position < self.input.len() {
             let ch = self.current_char();
             if ch.is_alphanumeric() || ch == '_' {
-                identifier.push(ch);
                 self.advance();
             } else {
                 break;
             }
         }
 
-        let token = match identifier.as_str() {
+        let identifier = &self.input[start..self.position];
+        let token = match identifier {
             "syntax" => Token::Syntax,
             "edition" => Token::Edition,
             "package" => Token::Package,
@@ -240,7 +365,7 @@ impl Lexer {
             "option" => Token::Option,
             "true" => Token::True,
             "false" => Token::False,
-            _ => Token::Identifier(identifier),
+            _ => Token::Identifier(identifier.to_string()),
         };
 
         Ok(token)
@@ -249,9 +374,40 @@ impl Lexer {
     fn read_number(&mut self) -> Result<Token, ParseError> {
         let mut number = String::new();
 
-        if self.current_char() == '-' {
-            number.push('-');
-            self.advance();
+        // A leading `+` is accepted but not kept in the literal
+        match self.current_char() {
+            '-' => {
+                number.push('-');
+                self.advance();
+            }
+            '+' => {
+                // Unlike `-`, `+` must be followed by digits: `+inf` isn't a number
+                let rest = &self.input[self.position + 1..];
+                let mut chars = rest.chars();
+                let starts_digits = match chars.next() {
+                    Some('.') => chars.next().is_some_and(|ch| ch.is_ascii_digit()),
+                    Some(ch) => ch.is_ascii_digit(),
+                    None => false,
+                };
+                if !starts_digits {
+                    let word: String = rest
+                        .chars()
+                        .take_while(|ch| ch.is_alphanumeric() || *ch == '_')
+                        .collect();
+                    return Err(ParseError::InvalidNumber(format!("+{word}")));
+                }
+                self.advance();
+            }
+            _ => {}
+        }
+
+        // `-inf` and `-nan`; the parser decides whether the word is valid
+        if self.position < self.input.len() && self.current_char().is_alphabetic() {
+            while self.position < self.input.len() && self.current_char().is_alphanumeric() {
+                number.push(self.current_char());
+                self.advance();
+            }
+            return Ok(Token::NumberLiteral(number));
         }
 
         while self.position < self.input.len() {
@@ -339,4 +495,90 @@ message Test {}
             Token::Identifier("Test".to_string())
         );
     }
+
+    #[test]
+    fn test_token_spans() {
+        let mut lexer = Lexer::new("message Person {\n  string name = 1;\n}");
+
+        let message = lexer.next_spanned_token().unwrap();
+        assert_eq!(message.node, Token::Message);
+        assert_eq!(
+            message.span,
+            Span {
+                start: Position { line: 0, column: 0 },
+                end: Position { line: 0, column: 7 },
+            }
+        );
+
+        lexer.next_spanned_token().unwrap(); // Person
+        lexer.next_spanned_token().unwrap(); // {
+
+        let field_type = lexer.next_spanned_token().unwrap();
+        assert_eq!(field_type.node, Token::Identifier("string".to_string()));
+        assert_eq!(field_type.span.start, Position { line: 1, column: 2 });
+        assert_eq!(field_type.span.end, Position { line: 1, column: 8 });
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut lexer = Lexer::new("message Test {}\n  /* never closed");
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Message);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("Test".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::LeftBrace);
+        assert_eq!(lexer.next_token().unwrap(), Token::RightBrace);
+        assert!(matches!(
+            lexer.next_token(),
+            Err(ParseError::UnterminatedComment { line: 2, column: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_closed_block_comment() {
+        let mut lexer = Lexer::new("/* closed */ message /**/");
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Message);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_signed_numbers() {
+        let mut lexer = Lexer::new("+5 -3");
+
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::NumberLiteral("5".to_string())
+        );
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::NumberLiteral("-3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_multibyte_input() {
+        let mut lexer = Lexer::new("// 日本語 😀\nmessage Ünïcode { string 名前 = 1; }");
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Message);
+        let name = lexer.next_spanned_token().unwrap();
+        assert_eq!(name.node, Token::Identifier("Ünïcode".to_string()));
+        assert_eq!(
+            name.span.end,
+            Position {
+                line: 1,
+                column: 15
+            }
+        );
+
+        lexer.next_token().unwrap(); // {
+        lexer.next_token().unwrap(); // string
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("名前".to_string())
+        );
+        assert_eq!(lexer.comments()[0].text, "日本語 😀");
+    }
 }
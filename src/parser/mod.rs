@@ -2,25 +2,66 @@ use anyhow::Result;
 use std::collections::HashMap;
 use thiserror::Error;
 
+mod features;
 mod lexer;
 mod parser_impl;
+mod printer;
 mod validator;
 
-pub use parser_impl::parse_proto;
-pub use validator::{validate_proto, ValidationError};
+pub use features::{resolve_field_features, resolve_file_features, FeatureSet, FEATURE_VALUES};
+pub use lexer::{tokenize, tokenize_with_comments, Comment, Spanned, Token};
+pub use parser_impl::{
+    parse_proto, parse_proto_with_max_depth, DEFAULT_MAX_NESTING_DEPTH, MAX_FIELD_NUMBER,
+};
+pub(crate) use printer::line_break;
+pub use printer::{format_declaration, format_proto};
+pub use validator::{
+    suggest_scalar_type, validate_proto, validate_proto_with_max_depth, RelatedLocation,
+    Replacement, Severity, ValidationError,
+};
+
+/// A zero-based location in the source. Columns are counted in UTF-16 code units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ProtoFile {
     pub syntax: Option<String>,
     pub edition: Option<String>,
-    pub statements: Vec<Statement>,
+    /// Span of the `syntax` or `edition` declaration.
+    pub syntax_span: Option<Span>,
+    /// Span of the declaration's string literal, including its quotes.
+    pub syntax_value_span: Option<Span>,
+    /// Top-level statements, each with the span it covers.
+    pub statements: Vec<Spanned<Statement>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Statement {
     Package(String),
     Import {
         path: String,
+        /// Span of the path string literal, including its quotes.
+        path_span: Span,
         public: bool,
         weak: bool,
     },
@@ -34,74 +75,269 @@ pub enum Statement {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Message {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub fields: Vec<Field>,
     pub oneofs: Vec<Oneof>,
     pub nested_messages: Vec<Message>,
     pub nested_enums: Vec<Enum>,
+    pub reserved_ranges: Vec<NumberRange>,
+    pub reserved_names: Vec<ReservedName>,
+    pub extension_ranges: Vec<NumberRange>,
     pub options: HashMap<String, OptionValue>,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
+}
+
+/// An inclusive range of numbers, e.g. `9 to 11` in a `reserved` statement.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NumberRange {
+    pub start: i64,
+    pub end: i64,
+    pub span: Span,
+}
+
+impl NumberRange {
+    pub fn contains(&self, number: i64) -> bool {
+        self.start <= number && number <= self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ReservedName {
+    pub name: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Field {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub field_type: String,
+    pub type_span: Span,
+    /// Key and value types when the field is declared as `map<K, V>`.
+    pub map_type: Option<MapType>,
     pub number: u32,
     pub label: Option<FieldLabel>,
+    pub label_span: Option<Span>,
     pub options: HashMap<String, OptionValue>,
+    /// Names in the bracketed option list in source order, including any
+    /// that are repeated (`options` keeps only the last value).
+    pub option_names: Vec<OptionName>,
+    /// Span of the bracketed option list, including the brackets.
+    pub options_span: Option<Span>,
+    /// Whether the field was declared with proto2 `group` syntax.
+    pub is_group: bool,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
+    /// A comment on the same line after the declaration.
+    pub trailing_comments: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OptionName {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MapType {
+    pub key_type: String,
+    pub key_span: Span,
+    pub value_type: String,
+    pub value_span: Span,
+}
+
+impl MapType {
+    /// Whether `field_type` is spelled as a map type, e.g. `map<string, int32>`.
+    pub fn is_map(field_type: &str) -> bool {
+        field_type.starts_with("map<")
+    }
+}
+
+impl Field {
+    /// The field's JSON name: the explicit `json_name` option if present,
+    /// otherwise the lowerCamelCase form of the field name.
+    pub fn json_name(&self) -> String {
+        match self.options.get("json_name") {
+            Some(OptionValue::String(json_name)) => json_name.clone(),
+            _ => default_json_name(&self.name),
+        }
+    }
+}
+
+/// The built-in scalar field types.
+pub const SCALAR_TYPES: &[&str] = &[
+    "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32",
+    "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
+];
+
+/// Editions this parser understands.
+pub const SUPPORTED_EDITIONS: &[&str] = &["2023"];
+
+/// Values accepted by a `syntax` declaration.
+pub const SUPPORTED_SYNTAXES: &[&str] = &["proto2", "proto3"];
+
+/// Values accepted by the `idempotency_level` method option.
+pub const IDEMPOTENCY_LEVELS: &[&str] = &["IDEMPOTENCY_UNKNOWN", "NO_SIDE_EFFECTS", "IDEMPOTENT"];
+
+/// Whether `name` is a valid protobuf identifier: a letter or underscore
+/// followed by letters, digits, or underscores.
+pub fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Derives the default JSON name the way protoc does: underscores are
+/// dropped and the letter following each one is capitalized.
+pub fn default_json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut capitalize_next = false;
+
+    for ch in name.chars() {
+        if ch == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            json_name.extend(ch.to_uppercase());
+            capitalize_next = false;
+        } else {
+            json_name.push(ch);
+        }
+    }
+
+    json_name
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum FieldLabel {
     Optional,
     Required,
     Repeated,
 }
 
+impl FieldLabel {
+    /// The label as written in source.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            FieldLabel::Optional => "optional",
+            FieldLabel::Required => "required",
+            FieldLabel::Repeated => "repeated",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Oneof {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub fields: Vec<Field>,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Enum {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub values: Vec<EnumValue>,
     pub options: HashMap<String, OptionValue>,
+    /// Names of the `option` statements in source order, including repeats.
+    pub option_names: Vec<OptionName>,
+    pub reserved_ranges: Vec<NumberRange>,
+    pub reserved_names: Vec<ReservedName>,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct EnumValue {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub number: i32,
     pub options: HashMap<String, OptionValue>,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
+    /// A comment on the same line after the declaration.
+    pub trailing_comments: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Service {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub methods: Vec<Method>,
     pub options: HashMap<String, OptionValue>,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Method {
     pub name: String,
+    pub span: Span,
+    pub name_span: Span,
     pub request_type: String,
+    pub request_type_span: Span,
     pub response_type: String,
+    pub response_type_span: Span,
     pub client_streaming: bool,
     pub server_streaming: bool,
     pub options: HashMap<String, OptionValue>,
+    /// The comment lines directly above the declaration, joined by `\n`.
+    pub leading_comments: Option<String>,
+    /// A comment on the same line after the declaration.
+    pub trailing_comments: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum OptionValue {
     String(String),
     Number(f64),
     Bool(bool),
     Identifier(String),
+    /// A text-format message literal, `{ key: value ... }`, as its fields in
+    /// source order. List values (`key: [a, b]`) become one entry per element.
+    Message(Vec<LiteralField>),
+}
+
+/// One `name: value` entry of a message literal.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LiteralField {
+    pub name: String,
+    pub value: OptionValue,
+    /// From the start of the name to the end of the value.
+    pub span: Span,
+}
+
+/// Parses `input` and serializes the resulting AST as JSON, so tools
+/// written in other languages can use it without their own parser.
+#[cfg(feature = "serde")]
+pub fn parse_to_json(input: &str) -> Result<String> {
+    Ok(serde_json::to_string(&parse_proto(input)?)?)
 }
 
 #[derive(Error, Debug, Clone)]
@@ -118,9 +354,21 @@ pub enum ParseError {
     #[error("Unterminated string")]
     UnterminatedString,
 
+    #[error("Unterminated block comment starting at line {line}, column {column}")]
+    UnterminatedComment { line: usize, column: usize },
+
     #[error("Invalid number: {0}")]
     InvalidNumber(String),
 
     #[error("End of file reached unexpectedly")]
     UnexpectedEof,
 }
+
+/// A `ParseError` with where it was found: the offending token, or the
+/// point where the lexer gave up. This is what `parse_proto` fails with.
+#[derive(Error, Debug, Clone)]
+#[error("{error}")]
+pub struct PositionedParseError {
+    pub error: ParseError,
+    pub span: Span,
+}
@@ -1,90 +1,212 @@
 use super::*;
-use crate::parser::lexer::{Lexer, Token};
+use crate::parser::lexer::{Lexer, Spanned, Token};
 use std::collections::HashMap;
+use std::num::IntErrorKind;
+
+/// Largest valid field number, used for `to max` in message ranges.
+pub const MAX_FIELD_NUMBER: i64 = 536_870_911;
+
+/// A field's options, their names in source order, and the span of the
+/// bracketed list.
+type FieldOptions = (HashMap<String, OptionValue>, Vec<OptionName>, Option<Span>);
+
+/// How deeply messages and message literals may nest before
+/// [`parse_proto`] gives up, so pathological input can't overflow the stack.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 100;
 
 pub fn parse_proto(input: &str) -> Result<ProtoFile> {
-    let mut parser = Parser::new(input);
-    parser.parse()
+    parse_proto_with_max_depth(input, DEFAULT_MAX_NESTING_DEPTH)
+}
+
+/// Like [`parse_proto`], but fails once messages or message literals nest
+/// more than `max_depth` levels deep.
+pub fn parse_proto_with_max_depth(input: &str, max_depth: usize) -> Result<ProtoFile> {
+    let mut parser = Parser::new(input, max_depth)?;
+    parser.parse().map_err(|error| parser.locate(error))
 }
 
-struct Parser {
-    lexer: Lexer,
+struct Parser<'a> {
+    lexer: Lexer<'a>,
     current_token: Token,
+    current_span: Span,
+    previous_end: Position,
+    /// Whether the file is proto2, declared or by default without a
+    /// `syntax` or `edition`. There `group` is a keyword rather than an
+    /// ordinary type name.
+    proto2: bool,
+    /// Messages and message literals currently open.
+    depth: usize,
+    max_depth: usize,
 }
 
-impl Parser {
-    fn new(input: &str) -> Self {
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, max_depth: usize) -> Result<Self> {
         let mut lexer = Lexer::new(input);
-        let current_token = lexer.next_token().unwrap_or(Token::Eof);
-        Self {
+        let Spanned { node, span } = Self::next_token(&mut lexer)?;
+        Ok(Self {
             lexer,
-            current_token,
-        }
+            current_token: node,
+            current_span: span,
+            previous_end: Position::default(),
+            proto2: true,
+            depth: 0,
+            max_depth,
+        })
     }
 
     fn parse(&mut self) -> Result<ProtoFile> {
         let mut proto_file = ProtoFile {
             syntax: None,
             edition: None,
+            syntax_span: None,
+            syntax_value_span: None,
             statements: Vec::new(),
         };
 
         while self.current_token != Token::Eof {
-            match &self.current_token {
+            let start = self.current_span.start;
+            let statement = match &self.current_token {
                 Token::Syntax => {
-                    proto_file.syntax = Some(self.parse_syntax()?);
+                    let (syntax, value_span) = self.parse_syntax()?;
+                    self.proto2 = syntax == "proto2";
+                    proto_file.syntax = Some(syntax);
+                    proto_file.syntax_span = Some(self.span_from(start));
+                    proto_file.syntax_value_span = Some(value_span);
+                    None
                 }
                 Token::Edition => {
-                    proto_file.edition = Some(self.parse_edition()?);
-                }
-                Token::Package => {
-                    proto_file
-                        .statements
-                        .push(Statement::Package(self.parse_package()?));
-                }
-                Token::Import => {
-                    proto_file.statements.push(self.parse_import()?);
-                }
-                Token::Message => {
-                    proto_file
-                        .statements
-                        .push(Statement::Message(self.parse_message()?));
-                }
-                Token::Enum => {
-                    proto_file
-                        .statements
-                        .push(Statement::Enum(self.parse_enum()?));
-                }
-                Token::Service => {
-                    proto_file
-                        .statements
-                        .push(Statement::Service(self.parse_service()?));
+                    let (edition, value_span) = self.parse_edition()?;
+                    self.proto2 = false;
+                    proto_file.edition = Some(edition);
+                    proto_file.syntax_span = Some(self.span_from(start));
+                    proto_file.syntax_value_span = Some(value_span);
+                    None
                 }
+                Token::Package => Some(Statement::Package(self.parse_package()?)),
+                Token::Import => Some(self.parse_import()?),
+                Token::Message => Some(Statement::Message(self.parse_message()?)),
+                Token::Enum => Some(Statement::Enum(self.parse_enum()?)),
+                Token::Service => Some(Statement::Service(self.parse_service()?)),
                 Token::Option => {
-                    let (name, value) = self.parse_option()?;
-                    proto_file
-                        .statements
-                        .push(Statement::Option { name, value });
+                    let (OptionName { name, .. }, value) = self.parse_option()?;
+                    Some(Statement::Option { name, value })
                 }
                 Token::Semicolon => {
                     self.advance()?;
+                    None
                 }
+                Token::Stream => return Err(self.stream_outside_rpc()),
                 _ => {
                     return Err(
                         ParseError::UnexpectedToken(format!("{:?}", self.current_token)).into(),
                     );
                 }
+            };
+
+            if let Some(node) = statement {
+                proto_file.statements.push(Spanned {
+                    node,
+                    span: self.span_from(start),
+                });
             }
         }
 
         Ok(proto_file)
     }
 
+    /// Attaches the current token's span to a `ParseError` that doesn't
+    /// have a position yet.
+    /// Reads the next token. Lexer errors point at the character that
+    /// couldn't be read, or span an unterminated comment from its `/*`.
+    fn next_token(lexer: &mut Lexer) -> Result<Spanned<Token>> {
+        lexer.next_spanned_token().map_err(|error| {
+            let end = lexer.current_position();
+            let start = match error {
+                ParseError::UnterminatedComment { line, column } => Position {
+                    line: line - 1,
+                    column: column - 1,
+                },
+                _ => end,
+            };
+            PositionedParseError {
+                error,
+                span: Span { start, end },
+            }
+            .into()
+        })
+    }
+
+    fn locate(&self, error: anyhow::Error) -> anyhow::Error {
+        match error.downcast::<ParseError>() {
+            Ok(error) => PositionedParseError {
+                error,
+                span: self.current_span,
+            }
+            .into(),
+            Err(error) => error,
+        }
+    }
+
     fn advance(&mut self) -> Result<()> {
-        self.current_token = self.lexer.next_token()?;
+        let Spanned { node, span } = Self::next_token(&mut self.lexer)?;
+        self.previous_end = self.current_span.end;
+        self.current_token = node;
+        self.current_span = span;
         Ok(())
     }
 
+    /// The comment following the most recently consumed token on the same
+    /// line, e.g. `// unique id` in `int32 id = 1; // unique id`.
+    fn trailing_comments(&self) -> Option<String> {
+        self.lexer
+            .comments()
+            .iter()
+            .rev()
+            .take_while(|comment| comment.span.start >= self.previous_end)
+            .last()
+            .filter(|comment| comment.span.start.line == self.previous_end.line)
+            .map(|comment| comment.text.clone())
+    }
+
+    /// The block of comments directly above the current token, one line per
+    /// comment line, e.g. the `//` lines documenting a field. A blank line
+    /// between the comments and the token detaches them.
+    fn leading_comments(&self) -> Option<String> {
+        let mut next_line = self.current_span.start.line;
+        let mut block: Vec<&str> = Vec::new();
+
+        for comment in self
+            .lexer
+            .comments()
+            .iter()
+            .rev()
+            .take_while(|comment| comment.span.start >= self.previous_end)
+        {
+            // A comment after the previous token on its line trails that token
+            let trails_previous = self.previous_end != Position::default()
+                && comment.span.start.line == self.previous_end.line;
+            if trails_previous || comment.span.end.line + 1 != next_line {
+                break;
+            }
+            block.push(&comment.text);
+            next_line = comment.span.start.line;
+        }
+
+        if block.is_empty() {
+            return None;
+        }
+        block.reverse();
+        Some(block.join("\n"))
+    }
+
+    /// Span from `start` to the end of the most recently consumed token.
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            start,
+            end: self.previous_end,
+        }
+    }
+
     fn expect(&mut self, expected: Token) -> Result<()> {
         if self.current_token == expected {
             self.advance()?;
@@ -98,7 +220,8 @@ impl Parser {
         }
     }
 
-    fn parse_syntax(&mut self) -> Result<String> {
+    /// Parses `syntax = "...";`, returning the value and its literal's span.
+    fn parse_syntax(&mut self) -> Result<(String, Span)> {
         self.expect(Token::Syntax)?;
         self.expect(Token::Equals)?;
 
@@ -113,12 +236,14 @@ impl Parser {
             }
         };
 
+        let span = self.current_span;
         self.advance()?;
         self.expect(Token::Semicolon)?;
-        Ok(syntax)
+        Ok((syntax, span))
     }
 
-    fn parse_edition(&mut self) -> Result<String> {
+    /// Parses `edition = "...";`, returning the value and its literal's span.
+    fn parse_edition(&mut self) -> Result<(String, Span)> {
         self.expect(Token::Edition)?;
         self.expect(Token::Equals)?;
 
@@ -133,30 +258,24 @@ impl Parser {
             }
         };
 
+        let span = self.current_span;
         self.advance()?;
         self.expect(Token::Semicolon)?;
-        Ok(edition)
+        Ok((edition, span))
     }
 
     fn parse_package(&mut self) -> Result<String> {
         self.expect(Token::Package)?;
 
+        // Malformed names such as `a..b` are kept as written for the validator
         let mut package_name = String::new();
         loop {
             match &self.current_token {
-                Token::Identifier(name) => {
-                    package_name.push_str(name);
-                    self.advance()?;
-
-                    if self.current_token == Token::Dot {
-                        package_name.push('.');
-                        self.advance()?;
-                    } else {
-                        break;
-                    }
-                }
+                Token::Identifier(name) => package_name.push_str(name),
+                Token::Dot => package_name.push('.'),
                 _ => break,
             }
+            self.advance()?;
         }
 
         self.expect(Token::Semicolon)?;
@@ -177,6 +296,7 @@ impl Parser {
             self.advance()?;
         }
 
+        let path_span = self.current_span;
         let path = match &self.current_token {
             Token::StringLiteral(s) => s.clone(),
             _ => {
@@ -191,12 +311,20 @@ impl Parser {
         self.advance()?;
         self.expect(Token::Semicolon)?;
 
-        Ok(Statement::Import { path, public, weak })
+        Ok(Statement::Import {
+            path,
+            path_span,
+            public,
+            weak,
+        })
     }
 
     fn parse_message(&mut self) -> Result<Message> {
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
         self.expect(Token::Message)?;
 
+        let name_span = self.current_span;
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
             _ => {
@@ -209,15 +337,32 @@ impl Parser {
         };
 
         self.advance()?;
+        self.parse_message_body(name, name_span, start, leading_comments)
+    }
+
+    fn parse_message_body(
+        &mut self,
+        name: String,
+        name_span: Span,
+        start: Position,
+        leading_comments: Option<String>,
+    ) -> Result<Message> {
+        self.enter_nested()?;
         self.expect(Token::LeftBrace)?;
 
         let mut message = Message {
             name,
+            span: Span::default(),
+            name_span,
             fields: Vec::new(),
             oneofs: Vec::new(),
             nested_messages: Vec::new(),
             nested_enums: Vec::new(),
+            reserved_ranges: Vec::new(),
+            reserved_names: Vec::new(),
+            extension_ranges: Vec::new(),
             options: HashMap::new(),
+            leading_comments,
         };
 
         while self.current_token != Token::RightBrace {
@@ -231,22 +376,61 @@ impl Parser {
                 Token::Oneof => {
                     message.oneofs.push(self.parse_oneof()?);
                 }
+                // `reserved` is contextual so it stays usable as a name
+                Token::Identifier(_) if self.at_reserved() => {
+                    let (ranges, names) = self.parse_reserved(MAX_FIELD_NUMBER)?;
+                    message.reserved_ranges.extend(ranges);
+                    message.reserved_names.extend(names);
+                }
                 Token::Option => {
-                    let (name, value) = self.parse_option()?;
+                    let (OptionName { name, .. }, value) = self.parse_option()?;
                     message.options.insert(name, value);
                 }
+                // `extensions` is contextual so it stays usable as a name
+                Token::Identifier(_) if self.at_extensions() => {
+                    message.extension_ranges.extend(self.parse_extensions()?);
+                }
                 Token::Optional | Token::Required | Token::Repeated => {
+                    let start = self.current_span.start;
+                    let label_span = self.current_span;
+                    let leading_comments = self.leading_comments();
                     let label = self.parse_field_label()?;
-                    let mut field = self.parse_field()?;
+                    let mut field = if self.at_group() {
+                        let (field, group) = self.parse_group()?;
+                        message.nested_messages.extend(group);
+                        field
+                    } else {
+                        self.parse_field()?
+                    };
+                    if field.map_type.is_some() {
+                        return Err(PositionedParseError {
+                            error: ParseError::InvalidSyntax(format!(
+                                "Map field '{}' cannot be '{}'; map fields are implicitly repeated",
+                                field.name,
+                                label.keyword()
+                            )),
+                            span: label_span,
+                        }
+                        .into());
+                    }
                     field.label = Some(label);
+                    field.label_span = Some(label_span);
+                    field.span.start = start;
+                    field.leading_comments = leading_comments;
                     message.fields.push(field);
                 }
-                Token::Identifier(_) => {
+                Token::Identifier(_) if self.at_group() => {
+                    let (field, group) = self.parse_group()?;
+                    message.nested_messages.extend(group);
+                    message.fields.push(field);
+                }
+                Token::Identifier(_) | Token::Dot => {
                     message.fields.push(self.parse_field()?);
                 }
                 Token::Semicolon => {
                     self.advance()?;
                 }
+                Token::Stream => return Err(self.stream_outside_rpc()),
                 _ => {
                     return Err(
                         ParseError::UnexpectedToken(format!("{:?}", self.current_token)).into(),
@@ -256,9 +440,24 @@ impl Parser {
         }
 
         self.expect(Token::RightBrace)?;
+        message.span = self.span_from(start);
+        self.depth -= 1;
         Ok(message)
     }
 
+    /// Counts one more level of nesting, failing past `max_depth`.
+    fn enter_nested(&mut self) -> Result<()> {
+        if self.depth == self.max_depth {
+            return Err(ParseError::InvalidSyntax(format!(
+                "nesting too deep: more than {} levels",
+                self.max_depth
+            ))
+            .into());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
     fn parse_field_label(&mut self) -> Result<FieldLabel> {
         let label = match &self.current_token {
             Token::Optional => FieldLabel::Optional,
@@ -278,11 +477,17 @@ impl Parser {
     }
 
     fn parse_field(&mut self) -> Result<Field> {
-        let field_type = match &self.current_token {
-            Token::Identifier(t) => t.clone(),
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
+        let (field_type, map_type) = self.parse_field_type()?;
+        let type_span = self.span_from(start);
+
+        let name_span = self.current_span;
+        let name = match &self.current_token {
+            Token::Identifier(n) => n.clone(),
             _ => {
                 return Err(ParseError::Expected {
-                    expected: "field type".to_string(),
+                    expected: "field name".to_string(),
                     found: format!("{:?}", self.current_token),
                 }
                 .into())
@@ -290,12 +495,150 @@ impl Parser {
         };
 
         self.advance()?;
+        self.expect(Token::Equals)?;
+        let number = self.parse_field_number()?;
 
-        let name = match &self.current_token {
-            Token::Identifier(n) => n.clone(),
+        let (options, option_names, options_span) = self.parse_optional_field_options()?;
+        self.expect(Token::Semicolon)?;
+
+        Ok(Field {
+            name,
+            span: self.span_from(start),
+            name_span,
+            field_type,
+            type_span,
+            map_type,
+            number,
+            label: None,
+            label_span: None,
+            options,
+            option_names,
+            options_span,
+            is_group: false,
+            leading_comments,
+            trailing_comments: self.trailing_comments(),
+        })
+    }
+
+    /// Parses a field type, including `map<K, V>`. Map types are returned
+    /// as their normalized spelling along with the key and value types.
+    fn parse_field_type(&mut self) -> Result<(String, Option<MapType>)> {
+        let field_type = self.parse_type_name("field type")?;
+
+        if field_type != "map" || self.current_token != Token::LeftAngle {
+            return Ok((field_type, None));
+        }
+
+        self.advance()?;
+        let key_start = self.current_span.start;
+        let (key_type, _) = self.parse_field_type()?;
+        let key_span = self.span_from(key_start);
+        self.expect(Token::Comma)?;
+        let value_start = self.current_span.start;
+        let (value_type, _) = self.parse_field_type()?;
+        let value_span = self.span_from(value_start);
+        self.expect(Token::RightAngle)?;
+
+        Ok((
+            format!("map<{key_type}, {value_type}>"),
+            Some(MapType {
+                key_type,
+                key_span,
+                value_type,
+                value_span,
+            }),
+        ))
+    }
+
+    /// Parses `reserved 2, 9 to 11, 40 to max;` or `reserved "foo", bar;`.
+    fn parse_reserved(&mut self, max: i64) -> Result<(Vec<NumberRange>, Vec<ReservedName>)> {
+        self.advance()?;
+
+        let mut ranges = Vec::new();
+        let mut names = Vec::new();
+
+        loop {
+            match &self.current_token {
+                Token::NumberLiteral(_) => ranges.push(self.parse_number_range(max)?),
+                // Editions spell reserved names as bare identifiers
+                Token::StringLiteral(name) | Token::Identifier(name) => {
+                    names.push(ReservedName {
+                        name: name.clone(),
+                        span: self.current_span,
+                    });
+                    self.advance()?;
+                }
+                _ => {
+                    return Err(ParseError::Expected {
+                        expected: "reserved field number or name".to_string(),
+                        found: format!("{:?}", self.current_token),
+                    }
+                    .into())
+                }
+            }
+
+            if self.current_token == Token::Comma {
+                self.advance()?;
+            } else {
+                break;
+            }
+        }
+
+        self.expect(Token::Semicolon)?;
+        Ok((ranges, names))
+    }
+
+    /// Parses `extensions 100 to 199, 500 to max [options];`.
+    fn parse_extensions(&mut self) -> Result<Vec<NumberRange>> {
+        self.advance()?;
+
+        let mut ranges = vec![self.parse_number_range(MAX_FIELD_NUMBER)?];
+        while self.current_token == Token::Comma {
+            self.advance()?;
+            ranges.push(self.parse_number_range(MAX_FIELD_NUMBER)?);
+        }
+
+        // Options such as `verification` and `declaration` aren't modelled yet
+        if self.current_token == Token::LeftBracket {
+            self.parse_field_options()?;
+        }
+
+        self.expect(Token::Semicolon)?;
+        Ok(ranges)
+    }
+
+    /// Parses `N`, `N to M`, or `N to max`, where `max` is context dependent.
+    fn parse_number_range(&mut self, max: i64) -> Result<NumberRange> {
+        let start_position = self.current_span.start;
+        let start = self.parse_integer()?;
+
+        let end = if self.current_token == Token::Identifier("to".to_string()) {
+            self.advance()?;
+            if self.current_token == Token::Identifier("max".to_string()) {
+                self.advance()?;
+                max
+            } else {
+                self.parse_integer()?
+            }
+        } else {
+            start
+        };
+
+        Ok(NumberRange {
+            start,
+            end,
+            span: self.span_from(start_position),
+        })
+    }
+
+    fn parse_integer(&mut self) -> Result<i64> {
+        let number = match &self.current_token {
+            Token::NumberLiteral(n) => n
+                .parse::<i64>()
+                .map_err(|_| ParseError::InvalidNumber(n.clone()))?,
             _ => {
                 return Err(ParseError::Expected {
-                    expected: "field name".to_string(),
+                    expected: "number".to_string(),
                     found: format!("{:?}", self.current_token),
                 }
                 .into())
@@ -303,9 +646,17 @@ impl Parser {
         };
 
         self.advance()?;
-        self.expect(Token::Equals)?;
+        Ok(number)
+    }
 
+    fn parse_field_number(&mut self) -> Result<u32> {
         let number = match &self.current_token {
+            Token::NumberLiteral(n) if n.starts_with('-') => {
+                return Err(ParseError::InvalidSyntax(format!(
+                    "field number must be positive, found {n}"
+                ))
+                .into())
+            }
             Token::NumberLiteral(n) => n
                 .parse::<u32>()
                 .map_err(|_| ParseError::InvalidNumber(n.clone()))?,
@@ -319,37 +670,151 @@ impl Parser {
         };
 
         self.advance()?;
+        Ok(number)
+    }
 
-        let mut options = HashMap::new();
-        if self.current_token == Token::LeftBracket {
-            options = self.parse_field_options()?;
+    /// Whether the current token starts a `reserved` statement rather than
+    /// a field of type `reserved` or an enum value named `reserved`.
+    fn at_reserved(&self) -> bool {
+        if !matches!(&self.current_token, Token::Identifier(id) if id == "reserved") {
+            return false;
         }
 
-        self.expect(Token::Semicolon)?;
+        let mut lexer = self.lexer.clone();
+        match lexer.next_spanned_token().map(|token| token.node) {
+            Ok(Token::NumberLiteral(_) | Token::StringLiteral(_)) => true,
+            // `reserved foo, bar;` in editions, but `reserved foo = 1;` is a field
+            Ok(Token::Identifier(_)) => !matches!(
+                lexer.next_spanned_token().map(|token| token.node),
+                Ok(Token::Equals)
+            ),
+            _ => false,
+        }
+    }
 
-        Ok(Field {
-            name,
-            field_type,
+    /// Whether the current token starts an `extensions` statement rather
+    /// than a field of type `extensions`.
+    fn at_extensions(&self) -> bool {
+        if !matches!(&self.current_token, Token::Identifier(id) if id == "extensions") {
+            return false;
+        }
+
+        let mut lexer = self.lexer.clone();
+        matches!(
+            lexer.next_spanned_token().map(|token| token.node),
+            Ok(Token::NumberLiteral(_))
+        )
+    }
+
+    /// Whether the current token starts a `group` declaration: the
+    /// contextual keyword followed by the group's name.
+    fn at_group(&self) -> bool {
+        if !matches!(&self.current_token, Token::Identifier(id) if id == "group") {
+            return false;
+        }
+
+        let mut lexer = self.lexer.clone();
+        matches!(
+            lexer.next_spanned_token().map(|token| token.node),
+            Ok(Token::Identifier(_))
+        )
+    }
+
+    /// Parses `group Name = N { ... }` into the group's field and message.
+    /// Outside proto2, `group` is also an ordinary type name, so a
+    /// declaration without a body is a field of type `group` instead.
+    fn parse_group(&mut self) -> Result<(Field, Option<Message>)> {
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
+        let group_span = self.current_span;
+        self.advance()?;
+
+        let name_span = self.current_span;
+        let name = self.parse_identifier("group name")?;
+        self.expect(Token::Equals)?;
+        let number = self.parse_field_number()?;
+
+        let (options, option_names, options_span) = self.parse_optional_field_options()?;
+        if !self.proto2 && self.current_token != Token::LeftBrace {
+            self.expect(Token::Semicolon)?;
+            let field = Field {
+                name,
+                span: self.span_from(start),
+                name_span,
+                field_type: "group".to_string(),
+                type_span: group_span,
+                map_type: None,
+                number,
+                label: None,
+                label_span: None,
+                options,
+                option_names,
+                options_span,
+                is_group: false,
+                leading_comments,
+                trailing_comments: self.trailing_comments(),
+            };
+            return Ok((field, None));
+        }
+
+        let group = self.parse_message_body(name.clone(), name_span, start, None)?;
+        let field = Field {
+            name: name.to_lowercase(),
+            span: group.span,
+            name_span,
+            field_type: name,
+            type_span: name_span,
+            map_type: None,
             number,
             label: None,
+            label_span: None,
             options,
-        })
+            option_names,
+            options_span,
+            is_group: true,
+            leading_comments,
+            trailing_comments: self.trailing_comments(),
+        };
+
+        Ok((field, Some(group)))
     }
 
-    fn parse_field_options(&mut self) -> Result<HashMap<String, OptionValue>> {
-        let mut options = HashMap::new();
+    /// Parses a bracketed option list if one is present.
+    fn parse_optional_field_options(&mut self) -> Result<FieldOptions> {
+        if self.current_token != Token::LeftBracket {
+            return Ok((HashMap::new(), Vec::new(), None));
+        }
+
+        let start = self.current_span.start;
+        let options = self.parse_field_options()?;
+        let names = options.iter().map(|(name, _)| name.clone()).collect();
+        let options = options
+            .into_iter()
+            .map(|(name, value)| (name.name, value))
+            .collect();
+        Ok((options, names, Some(self.span_from(start))))
+    }
+
+    /// Parses `[name = value, ...]` in source order, keeping repeated names.
+    fn parse_field_options(&mut self) -> Result<Vec<(OptionName, OptionValue)>> {
+        let mut options = Vec::new();
 
         self.expect(Token::LeftBracket)?;
 
         loop {
+            let start = self.current_span.start;
             let name = self.parse_option_name()?;
+            let span = self.span_from(start);
             self.expect(Token::Equals)?;
             let value = self.parse_option_value()?;
-            options.insert(name, value);
+            options.push((OptionName { name, span }, value));
 
-            if self.current_token == Token::Comma {
-                self.advance()?;
-            } else {
+            if self.current_token != Token::Comma {
+                break;
+            }
+            self.advance()?;
+            // A trailing comma: `[deprecated = true,]`
+            if self.current_token == Token::RightBracket {
                 break;
             }
         }
@@ -358,48 +823,84 @@ impl Parser {
         Ok(options)
     }
 
+    /// Parses an option name such as `deprecated`, `features.field_presence`,
+    /// `(gogoproto.nullable)` or `(foo.bar).baz`. Each segment is either an
+    /// identifier or a parenthesized, possibly qualified, extension name.
     fn parse_option_name(&mut self) -> Result<String> {
-        let mut name = String::new();
+        let mut name = self.parse_option_name_segment()?;
 
-        if self.current_token == Token::LeftParen {
-            name.push('(');
+        while self.current_token == Token::Dot {
             self.advance()?;
+            name.push('.');
+            name.push_str(&self.parse_option_name_segment()?);
+        }
 
-            match &self.current_token {
-                Token::Identifier(id) => name.push_str(id),
-                _ => {
-                    return Err(ParseError::Expected {
-                        expected: "identifier".to_string(),
-                        found: format!("{:?}", self.current_token),
-                    }
-                    .into())
-                }
-            }
+        Ok(name)
+    }
+
+    fn parse_option_name_segment(&mut self) -> Result<String> {
+        if self.current_token != Token::LeftParen {
+            return self.parse_identifier("option name");
+        }
+
+        self.advance()?;
+        let mut name = String::from("(");
 
+        // Fully-qualified extension names may start with a dot
+        if self.current_token == Token::Dot {
+            name.push('.');
             self.advance()?;
-            self.expect(Token::RightParen)?;
-            name.push(')');
-        } else {
-            match &self.current_token {
-                Token::Identifier(id) => {
-                    name = id.clone();
-                    self.advance()?;
-                }
-                _ => {
-                    return Err(ParseError::Expected {
-                        expected: "option name".to_string(),
-                        found: format!("{:?}", self.current_token),
-                    }
-                    .into())
-                }
-            }
         }
 
+        name.push_str(&self.parse_identifier("identifier")?);
+        while self.current_token == Token::Dot {
+            self.advance()?;
+            name.push('.');
+            name.push_str(&self.parse_identifier("identifier")?);
+        }
+
+        self.expect(Token::RightParen)?;
+        name.push(')');
         Ok(name)
     }
 
+    /// Parses a possibly qualified type name: `Foo`, `pkg.Foo.Bar`, or the
+    /// fully qualified `.pkg.Foo`.
+    fn parse_type_name(&mut self, expected: &str) -> Result<String> {
+        let mut name = String::new();
+        if self.current_token == Token::Dot {
+            name.push('.');
+            self.advance()?;
+        }
+
+        name.push_str(&self.parse_identifier(expected)?);
+        while self.current_token == Token::Dot {
+            self.advance()?;
+            name.push('.');
+            name.push_str(&self.parse_identifier(expected)?);
+        }
+
+        Ok(name)
+    }
+
+    fn parse_identifier(&mut self, expected: &str) -> Result<String> {
+        match &self.current_token {
+            Token::Identifier(id) => {
+                let id = id.clone();
+                self.advance()?;
+                Ok(id)
+            }
+            _ => Err(ParseError::Expected {
+                expected: expected.to_string(),
+                found: format!("{:?}", self.current_token),
+            }
+            .into()),
+        }
+    }
+
     fn parse_option_value(&mut self) -> Result<OptionValue> {
         let value = match &self.current_token {
+            Token::LeftBrace | Token::LeftAngle => return self.parse_message_literal(),
             Token::StringLiteral(s) => OptionValue::String(s.clone()),
             Token::NumberLiteral(n) => {
                 let num = n
@@ -409,6 +910,9 @@ impl Parser {
             }
             Token::True => OptionValue::Bool(true),
             Token::False => OptionValue::Bool(false),
+            // Float options may be infinite or NaN
+            Token::Identifier(id) if id == "inf" => OptionValue::Number(f64::INFINITY),
+            Token::Identifier(id) if id == "nan" => OptionValue::Number(f64::NAN),
             Token::Identifier(id) => OptionValue::Identifier(id.clone()),
             _ => {
                 return Err(ParseError::Expected {
@@ -423,9 +927,96 @@ impl Parser {
         Ok(value)
     }
 
+    /// Parses a text-format message literal delimited by `{}` or `<>`:
+    /// `{ name: "x" nested { id: 1 } ids: [1, 2] [ext.field]: true }`.
+    fn parse_message_literal(&mut self) -> Result<OptionValue> {
+        self.enter_nested()?;
+        let close = match self.current_token {
+            Token::LeftAngle => Token::RightAngle,
+            _ => Token::RightBrace,
+        };
+        self.advance()?;
+
+        let mut fields = Vec::new();
+        while self.current_token != close {
+            let start = self.current_span.start;
+            let name = self.parse_literal_field_name()?;
+            let has_colon = self.current_token == Token::Colon;
+            if has_colon {
+                self.advance()?;
+            }
+
+            match self.current_token {
+                Token::LeftBrace | Token::LeftAngle => {
+                    let value = self.parse_message_literal()?;
+                    fields.push(LiteralField {
+                        name,
+                        value,
+                        span: self.span_from(start),
+                    });
+                }
+                Token::LeftBracket if has_colon => {
+                    self.advance()?;
+                    while self.current_token != Token::RightBracket {
+                        let value = self.parse_option_value()?;
+                        fields.push(LiteralField {
+                            name: name.clone(),
+                            value,
+                            span: self.span_from(start),
+                        });
+                        if self.current_token != Token::Comma {
+                            break;
+                        }
+                        self.advance()?;
+                    }
+                    self.expect(Token::RightBracket)?;
+                }
+                _ if has_colon => {
+                    let value = self.parse_option_value()?;
+                    fields.push(LiteralField {
+                        name,
+                        value,
+                        span: self.span_from(start),
+                    });
+                }
+                _ => {
+                    return Err(ParseError::Expected {
+                        expected: "':' or message value".to_string(),
+                        found: format!("{:?}", self.current_token),
+                    }
+                    .into())
+                }
+            }
+
+            if matches!(self.current_token, Token::Comma | Token::Semicolon) {
+                self.advance()?;
+            }
+        }
+
+        self.advance()?;
+        self.depth -= 1;
+        Ok(OptionValue::Message(fields))
+    }
+
+    /// A field name in a message literal: `name`, or an extension or
+    /// `Any` type name in brackets, kept with its brackets.
+    fn parse_literal_field_name(&mut self) -> Result<String> {
+        if self.current_token != Token::LeftBracket {
+            return self.parse_identifier("field name");
+        }
+
+        self.advance()?;
+        let name = self.parse_type_name("extension name")?;
+        self.expect(Token::RightBracket)?;
+        Ok(format!("[{name}]"))
+    }
+
     fn parse_oneof(&mut self) -> Result<Oneof> {
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
         self.expect(Token::Oneof)?;
 
+        let name_span = self.current_span;
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
             _ => {
@@ -444,12 +1035,24 @@ impl Parser {
 
         while self.current_token != Token::RightBrace {
             match &self.current_token {
-                Token::Identifier(_) => {
+                Token::Identifier(_) | Token::Dot => {
                     fields.push(self.parse_field()?);
                 }
                 Token::Semicolon => {
                     self.advance()?;
                 }
+                Token::Optional | Token::Required | Token::Repeated => {
+                    let span = self.current_span;
+                    return Err(PositionedParseError {
+                        error: ParseError::InvalidSyntax(format!(
+                            "Fields in oneof '{}' cannot be '{}'",
+                            name,
+                            self.parse_field_label()?.keyword()
+                        )),
+                        span,
+                    }
+                    .into());
+                }
                 _ => {
                     return Err(
                         ParseError::UnexpectedToken(format!("{:?}", self.current_token)).into(),
@@ -460,12 +1063,21 @@ impl Parser {
 
         self.expect(Token::RightBrace)?;
 
-        Ok(Oneof { name, fields })
+        Ok(Oneof {
+            name,
+            span: self.span_from(start),
+            name_span,
+            fields,
+            leading_comments,
+        })
     }
 
     fn parse_enum(&mut self) -> Result<Enum> {
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
         self.expect(Token::Enum)?;
 
+        let name_span = self.current_span;
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
             _ => {
@@ -482,25 +1094,37 @@ impl Parser {
 
         let mut enum_def = Enum {
             name,
+            span: Span::default(),
+            name_span,
             values: Vec::new(),
             options: HashMap::new(),
+            option_names: Vec::new(),
+            reserved_ranges: Vec::new(),
+            reserved_names: Vec::new(),
+            leading_comments,
         };
 
         while self.current_token != Token::RightBrace {
             match &self.current_token {
                 Token::Option => {
                     let (name, value) = self.parse_option()?;
-                    enum_def.options.insert(name, value);
+                    enum_def.options.insert(name.name.clone(), value);
+                    enum_def.option_names.push(name);
+                }
+                Token::Identifier(_) if self.at_reserved() => {
+                    let (ranges, names) = self.parse_reserved(i32::MAX.into())?;
+                    enum_def.reserved_ranges.extend(ranges);
+                    enum_def.reserved_names.extend(names);
                 }
                 Token::Identifier(value_name) => {
                     let value_name = value_name.clone();
+                    let value_span = self.current_span;
+                    let leading_comments = self.leading_comments();
                     self.advance()?;
                     self.expect(Token::Equals)?;
 
                     let number = match &self.current_token {
-                        Token::NumberLiteral(n) => n
-                            .parse::<i32>()
-                            .map_err(|_| ParseError::InvalidNumber(n.clone()))?,
+                        Token::NumberLiteral(n) => parse_enum_number(n)?,
                         _ => {
                             return Err(ParseError::Expected {
                                 expected: "enum value number".to_string(),
@@ -512,17 +1136,18 @@ impl Parser {
 
                     self.advance()?;
 
-                    let mut options = HashMap::new();
-                    if self.current_token == Token::LeftBracket {
-                        options = self.parse_field_options()?;
-                    }
+                    let (options, _, _) = self.parse_optional_field_options()?;
 
                     self.expect(Token::Semicolon)?;
 
                     enum_def.values.push(EnumValue {
                         name: value_name,
+                        span: self.span_from(value_span.start),
+                        name_span: value_span,
                         number,
                         options,
+                        leading_comments,
+                        trailing_comments: self.trailing_comments(),
                     });
                 }
                 Token::Semicolon => {
@@ -537,12 +1162,16 @@ impl Parser {
         }
 
         self.expect(Token::RightBrace)?;
+        enum_def.span = self.span_from(start);
         Ok(enum_def)
     }
 
     fn parse_service(&mut self) -> Result<Service> {
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
         self.expect(Token::Service)?;
 
+        let name_span = self.current_span;
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
             _ => {
@@ -559,8 +1188,11 @@ impl Parser {
 
         let mut service = Service {
             name,
+            span: Span::default(),
+            name_span,
             methods: Vec::new(),
             options: HashMap::new(),
+            leading_comments,
         };
 
         while self.current_token != Token::RightBrace {
@@ -569,27 +1201,54 @@ impl Parser {
                     service.methods.push(self.parse_rpc()?);
                 }
                 Token::Option => {
-                    let (name, value) = self.parse_option()?;
+                    let (OptionName { name, .. }, value) = self.parse_option()?;
                     service.options.insert(name, value);
                 }
                 Token::Semicolon => {
                     self.advance()?;
                 }
                 _ => {
-                    return Err(
-                        ParseError::UnexpectedToken(format!("{:?}", self.current_token)).into(),
-                    );
+                    return Err(ParseError::InvalidSyntax(format!(
+                        "unexpected {:?}: only rpc and option are allowed inside a service",
+                        self.current_token
+                    ))
+                    .into());
                 }
             }
         }
 
         self.expect(Token::RightBrace)?;
+        service.span = self.span_from(start);
         Ok(service)
     }
 
+    /// `stream` is only valid directly after the opening parenthesis of a
+    /// request or response type; give a targeted error anywhere else.
+    fn reject_misplaced_stream(&self) -> Result<()> {
+        if self.current_token == Token::Stream {
+            return Err(ParseError::InvalidSyntax(
+                "'stream' must come right after '(', e.g. 'rpc Method(stream Request) returns (stream Response)'"
+                    .to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Error for a `stream` keyword found outside an rpc declaration.
+    fn stream_outside_rpc(&self) -> anyhow::Error {
+        ParseError::InvalidSyntax(
+            "'stream' is only allowed in an rpc's request or response type".to_string(),
+        )
+        .into()
+    }
+
     fn parse_rpc(&mut self) -> Result<Method> {
+        let start = self.current_span.start;
+        let leading_comments = self.leading_comments();
         self.expect(Token::Rpc)?;
 
+        let name_span = self.current_span;
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
             _ => {
@@ -610,20 +1269,13 @@ impl Parser {
             self.advance()?;
         }
 
-        let request_type = match &self.current_token {
-            Token::Identifier(t) => t.clone(),
-            _ => {
-                return Err(ParseError::Expected {
-                    expected: "request type".to_string(),
-                    found: format!("{:?}", self.current_token),
-                }
-                .into())
-            }
-        };
-
-        self.advance()?;
+        let request_start = self.current_span.start;
+        let request_type = self.parse_type_name("request type")?;
+        let request_type_span = self.span_from(request_start);
+        self.reject_misplaced_stream()?;
         self.expect(Token::RightParen)?;
         self.expect(Token::Returns)?;
+        self.reject_misplaced_stream()?;
         self.expect(Token::LeftParen)?;
 
         let mut server_streaming = false;
@@ -632,18 +1284,10 @@ impl Parser {
             self.advance()?;
         }
 
-        let response_type = match &self.current_token {
-            Token::Identifier(t) => t.clone(),
-            _ => {
-                return Err(ParseError::Expected {
-                    expected: "response type".to_string(),
-                    found: format!("{:?}", self.current_token),
-                }
-                .into())
-            }
-        };
-
-        self.advance()?;
+        let response_start = self.current_span.start;
+        let response_type = self.parse_type_name("response type")?;
+        let response_type_span = self.span_from(response_start);
+        self.reject_misplaced_stream()?;
         self.expect(Token::RightParen)?;
 
         let mut options = HashMap::new();
@@ -654,7 +1298,7 @@ impl Parser {
             while self.current_token != Token::RightBrace {
                 match &self.current_token {
                     Token::Option => {
-                        let (name, value) = self.parse_option()?;
+                        let (OptionName { name, .. }, value) = self.parse_option()?;
                         options.insert(name, value);
                     }
                     Token::Semicolon => {
@@ -677,34 +1321,42 @@ impl Parser {
 
         Ok(Method {
             name,
+            span: self.span_from(start),
+            name_span,
             request_type,
+            request_type_span,
             response_type,
+            response_type_span,
             client_streaming,
             server_streaming,
             options,
+            leading_comments,
+            trailing_comments: self.trailing_comments(),
         })
     }
 
-    fn parse_option(&mut self) -> Result<(String, OptionValue)> {
+    fn parse_option(&mut self) -> Result<(OptionName, OptionValue)> {
         self.expect(Token::Option)?;
 
-        let name = match &self.current_token {
-            Token::Identifier(n) => n.clone(),
-            _ => {
-                return Err(ParseError::Expected {
-                    expected: "option name".to_string(),
-                    found: format!("{:?}", self.current_token),
-                }
-                .into())
-            }
-        };
-
-        self.advance()?;
+        let start = self.current_span.start;
+        let name = self.parse_option_name()?;
+        let span = self.span_from(start);
         self.expect(Token::Equals)?;
 
         let value = self.parse_option_value()?;
         self.expect(Token::Semicolon)?;
 
-        Ok((name, value))
+        Ok((OptionName { name, span }, value))
     }
 }
+
+/// Parses an enum value literal, telling integer overflow apart from a
+/// literal that isn't an integer at all.
+fn parse_enum_number(literal: &str) -> Result<i32, ParseError> {
+    literal.parse::<i32>().map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+            ParseError::InvalidSyntax(format!("enum value {literal} out of range for int32"))
+        }
+        _ => ParseError::InvalidNumber(literal.to_string()),
+    })
+}
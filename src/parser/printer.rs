@@ -0,0 +1,314 @@
+use super::lexer::{tokenize_with_comments, Comment, Spanned, Token};
+use super::{parse_proto, ParseError, Position, Span};
+use anyhow::Result;
+
+/// Pretty-prints a whole file: one statement per line, `indent` per level of
+/// nesting, single spaces between tokens and at most one blank line in a row.
+/// Comments and the original spelling of literals are kept as written, and
+/// files with CRLF line breaks keep them.
+///
+/// Fails if `input` doesn't parse, so broken files are never rewritten.
+pub fn format_proto(input: &str, indent: &str) -> Result<String> {
+    parse_proto(input)?;
+    let mut formatted = print(input, None, indent)?;
+    if !formatted.is_empty() {
+        formatted.push_str(line_break(input));
+    }
+    Ok(formatted)
+}
+
+/// Pretty-prints only the tokens and comments within `span` (typically a
+/// top-level declaration), as if it were at the top level. The result has no
+/// trailing newline, so it can replace the span's text directly.
+pub fn format_declaration(input: &str, span: Span, indent: &str) -> Result<String, ParseError> {
+    print(input, Some(span), indent)
+}
+
+fn print(input: &str, span: Option<Span>, indent: &str) -> Result<String, ParseError> {
+    let (tokens, comments) = tokenize_with_comments(input)?;
+    let within = |item_span: &Span| {
+        span.is_none_or(|span| span.start <= item_span.start && item_span.end <= span.end)
+    };
+
+    let mut items: Vec<Item> = tokens
+        .iter()
+        .filter(|token| within(&token.span))
+        .map(Item::Token)
+        .chain(
+            comments
+                .iter()
+                .filter(|comment| within(&comment.span))
+                .map(Item::Comment),
+        )
+        .collect();
+    items.sort_by_key(|item| item.span().start);
+
+    let mut printer = Printer::new(input, indent);
+    for (i, item) in items.iter().enumerate() {
+        let next_line = items.get(i + 1).map(|next| next.span().start.line);
+        match item {
+            Item::Token(token) => printer.token(token),
+            Item::Comment(comment) => printer.comment(comment, next_line),
+        }
+    }
+    Ok(printer.finish().join(line_break(input)))
+}
+
+/// The line break `input` uses: CRLF if its first line ends in one.
+pub(crate) fn line_break(input: &str) -> &'static str {
+    match input.find('\n') {
+        Some(newline) if input[..newline].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+enum Item<'a> {
+    Token(&'a Spanned<Token>),
+    Comment(&'a Comment),
+}
+
+impl Item<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Item::Token(token) => token.span,
+            Item::Comment(comment) => comment.span,
+        }
+    }
+}
+
+struct Printer<'a> {
+    source: Source<'a>,
+    indent: &'a str,
+    lines: Vec<String>,
+    line: String,
+    depth: usize,
+    /// The last token printed, for spacing decisions.
+    previous: Option<Token>,
+    /// Source line on which the last printed item ended.
+    previous_end_line: Option<usize>,
+    /// The current line is complete; the next item starts a new one unless
+    /// it's a trailing comment.
+    break_pending: bool,
+    /// The current line ends in a `//` comment, so nothing can follow it.
+    after_line_comment: bool,
+    /// Nothing has been printed since the last `{`.
+    at_block_start: bool,
+    /// Inside a statement that hasn't reached its `;` or `{` yet, so a line
+    /// break (forced by a comment) gets continuation indentation.
+    in_statement: bool,
+    /// Nesting of `{` blocks that are message-literal option values rather
+    /// than declarations. Their fields keep the source's line breaks.
+    literal_depth: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(input: &'a str, indent: &'a str) -> Self {
+        Self {
+            source: Source::new(input),
+            indent,
+            lines: Vec::new(),
+            line: String::new(),
+            depth: 0,
+            previous: None,
+            previous_end_line: None,
+            break_pending: false,
+            after_line_comment: false,
+            at_block_start: false,
+            in_statement: false,
+            literal_depth: 0,
+        }
+    }
+
+    fn token(&mut self, token: &Spanned<Token>) {
+        let text = self.source.slice(token.span);
+
+        match token.node {
+            Token::RightBrace => {
+                self.depth = self.depth.saturating_sub(1);
+                self.literal_depth = self.literal_depth.saturating_sub(1);
+                if self.at_block_start && self.previous == Some(Token::LeftBrace) {
+                    // Keep empty blocks on one line: `message Empty {}`
+                    self.line.push('}');
+                } else {
+                    self.flush();
+                    self.push_indent(self.depth);
+                    self.line.push('}');
+                }
+                self.break_pending = true;
+                self.in_statement = false;
+            }
+            Token::Semicolon => {
+                if self.after_line_comment {
+                    self.start_line(token.span.start.line);
+                }
+                self.line.push(';');
+                self.break_pending = true;
+                self.in_statement = false;
+            }
+            // Closing punctuation stays on the line of a preceding `}`, as in
+            // `[(custom) = { id: 1 }]`
+            Token::Comma | Token::RightBracket | Token::RightParen | Token::Colon
+                if !self.line.is_empty() && !self.after_line_comment =>
+            {
+                self.line.push_str(text);
+                self.in_statement = true;
+            }
+            _ => {
+                let new_literal_line = self.literal_depth > 0
+                    && self
+                        .previous_end_line
+                        .is_some_and(|previous| token.span.start.line > previous);
+                if new_literal_line {
+                    self.in_statement = false;
+                }
+
+                if self.break_pending || self.line.is_empty() || new_literal_line {
+                    self.start_line(token.span.start.line);
+                } else if self.needs_space(&token.node) {
+                    self.line.push(' ');
+                }
+                self.line.push_str(text);
+
+                if token.node == Token::LeftBrace {
+                    if self.literal_depth > 0
+                        || matches!(self.previous, Some(Token::Equals | Token::Colon))
+                    {
+                        self.literal_depth += 1;
+                    }
+                    self.depth += 1;
+                    self.break_pending = true;
+                    self.in_statement = false;
+                } else {
+                    self.in_statement = true;
+                }
+            }
+        }
+
+        self.at_block_start = token.node == Token::LeftBrace;
+        self.after_line_comment = false;
+        self.previous = Some(token.node.clone());
+        self.previous_end_line = Some(token.span.end.line);
+    }
+
+    fn comment(&mut self, comment: &Comment, next_line: Option<usize>) {
+        let text = self.source.slice(comment.span).trim_end();
+        let trailing =
+            !self.line.is_empty() && self.previous_end_line == Some(comment.span.start.line);
+
+        if trailing {
+            self.line.push(' ');
+            self.line.push_str(text);
+        } else {
+            self.start_line(comment.span.start.line);
+            self.line.push_str(text);
+        }
+
+        if text.starts_with("//") {
+            self.break_pending = true;
+            self.after_line_comment = true;
+        } else if !trailing {
+            // A block comment on its own line stays there, unless code
+            // follows it on the same line
+            self.break_pending = next_line != Some(comment.span.end.line);
+        }
+
+        self.at_block_start = false;
+        self.previous_end_line = Some(comment.span.end.line);
+    }
+
+    /// Ends the current line and indents a new one, keeping a single blank
+    /// line if the source had any before `source_line`.
+    fn start_line(&mut self, source_line: usize) {
+        self.flush();
+        let after_gap = self
+            .previous_end_line
+            .is_some_and(|previous| source_line > previous + 1);
+        if after_gap && !self.at_block_start && !self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+
+        let depth = if self.in_statement {
+            self.depth + 1
+        } else {
+            self.depth
+        };
+        self.push_indent(depth);
+        self.break_pending = false;
+    }
+
+    fn push_indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.line.push_str(self.indent);
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.lines.push(std::mem::take(&mut self.line));
+        }
+    }
+
+    fn needs_space(&self, next: &Token) -> bool {
+        let Some(previous) = &self.previous else {
+            return false;
+        };
+
+        let tight_before = match next {
+            Token::Comma | Token::RightParen | Token::RightBracket | Token::Colon => true,
+            // `map<string, int32>`, but `option (custom) = <key: 1>`
+            Token::LeftAngle => matches!(previous, Token::Identifier(_)),
+            Token::RightAngle => true,
+            // `rpc Get(Request)`, but `returns (Response)` and `option (custom)`
+            Token::LeftParen => matches!(previous, Token::Identifier(_)),
+            // `google.protobuf.Empty` and `(custom).field`, but `repeated .pkg.Type`
+            Token::Dot => matches!(previous, Token::Identifier(_) | Token::RightParen),
+            _ => false,
+        };
+        let tight_after = matches!(
+            previous,
+            Token::LeftParen | Token::LeftBracket | Token::LeftAngle | Token::Dot
+        );
+
+        !tight_before && !tight_after
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.flush();
+        self.lines
+    }
+}
+
+/// Maps spans back to the text they cover.
+struct Source<'a> {
+    text: &'a str,
+    /// Byte offset at which each line starts.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    fn slice(&self, span: Span) -> &'a str {
+        &self.text[self.offset(span.start)..self.offset(span.end)]
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line) else {
+            return self.text.len();
+        };
+
+        let mut column = 0;
+        for (offset, ch) in self.text[line_start..].char_indices() {
+            if column >= position.column || ch == '\n' {
+                return line_start + offset;
+            }
+            column += ch.len_utf16();
+        }
+        self.text.len()
+    }
+}
@@ -1,177 +1,1361 @@
 use super::*;
 use std::collections::{HashMap, HashSet};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
 #[derive(Debug, Clone)]
 pub struct ValidationError {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the error applies; the start of the file if it has no location.
+    pub span: Span,
+    /// Other locations relevant to this error, e.g. the first declaration
+    /// when reporting a duplicate.
+    pub related: Vec<RelatedLocation>,
+    /// A suggested fix, offered to the client as a quick fix.
+    pub replacement: Option<Replacement>,
+}
+
+/// Text that should replace `span` to fix a validation error.
+#[derive(Debug, Clone)]
+pub struct Replacement {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelatedLocation {
+    pub span: Span,
     pub message: String,
-    pub line: usize,
-    pub column: usize,
 }
 
 impl ValidationError {
-    fn new(message: String) -> Self {
+    fn new(code: &'static str, severity: Severity, message: String) -> Self {
         Self {
+            code,
+            severity,
             message,
-            line: 0,
-            column: 0,
+            span: Span::default(),
+            related: Vec::new(),
+            replacement: None,
+        }
+    }
+
+    fn error(code: &'static str, message: String) -> Self {
+        Self::new(code, Severity::Error, message)
+    }
+
+    fn warning(code: &'static str, message: String) -> Self {
+        Self::new(code, Severity::Warning, message)
+    }
+
+    fn at(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    fn replace_with(mut self, span: Span, text: &str) -> Self {
+        self.replacement = Some(Replacement {
+            span,
+            text: text.to_string(),
+        });
+        self
+    }
+
+    fn related_to(mut self, span: Span, message: &str) -> Self {
+        self.related.push(RelatedLocation {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+}
+
+/// Scalar types allowed as map keys: any integral type, bool, or string.
+const MAP_KEY_TYPES: &[&str] = &[
+    "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32", "fixed64", "sfixed32",
+    "sfixed64", "bool", "string",
+];
+
+/// Joins a parent path and a name with a dot, e.g. `Outer.Inner`.
+fn qualified_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{scope}.{name}")
+    }
+}
+
+/// Finds the scalar type that `name` is most likely a misspelling of, e.g.
+/// `string` for `strnig`. Only lowercase names within an edit distance of
+/// two are considered, since message and enum types are PascalCase.
+pub fn suggest_scalar_type(name: &str) -> Option<&'static str> {
+    if !name.starts_with(|ch: char| ch.is_ascii_lowercase()) || SCALAR_TYPES.contains(&name) {
+        return None;
+    }
+
+    SCALAR_TYPES
+        .iter()
+        .map(|scalar| (edit_distance(name, scalar), *scalar))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, scalar)| scalar)
+}
+
+/// Finds the supported edition that `edition` is most likely a typo of,
+/// e.g. `2023` for `2O23`.
+fn suggest_edition(edition: &str) -> Option<&'static str> {
+    SUPPORTED_EDITIONS
+        .iter()
+        .map(|supported| (edit_distance(edition, supported), *supported))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, supported)| supported)
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_ch) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_ch) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_ch != *b_ch);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+fn check_all_features(options: &HashMap<String, OptionValue>, errors: &mut Vec<ValidationError>) {
+    for (name, value) in options {
+        check_features(name, value, errors);
+    }
+}
+
+/// Checks the entries of a `features = { ... }` literal against the known
+/// features and their values. Extension features (`[pkg.ext]`) are skipped.
+fn check_features(name: &str, value: &OptionValue, errors: &mut Vec<ValidationError>) {
+    let OptionValue::Message(fields) = value else {
+        return;
+    };
+    if name != "features" {
+        return;
+    }
+
+    for field in fields.iter().filter(|field| !field.name.starts_with('[')) {
+        let Some((_, allowed)) = FEATURE_VALUES
+            .iter()
+            .find(|(feature, _)| *feature == field.name)
+        else {
+            errors.push(
+                ValidationError::error(
+                    "unknown-feature",
+                    format!("Unknown feature '{}'", field.name),
+                )
+                .at(field.span),
+            );
+            continue;
+        };
+
+        let valid = matches!(&field.value, OptionValue::Identifier(value)
+            if allowed.contains(&value.as_str()));
+        if !valid {
+            errors.push(
+                ValidationError::error(
+                    "invalid-feature-value",
+                    format!(
+                        "Invalid value '{}' for feature '{}'; expected one of {}",
+                        option_value_text(&field.value),
+                        field.name,
+                        allowed.join(", ")
+                    ),
+                )
+                .at(field.span),
+            );
+        }
+    }
+}
+
+/// Renders an option value the way it would be written in source.
+fn option_value_text(value: &OptionValue) -> String {
+    match value {
+        OptionValue::String(s) => format!("\"{s}\""),
+        OptionValue::Number(n) if n.is_nan() => "nan".to_string(),
+        OptionValue::Number(n) => n.to_string(),
+        OptionValue::Bool(b) => b.to_string(),
+        OptionValue::Identifier(id) => id.clone(),
+        OptionValue::Message(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name, option_value_text(&field.value)))
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+    }
+}
+
+/// The type of an option value, as named in error messages.
+fn option_value_kind(value: &OptionValue) -> &'static str {
+    match value {
+        OptionValue::String(_) => "string",
+        OptionValue::Number(_) => "number",
+        OptionValue::Bool(_) => "bool",
+        OptionValue::Identifier(_) => "identifier",
+        OptionValue::Message(_) => "message",
+    }
+}
+
+/// Checks an enum's `option` statements: built-in options must have the
+/// right type and appear once, and unknown ones are flagged as warnings.
+fn check_enum_options(enum_def: &Enum, errors: &mut Vec<ValidationError>) {
+    const BOOL_OPTIONS: [&str; 3] = [
+        "allow_alias",
+        "deprecated",
+        "deprecated_legacy_json_field_conflicts",
+    ];
+
+    let mut seen: HashMap<&str, Span> = HashMap::new();
+    for option in &enum_def.option_names {
+        // Repeated custom options may legally appear several times
+        if option.name.starts_with('(') {
+            continue;
+        }
+
+        if let Some(&first) = seen.get(option.name.as_str()) {
+            errors.push(
+                ValidationError::error(
+                    "duplicate-option",
+                    format!(
+                        "Option '{}' is set more than once on enum '{}'",
+                        option.name, enum_def.name
+                    ),
+                )
+                .at(option.span)
+                .related_to(first, "first set here"),
+            );
+            continue;
+        }
+        seen.insert(&option.name, option.span);
+
+        // Feature overrides are checked elsewhere
+        let is_feature = option.name == "features" || option.name.starts_with("features.");
+        if !is_feature && !BOOL_OPTIONS.contains(&option.name.as_str()) {
+            errors.push(
+                ValidationError::warning(
+                    "unknown-option",
+                    format!(
+                        "Unknown option '{}' on enum '{}'",
+                        option.name, enum_def.name
+                    ),
+                )
+                .at(option.span),
+            );
+        }
+    }
+
+    for name in BOOL_OPTIONS {
+        let Some(value) = enum_def.options.get(name) else {
+            continue;
+        };
+        if option_value_kind(value) == "bool" {
+            continue;
+        }
+
+        // `options` keeps the last value, so point at the last occurrence
+        let span = enum_def
+            .option_names
+            .iter()
+            .rev()
+            .find(|option| option.name == name)
+            .map_or(enum_def.name_span, |option| option.span);
+        errors.push(
+            ValidationError::error(
+                "invalid-option-value",
+                format!(
+                    "Option '{}' on enum '{}' must be a bool, found {}",
+                    name,
+                    enum_def.name,
+                    option_value_text(value)
+                ),
+            )
+            .at(span),
+        );
+    }
+}
+
+/// Renders a number range the way it would be written in a `reserved` statement.
+fn range_text(range: &NumberRange) -> String {
+    if range.start == range.end {
+        range.start.to_string()
+    } else {
+        format!("{} to {}", range.start, range.end)
+    }
+}
+
+/// Flags a message (or group) or enum named after a scalar type, which would
+/// make field types referring to it ambiguous.
+fn check_type_name(kind: &str, name: &str, name_span: Span, errors: &mut Vec<ValidationError>) {
+    if SCALAR_TYPES.contains(&name) {
+        errors.push(
+            ValidationError::error(
+                "reserved-type-name",
+                format!("{kind} '{name}' conflicts with the built-in type '{name}'"),
+            )
+            .at(name_span),
+        );
+    }
+}
+
+/// Collects the simple names of every message and enum declared in `message`.
+fn collect_declared_types<'a>(
+    message: &'a Message,
+    depth: usize,
+    max_depth: usize,
+    names: &mut HashSet<&'a str>,
+    enum_names: &mut HashSet<&'a str>,
+) {
+    names.insert(&message.name);
+    for enum_def in &message.nested_enums {
+        names.insert(&enum_def.name);
+        enum_names.insert(&enum_def.name);
+    }
+    if depth < max_depth {
+        for nested in &message.nested_messages {
+            collect_declared_types(nested, depth + 1, max_depth, names, enum_names);
         }
     }
 }
 
 pub fn validate_proto(proto_file: &ProtoFile) -> Vec<ValidationError> {
+    validate_proto_with_max_depth(proto_file, DEFAULT_MAX_NESTING_DEPTH)
+}
+
+/// Like [`validate_proto`], for files parsed with
+/// [`parse_proto_with_max_depth`]. Messages nested deeper than `max_depth`
+/// are reported instead of validated.
+pub fn validate_proto_with_max_depth(
+    proto_file: &ProtoFile,
+    max_depth: usize,
+) -> Vec<ValidationError> {
     let mut errors = Vec::new();
-    let mut validator = Validator::new();
+    let mut validator = Validator::new(max_depth);
+
+    for statement in &proto_file.statements {
+        match &statement.node {
+            Statement::Message(message) => collect_declared_types(
+                message,
+                1,
+                max_depth,
+                &mut validator.declared_types,
+                &mut validator.declared_enums,
+            ),
+            Statement::Enum(enum_def) => {
+                validator.declared_types.insert(&enum_def.name);
+                validator.declared_enums.insert(&enum_def.name);
+            }
+            _ => {}
+        }
+    }
 
     validator.validate_proto_file(proto_file, &mut errors);
     errors
 }
 
-struct Validator {
-    used_field_numbers: HashMap<String, HashSet<u32>>,
-    used_enum_values: HashMap<String, HashSet<i32>>,
+struct Validator<'a> {
+    /// Simple names of every message and enum declared in the file.
+    declared_types: HashSet<&'a str>,
+    /// The subset of `declared_types` that are enums.
+    declared_enums: HashSet<&'a str>,
+    /// Numbers used so far, keyed by the message's dotted path within the file.
+    used_field_numbers: HashMap<String, HashMap<u32, Span>>,
+    /// Values used so far, keyed by the enum's dotted path within the file.
+    used_enum_values: HashMap<String, HashMap<i32, Span>>,
+    is_proto2: bool,
+    /// How deeply messages may nest before they're no longer validated.
+    max_depth: usize,
 }
 
-impl Validator {
-    fn new() -> Self {
+impl<'a> Validator<'a> {
+    fn new(max_depth: usize) -> Self {
         Self {
+            declared_types: HashSet::new(),
+            declared_enums: HashSet::new(),
             used_field_numbers: HashMap::new(),
             used_enum_values: HashMap::new(),
+            is_proto2: false,
+            max_depth,
         }
     }
 
     fn validate_proto_file(&mut self, proto_file: &ProtoFile, errors: &mut Vec<ValidationError>) {
+        // Files without `syntax` or `edition` default to proto2
+        self.is_proto2 = match (&proto_file.syntax, &proto_file.edition) {
+            (Some(syntax), _) => syntax == "proto2",
+            (None, edition) => edition.is_none(),
+        };
+
+        match (&proto_file.syntax, &proto_file.edition) {
+            (Some(_), Some(_)) => {
+                errors.push(ValidationError::error(
+                    "syntax-and-edition",
+                    "A file cannot declare both 'syntax' and 'edition'".to_string(),
+                ));
+            }
+            (None, None) => {
+                errors.push(ValidationError::warning(
+                    "missing-syntax",
+                    "No syntax or edition specified. Defaulting to proto2 syntax.".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        if let (Some(syntax_span), Some(first_statement)) =
+            (proto_file.syntax_span, proto_file.statements.first())
+        {
+            if first_statement.span.start < syntax_span.start {
+                errors.push(
+                    ValidationError::error(
+                        "syntax-not-first",
+                        "The 'syntax' or 'edition' declaration must be the first statement in the file"
+                            .to_string(),
+                    )
+                    .at(syntax_span),
+                );
+            }
+        }
+
         // Validate edition if present
         if let Some(edition) = &proto_file.edition {
-            if edition != "2023" {
-                errors.push(ValidationError::new(format!(
-                    "Unsupported edition '{edition}'. Only edition 2023 is supported."
-                )));
+            if !SUPPORTED_EDITIONS.contains(&edition.as_str()) {
+                let supported = SUPPORTED_EDITIONS.join(", ");
+                let error = if edition.len() == 4 && edition.bytes().all(|b| b.is_ascii_digit()) {
+                    // A real edition year, just not one this server knows
+                    ValidationError::error(
+                        "unsupported-edition",
+                        format!(
+                            "Unsupported edition '{edition}'. Supported editions: {supported}."
+                        ),
+                    )
+                } else if let Some(suggestion) = suggest_edition(edition) {
+                    ValidationError::error(
+                        "invalid-edition",
+                        format!("Invalid edition '{edition}'; did you mean '{suggestion}'?"),
+                    )
+                } else {
+                    ValidationError::error(
+                        "invalid-edition",
+                        format!("Invalid edition '{edition}'. Supported editions: {supported}."),
+                    )
+                };
+                errors.push(match proto_file.syntax_span {
+                    Some(span) => error.at(span),
+                    None => error,
+                });
             }
         }
 
         // Validate syntax if present
         if let Some(syntax) = &proto_file.syntax {
-            if syntax != "proto2" && syntax != "proto3" {
-                errors.push(ValidationError::new(format!(
-                    "Invalid syntax '{syntax}'. Must be 'proto2' or 'proto3'."
-                )));
+            if !SUPPORTED_SYNTAXES.contains(&syntax.as_str()) {
+                let error = ValidationError::error(
+                    "invalid-syntax",
+                    format!("Invalid syntax '{syntax}'. Must be 'proto2' or 'proto3'."),
+                );
+                errors.push(
+                    match proto_file.syntax_value_span.or(proto_file.syntax_span) {
+                        Some(span) => error.at(span),
+                        None => error,
+                    },
+                );
+            }
+        }
+
+        // A file belongs to a single package
+        let mut packages = proto_file
+            .statements
+            .iter()
+            .filter(|statement| matches!(statement.node, Statement::Package(_)));
+        if let Some(first) = packages.next() {
+            for statement in packages {
+                if let Statement::Package(package) = &statement.node {
+                    errors.push(
+                        ValidationError::error(
+                            "duplicate-package",
+                            format!("Duplicate package declaration '{package}'; a file can declare only one package"),
+                        )
+                        .at(statement.span)
+                        .related_to(first.span, "package first declared here"),
+                    );
+                }
             }
         }
 
         // Validate statements
         for statement in &proto_file.statements {
-            self.validate_statement(statement, errors);
+            self.validate_statement(&statement.node, statement.span, errors);
         }
     }
 
-    fn validate_statement(&mut self, statement: &Statement, errors: &mut Vec<ValidationError>) {
+    fn validate_statement(
+        &mut self,
+        statement: &Statement,
+        span: Span,
+        errors: &mut Vec<ValidationError>,
+    ) {
         match statement {
+            Statement::Package(package) => {
+                Self::validate_package(package, span, errors);
+            }
+            Statement::Import {
+                path,
+                path_span,
+                public,
+                weak,
+            } => {
+                Self::validate_import(path, *path_span, *public, *weak, span, errors);
+            }
             Statement::Message(message) => {
-                self.validate_message(message, errors);
+                self.validate_message(message, "", 1, errors);
             }
             Statement::Enum(enum_def) => {
-                self.validate_enum(enum_def, errors);
+                self.validate_enum(enum_def, "", errors);
             }
             Statement::Service(service) => {
                 self.validate_service(service, errors);
             }
-            _ => {}
+            Statement::Option { name, value } => check_features(name, value, errors),
         }
     }
 
-    fn validate_message(&mut self, message: &Message, errors: &mut Vec<ValidationError>) {
-        let message_key = message.name.clone();
+    fn validate_message(
+        &mut self,
+        message: &Message,
+        scope: &str,
+        depth: usize,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let message_key = qualified_name(scope, &message.name);
+        check_type_name("Message", &message.name, message.name_span, errors);
+        check_all_features(&message.options, errors);
 
-        // Check for duplicate field numbers
-        let field_numbers = self
-            .used_field_numbers
-            .entry(message_key.clone())
-            .or_default();
+        Self::check_duplicate_field_numbers(
+            message,
+            self.used_field_numbers
+                .entry(message_key.clone())
+                .or_default(),
+            errors,
+        );
 
         for field in &message.fields {
-            if !field_numbers.insert(field.number) {
-                errors.push(ValidationError::new(format!(
-                    "Duplicate field number {} in message '{}'",
-                    field.number, message.name
-                )));
-            }
-
             // Validate field number range
             if field.number == 0 {
-                errors.push(ValidationError::new(format!(
-                    "Field number cannot be 0 in field '{}' of message '{}'",
-                    field.name, message.name
-                )));
+                errors.push(
+                    ValidationError::error(
+                        "invalid-field-number",
+                        format!(
+                            "Field number cannot be 0 in field '{}' of message '{}'",
+                            field.name, message.name
+                        ),
+                    )
+                    .at(field.span),
+                );
             }
 
             if field.number >= 19000 && field.number <= 19999 {
-                errors.push(ValidationError::new(
-                    format!("Field number {} is reserved for protocol buffer implementation in field '{}' of message '{}'", 
-                            field.number, field.name, message.name)
-                ));
+                errors.push(
+                    ValidationError::error(
+                        "reserved-field-number",
+                        format!(
+                            "Field number {} is reserved for protocol buffer implementation in field '{}' of message '{}'",
+                            field.number, field.name, message.name
+                        ),
+                    )
+                    .at(field.span),
+                );
+            }
+
+            if field.is_group && !self.is_proto2 {
+                errors.push(
+                    ValidationError::error(
+                        "group-requires-proto2",
+                        format!(
+                            "Group '{}' in message '{}' is only supported in proto2",
+                            field.field_type, message.name
+                        ),
+                    )
+                    .at(field.span),
+                );
+            }
+
+            if let Some(map_type) = &field.map_type {
+                Self::validate_map_field(field, map_type, message, errors);
             }
         }
 
-        // Validate oneof fields
-        for oneof in &message.oneofs {
-            for field in &oneof.fields {
-                if !field_numbers.insert(field.number) {
-                    errors.push(ValidationError::new(format!(
-                        "Duplicate field number {} in oneof '{}' of message '{}'",
-                        field.number, oneof.name, message.name
-                    )));
+        Self::validate_reserved_ranges(message, errors);
+        Self::validate_reserved_names(message, errors);
+
+        // Check fields against numbers set aside by `reserved` and
+        // `extensions`, and against reserved names
+        let all_fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()));
+        for field in all_fields {
+            Self::check_field_type(field, message, &self.declared_types, errors);
+            Self::check_packed(
+                field,
+                message,
+                &self.declared_types,
+                &self.declared_enums,
+                errors,
+            );
+            Self::check_duplicate_options(field, message, errors);
+            Self::check_option_types(field, message, errors);
+            check_all_features(&field.options, errors);
+
+            let off_limits = [
+                (
+                    "field-uses-reserved-number",
+                    "reserved",
+                    &message.reserved_ranges,
+                ),
+                (
+                    "extension-range-conflict",
+                    "an extension range",
+                    &message.extension_ranges,
+                ),
+            ];
+            for (code, description, ranges) in off_limits {
+                let Some(range) = ranges
+                    .iter()
+                    .find(|range| range.contains(i64::from(field.number)))
+                else {
+                    continue;
+                };
+
+                errors.push(
+                    ValidationError::error(
+                        code,
+                        format!(
+                            "Field '{}' in message '{}' uses number {}, which is {} ({})",
+                            field.name,
+                            message.name,
+                            field.number,
+                            description,
+                            range_text(range)
+                        ),
+                    )
+                    .at(field.span),
+                );
+            }
+
+            if message
+                .reserved_names
+                .iter()
+                .any(|reserved| reserved.name == field.name)
+            {
+                errors.push(
+                    ValidationError::error(
+                        "reserved-field-name",
+                        format!(
+                            "Field '{}' in message '{}' uses a reserved name",
+                            field.name, message.name
+                        ),
+                    )
+                    .at(field.name_span),
+                );
+            }
+        }
+
+        Self::check_duplicate_field_names(message, errors);
+
+        // Check for conflicting JSON names, which oneof members share with
+        // the message's other fields
+        let mut json_names: HashMap<String, &Field> = HashMap::new();
+        let all_fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()));
+        for field in all_fields {
+            if let Some(OptionValue::String(json_name)) = field.options.get("json_name") {
+                if json_name.is_empty() {
+                    errors.push(
+                        ValidationError::error(
+                            "empty-json-name",
+                            format!(
+                                "json_name of field '{}' in message '{}' cannot be empty",
+                                field.name, message.name
+                            ),
+                        )
+                        .at(field.span),
+                    );
+                    continue;
                 }
             }
+
+            let json_name = field.json_name();
+            if let Some(previous) = json_names.get(&json_name) {
+                // Same-named fields are already reported as duplicates
+                if previous.name == field.name {
+                    continue;
+                }
+
+                errors.push(
+                    ValidationError::error(
+                        "json-name-conflict",
+                        format!(
+                            "JSON name '{}' of field '{}' conflicts with field '{}' in message '{}'",
+                            json_name, field.name, previous.name, message.name
+                        ),
+                    )
+                    .at(field.name_span)
+                    .related_to(
+                        previous.name_span,
+                        &format!("'{}' also maps to '{}'", previous.name, json_name),
+                    ),
+                );
+            } else {
+                json_names.insert(json_name, field);
+            }
+        }
+
+        // Map fields can't be oneof members
+        for oneof in &message.oneofs {
+            for field in oneof.fields.iter().filter(|field| field.map_type.is_some()) {
+                errors.push(
+                    ValidationError::error(
+                        "map-in-oneof",
+                        format!(
+                            "Map field '{}' is not allowed in oneof '{}' of message '{}'",
+                            field.name, oneof.name, message.name
+                        ),
+                    )
+                    .at(field.type_span),
+                );
+            }
         }
 
-        // Validate nested messages
+        // Validate nested messages, no deeper than the parser allows
         for nested in &message.nested_messages {
-            self.validate_message(nested, errors);
+            if depth < self.max_depth {
+                self.validate_message(nested, &message_key, depth + 1, errors);
+            } else {
+                errors.push(
+                    ValidationError::error(
+                        "nesting-too-deep",
+                        format!(
+                            "Message '{}' is nested more than {} levels deep and was not validated",
+                            nested.name, self.max_depth
+                        ),
+                    )
+                    .at(nested.name_span),
+                );
+            }
         }
 
         // Validate nested enums
         for nested in &message.nested_enums {
-            self.validate_enum(nested, errors);
+            self.validate_enum(nested, &message_key, errors);
+        }
+    }
+
+    /// Checks that field numbers are unique across a message's direct and
+    /// oneof fields. Each clash is reported on both fields, linked to each
+    /// other; a number used three times reports its first use once.
+    fn check_duplicate_field_numbers(
+        message: &Message,
+        field_numbers: &mut HashMap<u32, Span>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let mut fields: Vec<&Field> = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()))
+            .collect();
+        fields.sort_by_key(|field| field.span.start);
+
+        let mut reported_first = HashSet::new();
+        for field in fields {
+            let Some(first_span) = field_numbers.get(&field.number) else {
+                field_numbers.insert(field.number, field.span);
+                continue;
+            };
+
+            let message_text = format!(
+                "Duplicate field number {} in message '{}'",
+                field.number, message.name
+            );
+            if reported_first.insert(field.number) {
+                errors.push(
+                    ValidationError::error("duplicate-field-number", message_text.clone())
+                        .at(*first_span)
+                        .related_to(field.span, &format!("also used by '{}'", field.name)),
+                );
+            }
+            errors.push(
+                ValidationError::error("duplicate-field-number", message_text)
+                    .at(field.span)
+                    .related_to(*first_span, "first used here"),
+            );
+        }
+    }
+
+    /// Flags fields, including oneof members, that reuse the name of an
+    /// earlier field in the same message.
+    fn check_duplicate_field_names(message: &Message, errors: &mut Vec<ValidationError>) {
+        let mut fields: Vec<&Field> = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()))
+            .collect();
+        fields.sort_by_key(|field| field.span.start);
+
+        let mut names: HashMap<&str, Span> = HashMap::new();
+        for field in fields {
+            if let Some(&first) = names.get(field.name.as_str()) {
+                errors.push(
+                    ValidationError::error(
+                        "duplicate-field-name",
+                        format!(
+                            "Duplicate field name '{}' in message '{}'",
+                            field.name, message.name
+                        ),
+                    )
+                    .at(field.name_span)
+                    .related_to(first, "first used here"),
+                );
+            } else {
+                names.insert(&field.name, field.name_span);
+            }
+        }
+    }
+
+    fn validate_package(package: &str, span: Span, errors: &mut Vec<ValidationError>) {
+        let segments: Vec<&str> = package.split('.').collect();
+
+        if segments.iter().any(|segment| segment.is_empty()) {
+            errors.push(
+                ValidationError::error(
+                    "invalid-package-name",
+                    format!("Package name '{package}' contains an empty segment"),
+                )
+                .at(span),
+            );
+        } else if let Some(segment) = segments
+            .iter()
+            .find(|segment| segment.chars().any(|ch| ch.is_uppercase()))
+        {
+            errors.push(
+                ValidationError::warning(
+                    "package-name-case",
+                    format!("Package name '{package}' should be lowercase (segment '{segment}')"),
+                )
+                .at(span),
+            );
         }
     }
 
-    fn validate_enum(&mut self, enum_def: &Enum, errors: &mut Vec<ValidationError>) {
-        let enum_key = enum_def.name.clone();
+    fn validate_import(
+        path: &str,
+        path_span: Span,
+        public: bool,
+        weak: bool,
+        span: Span,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if path.is_empty() {
+            errors.push(
+                ValidationError::error(
+                    "empty-import-path",
+                    "Import path cannot be empty".to_string(),
+                )
+                .at(path_span),
+            );
+        } else if !path.ends_with(".proto") {
+            // Allowed, but almost always a missing extension
+            errors.push(
+                ValidationError::warning(
+                    "import-missing-extension",
+                    format!("Import '{path}' doesn't end in '.proto'"),
+                )
+                .at(path_span)
+                .replace_with(path_span, &format!("\"{path}.proto\"")),
+            );
+        }
+
+        if public && weak {
+            errors.push(
+                ValidationError::error(
+                    "invalid-import-modifier",
+                    format!("Import '{path}' cannot be both public and weak"),
+                )
+                .at(span),
+            );
+        } else if weak {
+            errors.push(
+                ValidationError::warning(
+                    "weak-import",
+                    format!("Weak import '{path}' is discouraged; use a regular import instead"),
+                )
+                .at(span),
+            );
+        }
+    }
+
+    /// Flags `[packed = true]` on a field that isn't a repeated field of a
+    /// numeric, bool, or enum type. Types declared in other files are given
+    /// the benefit of the doubt.
+    fn check_packed(
+        field: &Field,
+        message: &Message,
+        declared_types: &HashSet<&str>,
+        declared_enums: &HashSet<&str>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if field.options.get("packed") != Some(&OptionValue::Bool(true)) {
+            return;
+        }
+
+        let problem = if field.label != Some(FieldLabel::Repeated) || field.map_type.is_some() {
+            "it is not repeated"
+        } else if matches!(field.field_type.as_str(), "string" | "bytes")
+            || (declared_types.contains(field.field_type.as_str())
+                && !declared_enums.contains(field.field_type.as_str()))
+        {
+            "only repeated numeric, bool, and enum fields can be packed"
+        } else {
+            return;
+        };
+
+        errors.push(
+            ValidationError::error(
+                "invalid-packed-option",
+                format!(
+                    "Field '{}' in message '{}' cannot use 'packed': {}",
+                    field.name, message.name, problem
+                ),
+            )
+            .at(field.name_span),
+        );
+    }
+
+    /// Flags a built-in option set more than once in a field's option list.
+    /// Custom options are skipped, since repeated ones may legally appear
+    /// several times.
+    fn check_duplicate_options(
+        field: &Field,
+        message: &Message,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+        for option in &field.option_names {
+            if option.name.starts_with('(') {
+                continue;
+            }
+
+            if let Some(&first) = seen.get(option.name.as_str()) {
+                errors.push(
+                    ValidationError::error(
+                        "duplicate-option",
+                        format!(
+                            "Option '{}' is set more than once on field '{}' in message '{}'",
+                            option.name, field.name, message.name
+                        ),
+                    )
+                    .at(option.span)
+                    .related_to(first, "first set here"),
+                );
+            } else {
+                seen.insert(&option.name, option.span);
+            }
+        }
+    }
+
+    /// Flags built-in options whose value has the wrong type, e.g.
+    /// `[deprecated = "yes"]` or `[json_name = 5]`.
+    fn check_option_types(field: &Field, message: &Message, errors: &mut Vec<ValidationError>) {
+        for (name, expected) in [
+            ("deprecated", "bool"),
+            ("packed", "bool"),
+            ("json_name", "string"),
+        ] {
+            let Some(value) = field.options.get(name) else {
+                continue;
+            };
+            if option_value_kind(value) == expected {
+                continue;
+            }
+
+            // `options` keeps the last value, so point at the last occurrence
+            let span = field
+                .option_names
+                .iter()
+                .rev()
+                .find(|option| option.name == name)
+                .map_or(field.name_span, |option| option.span);
+            errors.push(
+                ValidationError::error(
+                    "invalid-option-value",
+                    format!(
+                        "Option '{}' on field '{}' in message '{}' must be a {}, found {}",
+                        name,
+                        field.name,
+                        message.name,
+                        expected,
+                        option_value_text(value)
+                    ),
+                )
+                .at(span),
+            );
+        }
+    }
+
+    /// Flags a field type that doesn't name a declared type but is either a
+    /// bare `map` or one or two edits away from a scalar, e.g. `strnig`.
+    fn check_field_type(
+        field: &Field,
+        message: &Message,
+        declared_types: &HashSet<&str>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let (type_name, span) = match &field.map_type {
+            Some(map_type) => (map_type.value_type.as_str(), map_type.value_span),
+            None => (field.field_type.as_str(), field.type_span),
+        };
+        if declared_types.contains(type_name) {
+            return;
+        }
+
+        // `map` is only a keyword when followed by `<`
+        if field.map_type.is_none() && type_name == "map" {
+            errors.push(
+                ValidationError::error(
+                    "map-missing-types",
+                    format!(
+                        "Field '{}' in message '{}' has type 'map' without key and value types; use 'map<KeyType, ValueType>'",
+                        field.name, message.name
+                    ),
+                )
+                .at(span),
+            );
+        } else if let Some(scalar) = suggest_scalar_type(type_name) {
+            errors.push(
+                ValidationError::warning(
+                    "scalar-type-typo",
+                    format!(
+                        "Unknown type '{type_name}' for field '{}'; did you mean '{scalar}'?",
+                        field.name
+                    ),
+                )
+                .at(span)
+                .replace_with(span, scalar),
+            );
+        }
+    }
+
+    /// Flags inverted `reserved` ranges (`10 to 5`) and ranges that overlap
+    /// an earlier one in the same message.
+    fn validate_reserved_ranges(message: &Message, errors: &mut Vec<ValidationError>) {
+        let mut seen: Vec<&NumberRange> = Vec::new();
+
+        for range in &message.reserved_ranges {
+            if range.start > range.end {
+                errors.push(
+                    ValidationError::error(
+                        "invalid-reserved-range",
+                        format!(
+                            "Reserved range {} to {} in message '{}' ends before it starts",
+                            range.start, range.end, message.name
+                        ),
+                    )
+                    .at(range.span),
+                );
+                continue;
+            }
+
+            if let Some(previous) = seen
+                .iter()
+                .find(|previous| previous.start <= range.end && range.start <= previous.end)
+            {
+                errors.push(
+                    ValidationError::error(
+                        "overlapping-reserved-range",
+                        format!(
+                            "Reserved range {} in message '{}' overlaps range {}",
+                            range_text(range),
+                            message.name,
+                            range_text(previous)
+                        ),
+                    )
+                    .at(range.span)
+                    .related_to(previous.span, "overlapping range reserved here"),
+                );
+            }
+            seen.push(range);
+        }
+    }
+
+    /// Reserved names stand in for field names, so each must be a legal
+    /// identifier: `reserved "1foo";` can never match a field.
+    fn validate_reserved_names(message: &Message, errors: &mut Vec<ValidationError>) {
+        for reserved in &message.reserved_names {
+            if !is_valid_identifier(&reserved.name) {
+                errors.push(
+                    ValidationError::error(
+                        "invalid-reserved-name",
+                        format!(
+                            "Reserved name '{}' in message '{}' is not a valid identifier",
+                            reserved.name, message.name
+                        ),
+                    )
+                    .at(reserved.span),
+                );
+            }
+        }
+    }
+
+    fn validate_map_field(
+        field: &Field,
+        map_type: &MapType,
+        message: &Message,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if !MAP_KEY_TYPES.contains(&map_type.key_type.as_str()) {
+            errors.push(
+                ValidationError::error(
+                    "invalid-map-key",
+                    format!(
+                        "Map field '{}' in message '{}' has key type '{}'; keys must be an integral type, bool, or string",
+                        field.name, message.name, map_type.key_type
+                    ),
+                )
+                .at(map_type.key_span),
+            );
+        }
+
+        if MapType::is_map(&map_type.value_type) {
+            errors.push(
+                ValidationError::error(
+                    "invalid-map-value",
+                    format!(
+                        "Map field '{}' in message '{}' cannot have a map as its value type",
+                        field.name, message.name
+                    ),
+                )
+                .at(map_type.value_span),
+            );
+        }
+    }
+
+    fn validate_enum(&mut self, enum_def: &Enum, scope: &str, errors: &mut Vec<ValidationError>) {
+        let enum_key = qualified_name(scope, &enum_def.name);
+        check_type_name("Enum", &enum_def.name, enum_def.name_span, errors);
+        check_all_features(&enum_def.options, errors);
+        check_enum_options(enum_def, errors);
+        let allow_alias = enum_def.options.get("allow_alias") == Some(&OptionValue::Bool(true));
         let enum_values = self.used_enum_values.entry(enum_key).or_default();
 
         let mut has_zero = false;
 
         for value in &enum_def.values {
-            if !enum_values.insert(value.number) {
-                errors.push(ValidationError::new(format!(
-                    "Duplicate enum value {} in enum '{}'",
-                    value.number, enum_def.name
-                )));
+            if let Some(&first) = enum_values.get(&value.number) {
+                // `allow_alias` lets several names share a number
+                if !allow_alias {
+                    errors.push(
+                        ValidationError::error(
+                            "duplicate-enum-value",
+                            format!(
+                                "Duplicate enum value {} in enum '{}'",
+                                value.number, enum_def.name
+                            ),
+                        )
+                        .at(value.span)
+                        .related_to(first, "first used here"),
+                    );
+                }
+            } else {
+                enum_values.insert(value.number, value.span);
             }
 
             if value.number == 0 {
                 has_zero = true;
             }
+            check_all_features(&value.options, errors);
+
+            let number = i64::from(value.number);
+            if let Some(range) = enum_def
+                .reserved_ranges
+                .iter()
+                .find(|range| range.start <= number && number <= range.end)
+            {
+                errors.push(
+                    ValidationError::error(
+                        "reserved-enum-number",
+                        format!(
+                            "Enum value '{}' in enum '{}' uses number {}, which is reserved ({})",
+                            value.name,
+                            enum_def.name,
+                            value.number,
+                            range_text(range)
+                        ),
+                    )
+                    .at(value.span)
+                    .related_to(range.span, "reserved here"),
+                );
+            }
+
+            if let Some(reserved) = enum_def
+                .reserved_names
+                .iter()
+                .find(|reserved| reserved.name == value.name)
+            {
+                errors.push(
+                    ValidationError::error(
+                        "reserved-enum-name",
+                        format!(
+                            "Enum value '{}' in enum '{}' uses a reserved name",
+                            value.name, enum_def.name
+                        ),
+                    )
+                    .at(value.name_span)
+                    .related_to(reserved.span, "reserved here"),
+                );
+            }
+        }
+
+        if enum_def.values.is_empty() {
+            errors.push(
+                ValidationError::error(
+                    "empty-enum",
+                    format!("Enum '{}' must have at least one value", enum_def.name),
+                )
+                .at(enum_def.name_span),
+            );
         }
 
         // In proto3, enums must have a zero value
         if !has_zero && !enum_def.values.is_empty() {
-            errors.push(ValidationError::new(format!(
-                "Enum '{}' must have a zero value",
-                enum_def.name
-            )));
+            errors.push(
+                ValidationError::error(
+                    "missing-enum-zero",
+                    format!("Enum '{}' must have a zero value", enum_def.name),
+                )
+                .at(enum_def.name_span),
+            );
+        }
+    }
+
+    /// Whether `type_name` names an enum declared in this file, either by its
+    /// simple name or by a path starting at one of the file's messages.
+    fn is_local_enum(&self, type_name: &str) -> bool {
+        let mut segments = type_name.split('.');
+        let first = segments.next().unwrap_or_default();
+        match segments.next_back() {
+            None => self.declared_enums.contains(first),
+            Some(last) => {
+                self.declared_types.contains(first)
+                    && !self.declared_enums.contains(first)
+                    && self.declared_enums.contains(last)
+            }
         }
     }
 
     fn validate_service(&mut self, service: &Service, errors: &mut Vec<ValidationError>) {
-        let mut method_names = HashSet::new();
+        let mut method_names: HashMap<&str, Span> = HashMap::new();
+        // Lowercased names, since some HTTP/JSON mappings ignore case
+        let mut folded_names: HashMap<String, &Method> = HashMap::new();
 
+        check_all_features(&service.options, errors);
         for method in &service.methods {
-            if !method_names.insert(&method.name) {
-                errors.push(ValidationError::new(format!(
-                    "Duplicate method name '{}' in service '{}'",
-                    method.name, service.name
-                )));
+            check_all_features(&method.options, errors);
+            if let Some(&first) = method_names.get(method.name.as_str()) {
+                errors.push(
+                    ValidationError::error(
+                        "duplicate-method-name",
+                        format!(
+                            "Duplicate method name '{}' in service '{}'",
+                            method.name, service.name
+                        ),
+                    )
+                    .at(method.name_span)
+                    .related_to(first, "first used here"),
+                );
+            } else {
+                method_names.insert(&method.name, method.name_span);
+            }
+
+            match folded_names.get(&method.name.to_lowercase()) {
+                Some(other) if other.name != method.name => errors.push(
+                    ValidationError::warning(
+                        "method-name-case-conflict",
+                        format!(
+                            "Method '{}' differs from '{}' in service '{}' only by case",
+                            method.name, other.name, service.name
+                        ),
+                    )
+                    .at(method.name_span)
+                    .related_to(other.name_span, &format!("'{}' declared here", other.name)),
+                ),
+                Some(_) => {}
+                None => {
+                    folded_names.insert(method.name.to_lowercase(), method);
+                }
+            }
+
+            let signature = [
+                ("Request", &method.request_type, method.request_type_span),
+                ("Response", &method.response_type, method.response_type_span),
+            ];
+            for (role, type_name, span) in signature {
+                let kind = if SCALAR_TYPES.contains(&type_name.as_str()) {
+                    "a scalar type"
+                } else if self.is_local_enum(type_name) {
+                    "an enum"
+                } else {
+                    continue;
+                };
+                errors.push(
+                    ValidationError::error(
+                        "invalid-rpc-type",
+                        format!(
+                            "{} type of rpc '{}' must be a message, but '{}' is {}",
+                            role, method.name, type_name, kind
+                        ),
+                    )
+                    .at(span),
+                );
+            }
+
+            if let Some(value) = method.options.get("idempotency_level") {
+                let valid = matches!(value, OptionValue::Identifier(level)
+                    if IDEMPOTENCY_LEVELS.contains(&level.as_str()));
+                if !valid {
+                    errors.push(
+                        ValidationError::error(
+                            "invalid-idempotency-level",
+                            format!(
+                                "Invalid idempotency_level {} on method '{}'; expected one of {}",
+                                option_value_text(value),
+                                method.name,
+                                IDEMPOTENCY_LEVELS.join(", ")
+                            ),
+                        )
+                        .at(method.name_span),
+                    );
+                }
             }
         }
     }
@@ -0,0 +1,200 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn binary() -> Command {
+        Command::new(env!("CARGO_BIN_EXE_protobuf-edition-lsp"))
+    }
+
+    #[test]
+    fn test_check_reports_errors() {
+        let mut file = tempfile::Builder::new()
+            .suffix(".proto")
+            .tempfile()
+            .unwrap();
+        write!(
+            file,
+            r#"syntax = "proto3";
+
+message Test {{
+  string field1 = 1;
+  int32 field2 = 1;
+}}
+"#
+        )
+        .unwrap();
+
+        let path = file.path().to_str().unwrap().to_string();
+        let output = binary().args(["--check", &path]).output().unwrap();
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(&format!("{path}:")));
+        assert!(stderr.to_lowercase().contains("duplicate field number"));
+    }
+
+    #[test]
+    fn test_check_clean_file_from_stdin() {
+        let mut child = binary()
+            .args(["--check", "-"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"edition = \"2023\";\n\nmessage Test {\n  string name = 1;\n}\n")
+            .unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty());
+    }
+
+    #[test]
+    fn test_serve_over_socket() {
+        // Reserve a free port, then hand it to the server
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let mut child = binary()
+            .args(["--socket", &port.to_string()])
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let stream = (0..50)
+            .find_map(|_| {
+                TcpStream::connect(("127.0.0.1", port)).ok().or_else(|| {
+                    thread::sleep(Duration::from_millis(100));
+                    None
+                })
+            })
+            .expect("server did not start listening");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#;
+        let mut writer = stream.try_clone().unwrap();
+        write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(length) = header.strip_prefix("Content-Length: ") {
+                content_length = length.parse().unwrap();
+            }
+        }
+
+        let mut response = vec![0; content_length];
+        reader.read_exact(&mut response).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let response: serde_json::Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(response["id"], 1);
+        assert!(response["result"]["capabilities"]["hoverProvider"]
+            .as_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn test_format_rewrites_file() {
+        let mut file = tempfile::Builder::new()
+            .suffix(".proto")
+            .tempfile()
+            .unwrap();
+        write!(
+            file,
+            "edition   = \"2023\";\nmessage Test{{\nstring name=1;   // the name\n\n\n  repeated int32 ids = 2 [ packed = true ];}}"
+        )
+        .unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let expected = "edition = \"2023\";\nmessage Test {\n  string name = 1; // the name\n\n  repeated int32 ids = 2 [packed = true];\n}\n";
+
+        let output = binary()
+            .args(["--format", &path, "--stdout"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+        // In place, and stable on a second run
+        for _ in 0..2 {
+            let output = binary().args(["--format", &path]).output().unwrap();
+            assert!(output.status.success());
+            assert!(output.stdout.is_empty());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_format_leaves_broken_file_alone() {
+        let mut file = tempfile::Builder::new()
+            .suffix(".proto")
+            .tempfile()
+            .unwrap();
+        write!(file, "message Test {{\n  string name = ;\n").unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+
+        let output = binary().args(["--format", &path]).output().unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr).unwrap().contains(&path));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "message Test {\n  string name = ;\n"
+        );
+    }
+
+    #[test]
+    fn test_check_json_output() {
+        let mut file = tempfile::Builder::new()
+            .suffix(".proto")
+            .tempfile()
+            .unwrap();
+        write!(
+            file,
+            "syntax = \"proto3\";\n\nmessage Test {{\n  string name = 1;\n  int32 name = 2;\n}}\n"
+        )
+        .unwrap();
+
+        let path = file.path().to_str().unwrap().to_string();
+        let output = binary()
+            .args(["--check", &path, "--format", "json"])
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        let diagnostics: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(
+            diagnostics,
+            serde_json::json!([{
+                "file": path,
+                "line": 5,
+                "column": 9,
+                "severity": "error",
+                "code": "duplicate-field-name",
+                "message": "Duplicate field name 'name' in message 'Test'",
+            }])
+        );
+    }
+}
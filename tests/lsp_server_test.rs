@@ -99,4 +99,2320 @@ message Test {
 
         assert!(hover.is_some());
     }
+
+    #[test]
+    fn test_disabled_rule_suppresses_diagnostic() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_diagnostics_with_settings;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let content = r#"
+message Test {
+  string field1 = 1;
+  int32 field2 = 1;
+}
+"#;
+
+        let settings = Settings::from_value(serde_json::json!({
+            "protobuf": { "disabledRules": ["duplicate-field-number"] }
+        }));
+        let diagnostics = compute_diagnostics_with_settings(content, &settings);
+
+        assert!(!diagnostics
+            .iter()
+            .any(|d| d.message.to_lowercase().contains("duplicate field number")));
+    }
+
+    #[test]
+    fn test_invalid_setting_keeps_other_settings() {
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let settings = Settings::from_value(serde_json::json!({
+            "protobuf": {
+                "disabledRules": ["duplicate-field-number"],
+                "importRoots": ["/protos"],
+                "maxFileSize": "8MB"
+            }
+        }));
+
+        assert!(!settings.is_rule_enabled("duplicate-field-number"));
+        assert_eq!(
+            settings.import_roots,
+            vec![std::path::PathBuf::from("/protos")]
+        );
+        assert_eq!(settings.max_file_size, Settings::default().max_file_size);
+    }
+
+    #[tokio::test]
+    async fn test_did_change_configuration_applies_to_diagnostics() {
+        use tower_lsp::{LanguageServer, LspService};
+
+        let (service, _socket) = LspService::new(ProtobufLanguageServer::new);
+        let server = service.inner();
+        let uri = Url::parse("file:///test.proto").unwrap();
+
+        server
+            .did_change_configuration(DidChangeConfigurationParams {
+                settings: serde_json::json!({ "disabledRules": ["duplicate-field-number"] }),
+            })
+            .await;
+        server
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "protobuf".to_string(),
+                    version: 1,
+                    text:
+                        "edition = \"2023\";\nmessage Test {\n  string a = 1;\n  int32 b = 1;\n}\n"
+                            .to_string(),
+                },
+            })
+            .await;
+
+        let report = server
+            .diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .await
+            .unwrap();
+
+        match report {
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(full)) => {
+                assert!(full.full_document_diagnostic_report.items.is_empty());
+            }
+            other => panic!("unexpected report: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_compute_selection_ranges() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_selection_ranges;
+
+        let content = r#"message Person {
+  string name = 1;
+}
+"#;
+
+        let ranges = compute_selection_ranges(
+            content,
+            &[Position {
+                line: 1,
+                character: 10,
+            }],
+        );
+        assert_eq!(ranges.len(), 1);
+
+        let mut chain = Vec::new();
+        let mut current = Some(&ranges[0]);
+        while let Some(range) = current {
+            chain.push(range.range);
+            current = range.parent.as_deref();
+        }
+
+        let range = |start: (u32, u32), end: (u32, u32)| Range {
+            start: Position {
+                line: start.0,
+                character: start.1,
+            },
+            end: Position {
+                line: end.0,
+                character: end.1,
+            },
+        };
+        assert_eq!(
+            chain,
+            vec![
+                range((1, 9), (1, 13)),
+                range((1, 2), (1, 18)),
+                range((0, 0), (2, 1)),
+                range((0, 0), (3, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hover_shows_resolved_field_features() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = r#"edition = "2023";
+
+option features.field_presence = IMPLICIT;
+
+message Test {
+  string name = 1 [features.field_presence = EXPLICIT];
+  string nickname = 2;
+}
+"#;
+
+        let hover_text = |line, character| {
+            let hover = compute_hover(content, Position { line, character }).unwrap();
+            match hover.contents {
+                HoverContents::Markup(markup) => markup.value,
+                other => panic!("unexpected hover contents: {other:?}"),
+            }
+        };
+
+        let overridden = hover_text(5, 10);
+        assert!(overridden.contains("`field_presence`: `EXPLICIT`"));
+        assert!(overridden.contains("JSON name: `name`"));
+
+        let inherited = hover_text(6, 10);
+        assert!(inherited.contains("`field_presence`: `IMPLICIT`"));
+
+        let file_level = hover_text(0, 12);
+        assert!(file_level.contains("`field_presence`: `IMPLICIT`"));
+        assert!(file_level.contains("`enum_type`: `OPEN`"));
+    }
+
+    #[test]
+    fn test_prepare_rename() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_prepare_rename;
+
+        let content = r#"message Person {
+  string name = 1;
+}
+"#;
+
+        let response = compute_prepare_rename(
+            content,
+            Position {
+                line: 0,
+                character: 10,
+            },
+        );
+        match response {
+            Some(PrepareRenameResponse::RangeWithPlaceholder { range, placeholder }) => {
+                assert_eq!(placeholder, "Person");
+                assert_eq!(range.start, Position::new(0, 8));
+                assert_eq!(range.end, Position::new(0, 14));
+            }
+            other => panic!("unexpected prepare rename response: {other:?}"),
+        }
+
+        // Scalar type keywords cannot be renamed
+        assert!(compute_prepare_rename(content, Position::new(1, 4)).is_none());
+    }
+
+    #[test]
+    fn test_rename_type_updates_references() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_rename;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"message Address {}
+
+message Person {
+  Address home = 1;
+}
+
+service AddressBook {
+  rpc Lookup(Person) returns (Address);
+}
+"#;
+
+        let edit = compute_rename(&uri, content, Position::new(0, 10), "Location").unwrap();
+        let mut edits = edit.changes.unwrap().remove(&uri).unwrap();
+        edits.sort_by_key(|edit| edit.range.start);
+
+        let starts: Vec<_> = edits.iter().map(|edit| edit.range.start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                Position::new(0, 8),
+                Position::new(3, 2),
+                Position::new(7, 30)
+            ]
+        );
+        assert!(edits.iter().all(|edit| edit.new_text == "Location"));
+    }
+
+    #[test]
+    fn test_rename_resolves_references() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_rename;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"message Foo {}
+
+message A {
+  message Inner {}
+  Inner inner = 1;
+  map<string, Foo> foos = 2;
+}
+
+message B {
+  message Inner {}
+  Inner inner = 1;
+  A.Inner other = 2;
+}
+"#;
+        let edit_starts = |position: Position| {
+            let edit = compute_rename(&uri, content, position, "Renamed").unwrap();
+            let mut starts: Vec<_> = edit
+                .changes
+                .unwrap()
+                .remove(&uri)
+                .unwrap()
+                .iter()
+                .map(|edit| edit.range.start)
+                .collect();
+            starts.sort();
+            starts
+        };
+
+        // Map value types are references too
+        assert_eq!(
+            edit_starts(Position::new(0, 9)),
+            vec![Position::new(0, 8), Position::new(5, 14)]
+        );
+
+        // Only references resolving to `A.Inner` are renamed, not `B.Inner`
+        assert_eq!(
+            edit_starts(Position::new(3, 11)),
+            vec![
+                Position::new(3, 10),
+                Position::new(4, 2),
+                Position::new(11, 4)
+            ]
+        );
+
+        // Renaming an outer message also renames qualifying prefixes
+        assert_eq!(
+            edit_starts(Position::new(2, 8)),
+            vec![Position::new(2, 8), Position::new(11, 2)]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_field_number_related_information() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_document_diagnostics;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string field1 = 1;
+  int32 field2 = 1;
+}
+"#;
+
+        // Both fields are reported, each linking to the other
+        let diagnostics = compute_document_diagnostics(&uri, content, &Settings::default());
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].range.start.line, 3);
+        assert_eq!(diagnostics[1].range.start.line, 4);
+        // The range covers the whole field declaration
+        assert_eq!(
+            diagnostics[1].range,
+            Range::new(Position::new(4, 2), Position::new(4, 19))
+        );
+
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].location.uri, uri);
+        assert_eq!(related[0].location.range.start.line, 4);
+        assert_eq!(related[0].message, "also used by 'field2'");
+
+        let related = diagnostics[1].related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].location.range.start.line, 3);
+        assert_eq!(related[0].message, "first used here");
+    }
+
+    #[test]
+    fn test_hover_shows_method_streaming_kind() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = r#"service Chat {
+  rpc Connect(stream Message) returns (stream Message);
+}
+"#;
+
+        let hover = compute_hover(content, Position::new(1, 8)).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("unexpected hover contents");
+        };
+        assert!(markup.value.contains("**rpc Connect**"));
+        assert!(markup.value.contains("Bidirectional streaming"));
+    }
+
+    #[test]
+    fn test_workspace_symbols_across_documents() {
+        use protobuf_edition_lsp::lsp_server::document_store::DocumentStore;
+        use protobuf_edition_lsp::lsp_server::handlers::compute_workspace_symbols;
+
+        let user_uri = Url::parse("file:///user.proto").unwrap();
+        let order_uri = Url::parse("file:///order.proto").unwrap();
+
+        let mut store = DocumentStore::new();
+        store.open_document(
+            user_uri.clone(),
+            "message UserProfile {\n  string user_id = 1;\n}\n".to_string(),
+            1,
+        );
+        store.open_document(
+            order_uri.clone(),
+            "service OrderService {\n  rpc GetUser(Request) returns (Response);\n}\n".to_string(),
+            1,
+        );
+
+        let mut symbols: Vec<_> = compute_workspace_symbols(&store, "USER")
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.location.uri))
+            .collect();
+        symbols.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            symbols,
+            vec![
+                ("GetUser".to_string(), order_uri),
+                ("UserProfile".to_string(), user_uri.clone()),
+                ("user_id".to_string(), user_uri),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_completion_resolve_adds_documentation() {
+        use protobuf_edition_lsp::lsp_server::document_store::DocumentStore;
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_completion_resolve, compute_completions,
+        };
+
+        let content = "message Address {\n  string city = 1;\n}\n\nmessage Person {\n  \n}\n";
+        let completions = compute_completions(content, Position::new(5, 2));
+
+        let int32 = completions
+            .iter()
+            .find(|item| item.label == "int32")
+            .unwrap()
+            .clone();
+        assert!(int32.documentation.is_none());
+
+        let mut store = DocumentStore::new();
+        let resolved = compute_completion_resolve(&store, int32);
+        let Some(Documentation::MarkupContent(markup)) = resolved.documentation else {
+            panic!("expected markdown documentation");
+        };
+        assert!(markup.value.contains("variable-length encoding"));
+
+        let address = completions
+            .iter()
+            .find(|item| item.label == "Address")
+            .unwrap()
+            .clone();
+        store.open_document(
+            Url::parse("file:///test.proto").unwrap(),
+            content.to_string(),
+            1,
+        );
+        let resolved = compute_completion_resolve(&store, address);
+        let Some(Documentation::MarkupContent(markup)) = resolved.documentation else {
+            panic!("expected markdown documentation");
+        };
+        assert!(markup.value.contains("`string city = 1`"));
+    }
+
+    #[test]
+    fn test_completions_out_of_range_positions() {
+        use protobuf_edition_lsp::lsp_server::handlers::{compute_completions, compute_hover};
+
+        let content = "message Test {\n  \n  string 名前 = 1;\n}\n";
+
+        // Past the end of the line behaves like the end of the line
+        let completions = compute_completions(content, Position::new(1, 100));
+        assert!(completions.iter().any(|c| c.label == "string"));
+
+        // Inside a multibyte identifier
+        let completions = compute_completions(content, Position::new(2, 10));
+        assert!(completions.is_empty());
+        assert!(compute_hover(content, Position::new(2, 100)).is_none());
+        assert!(compute_hover(content, Position::new(2, 10)).is_some());
+    }
+
+    #[test]
+    fn test_code_action_converts_optional_label() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_code_actions;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"edition = "2023";
+
+message Test {
+  optional string x = 1;
+}
+"#;
+
+        let actions = compute_code_actions(
+            &uri,
+            content,
+            Range::new(Position::new(3, 4), Position::new(3, 4)),
+            &[],
+        );
+        assert_eq!(actions.len(), 1);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits,
+            &vec![
+                TextEdit {
+                    range: Range::new(Position::new(3, 2), Position::new(3, 11)),
+                    new_text: String::new(),
+                },
+                TextEdit {
+                    range: Range::new(Position::new(3, 23), Position::new(3, 23)),
+                    new_text: " [features.field_presence = EXPLICIT]".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rapid_changes_publish_only_final_diagnostics() {
+        use futures::StreamExt;
+        use std::time::Duration;
+        use tower::{Service, ServiceExt};
+        use tower_lsp::jsonrpc::Request;
+        use tower_lsp::{LanguageServer, LspService};
+
+        let (mut service, mut socket) = LspService::new(ProtobufLanguageServer::new);
+        let initialize = Request::build("initialize")
+            .params(serde_json::json!({ "capabilities": {} }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let text = |field_number: u32| {
+            format!(
+                "syntax = \"proto3\";\nmessage Test {{\n  string a = 1;\n  string b = {field_number};\n}}\n"
+            )
+        };
+
+        service
+            .inner()
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "proto".to_string(), 1, text(2)),
+            })
+            .await;
+
+        for version in 2..=5 {
+            // Only the last version contains an error
+            let field_number = if version == 5 { 1 } else { 2 };
+            service
+                .inner()
+                .did_change(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: text(field_number),
+                    }],
+                })
+                .await;
+        }
+
+        let mut published = Vec::new();
+        while let Ok(Some(request)) =
+            tokio::time::timeout(Duration::from_millis(500), socket.next()).await
+        {
+            if request.method() == "textDocument/publishDiagnostics" {
+                let params: PublishDiagnosticsParams =
+                    serde_json::from_value(request.params().unwrap().clone()).unwrap();
+                published.push(params);
+            }
+        }
+
+        // The initial open publishes immediately; the burst of edits publishes once
+        assert_eq!(published.len(), 2);
+        assert_eq!(published[0].version, Some(1));
+        assert!(published[0].diagnostics.is_empty());
+        assert_eq!(published[1].version, Some(5));
+        assert_eq!(published[1].diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn test_unterminated_comment_diagnostic() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_diagnostics;
+
+        let diagnostics =
+            compute_diagnostics("syntax = \"proto3\";\n/* never closed\nmessage A {}\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]
+            .message
+            .contains("Unterminated block comment starting at line 2, column 1"));
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 0));
+
+        // A comment opening the file fails on the very first token
+        let diagnostics = compute_diagnostics("/* never closed\nmessage A {}\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]
+            .message
+            .contains("Unterminated block comment starting at line 1, column 1"));
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_well_known_type_hover_and_completion() {
+        use protobuf_edition_lsp::lsp_server::handlers::{compute_completions, compute_hover};
+
+        let content = r#"syntax = "proto3";
+
+import "google/protobuf/timestamp.proto";
+
+message Event {
+  google.protobuf.Timestamp created_at = 1;
+  
+}
+"#;
+
+        let hover = compute_hover(content, Position::new(5, 22)).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("unexpected hover contents");
+        };
+        assert!(markup.value.contains("google.protobuf.Timestamp"));
+        assert!(markup.value.contains("seconds"));
+        assert!(markup.value.contains("nanos"));
+
+        let labels: Vec<_> = compute_completions(content, Position::new(6, 2))
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert!(labels.contains(&"google.protobuf.Timestamp".to_string()));
+        assert!(!labels.contains(&"google.protobuf.Duration".to_string()));
+    }
+
+    #[test]
+    fn test_document_links_for_resolvable_imports() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_document_links;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let workspace = tempfile::tempdir().unwrap();
+        let roots = workspace.path().join("third_party");
+        std::fs::create_dir(&roots).unwrap();
+        std::fs::write(roots.join("dep.proto"), "syntax = \"proto3\";\n").unwrap();
+
+        let uri = Url::from_file_path(workspace.path().join("main.proto")).unwrap();
+        let content = r#"syntax = "proto3";
+
+import "dep.proto";
+import "missing.proto";
+"#;
+        let settings = Settings {
+            import_roots: vec![roots.clone()],
+            ..Settings::default()
+        };
+
+        let links = compute_document_links(&uri, content, &settings);
+        assert_eq!(links.len(), 1);
+        assert_eq!(
+            links[0].range,
+            Range::new(Position::new(2, 8), Position::new(2, 17))
+        );
+        assert_eq!(
+            links[0].target,
+            Some(Url::from_file_path(roots.join("dep.proto").canonicalize().unwrap()).unwrap())
+        );
+
+        // Relative roots are resolved against the workspace folders
+        let uri = Url::from_file_path(workspace.path().join("src/main.proto")).unwrap();
+        let settings = Settings {
+            import_roots: vec!["third_party".into()],
+            workspace_folders: vec![workspace.path().to_path_buf()],
+            ..Settings::default()
+        };
+        let links = compute_document_links(&uri, content, &settings);
+        assert_eq!(
+            links[0].target,
+            Some(Url::from_file_path(roots.join("dep.proto").canonicalize().unwrap()).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_type_definition_of_field() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_definition, compute_type_definition,
+        };
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message Address {
+  string city = 1;
+}
+
+message Person {
+  Address addr = 1;
+  string name = 2;
+}
+"#;
+
+        let address = Location::new(
+            uri.clone(),
+            Range::new(Position::new(2, 8), Position::new(2, 15)),
+        );
+
+        // Anywhere on the field, including its name, jumps to the type
+        assert_eq!(
+            compute_type_definition(&uri, content, Position::new(7, 12)),
+            Some(GotoDefinitionResponse::Scalar(address.clone()))
+        );
+        assert_eq!(
+            compute_type_definition(&uri, content, Position::new(8, 10)),
+            None
+        );
+
+        // Plain definition only applies to the type reference itself
+        assert_eq!(
+            compute_definition(&uri, content, Position::new(7, 4)),
+            Some(GotoDefinitionResponse::Scalar(address))
+        );
+        assert_eq!(
+            compute_definition(&uri, content, Position::new(7, 12)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_oneof_symbols_and_folding() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_document_symbols, compute_folding_ranges,
+        };
+
+        let content = r#"syntax = "proto3";
+
+message Shape {
+  string name = 1;
+  oneof kind {
+    double radius = 2;
+    double side = 3;
+  }
+}
+"#;
+
+        let symbols = compute_document_symbols(content);
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "Shape");
+
+        let children = symbols[0].children.as_ref().unwrap();
+        let names: Vec<&str> = children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(names, ["name", "kind"]);
+
+        let oneof = &children[1];
+        assert_eq!(oneof.kind, SymbolKind::STRUCT);
+        assert_eq!(
+            oneof.selection_range,
+            Range::new(Position::new(4, 8), Position::new(4, 12))
+        );
+        let fields: Vec<&str> = oneof
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect();
+        assert_eq!(fields, ["radius", "side"]);
+
+        let folds: Vec<(u32, u32)> = compute_folding_ranges(content)
+            .iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect();
+        assert_eq!(folds, [(2, 8), (4, 7)]);
+    }
+
+    #[test]
+    fn test_scalar_typo_quick_fix() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_code_actions, compute_diagnostics,
+        };
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message Test {
+  strnig name = 1;
+}
+"#;
+
+        let diagnostics = compute_diagnostics(content);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("scalar-type-typo".to_string()))
+        );
+        assert!(diagnostics[0].message.contains("did you mean 'string'?"));
+
+        let actions = compute_code_actions(
+            &uri,
+            content,
+            Range::new(Position::new(3, 2), Position::new(3, 2)),
+            &diagnostics,
+        );
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits,
+            &[TextEdit {
+                range: Range::new(Position::new(3, 2), Position::new(3, 8)),
+                new_text: "string".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_oversized_file_skips_diagnostics() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_document_diagnostics;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        // Would report a duplicate field number if it were analyzed
+        let content = "syntax = \"proto3\";\nmessage Test {\n  string a = 1;\n  string b = 1;\n}\n";
+
+        let settings = Settings {
+            max_file_size: 32,
+            ..Settings::default()
+        };
+        let diagnostics = compute_document_diagnostics(&uri, content, &settings);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("file-too-large".to_string()))
+        );
+
+        let diagnostics = compute_document_diagnostics(&uri, content, &Settings::default());
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    }
+
+    #[test]
+    fn test_idempotency_level_completion() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_completions;
+
+        let content = r#"syntax = "proto3";
+
+service Api {
+  rpc Get(Req) returns (Req) {
+    option idempotency_level = NO
+  }
+}
+"#;
+
+        let completions = compute_completions(content, Position::new(4, 33));
+        let labels: Vec<&str> = completions.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["IDEMPOTENCY_UNKNOWN", "NO_SIDE_EFFECTS", "IDEMPOTENT"]
+        );
+    }
+
+    #[test]
+    fn test_hover_shows_trailing_comment() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = r#"syntax = "proto3";
+
+message User {
+  int32 id = 1; // unique id
+}
+"#;
+
+        let Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) = compute_hover(content, Position::new(3, 9))
+        else {
+            panic!("expected markdown hover");
+        };
+        assert!(markup.value.starts_with("**id**\n\nunique id\n\n"));
+
+        let content = r#"syntax = "proto3";
+
+enum Role {
+  // The default
+  ROLE_UNSPECIFIED = 0; // unset
+}
+"#;
+
+        let Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) = compute_hover(content, Position::new(4, 4))
+        else {
+            panic!("expected markdown hover");
+        };
+        assert_eq!(
+            markup.value,
+            "**ROLE_UNSPECIFIED**\n\nThe default\n\nunset\n\nValue: `0`"
+        );
+    }
+
+    #[test]
+    fn test_require_editions() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_code_actions_with_settings, compute_document_diagnostics,
+        };
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = "syntax = \"proto3\";\n\nmessage Test {\n  string name = 1;\n}\n";
+        let syntax_line = Range::new(Position::new(0, 0), Position::new(0, 0));
+
+        let settings = Settings::default();
+        assert!(compute_document_diagnostics(&uri, content, &settings).is_empty());
+        assert!(
+            compute_code_actions_with_settings(&uri, content, syntax_line, &[], &settings)
+                .is_empty()
+        );
+
+        let settings = Settings {
+            require_editions: true,
+            ..Settings::default()
+        };
+        let diagnostics = compute_document_diagnostics(&uri, content, &settings);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("prefer-editions".to_string()))
+        );
+
+        let actions =
+            compute_code_actions_with_settings(&uri, content, syntax_line, &diagnostics, &settings);
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits,
+            &[TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 18)),
+                new_text: "edition = \"2023\";\n\noption features.field_presence = IMPLICIT;"
+                    .to_string(),
+            }]
+        );
+
+        let crlf = content.replace('\n', "\r\n");
+        let diagnostics = compute_document_diagnostics(&uri, &crlf, &settings);
+        let actions =
+            compute_code_actions_with_settings(&uri, &crlf, syntax_line, &diagnostics, &settings);
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits[0].new_text,
+            "edition = \"2023\";\r\n\r\noption features.field_presence = IMPLICIT;"
+        );
+    }
+
+    #[test]
+    fn test_deprecated_hints() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_diagnostics;
+
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string old_name = 1 [deprecated = true];
+  string name = 2;
+}
+
+service Api {
+  rpc Get(Test) returns (Test) {
+    option deprecated = true;
+  }
+}
+"#;
+
+        let diagnostics = compute_diagnostics(content);
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(3, 9), Position::new(3, 17))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(diagnostics[0].tags, Some(vec![DiagnosticTag::DEPRECATED]));
+        assert_eq!(diagnostics[0].message, "Field 'old_name' is deprecated");
+
+        assert_eq!(diagnostics[1].message, "Method 'Get' is deprecated");
+    }
+
+    #[tokio::test]
+    async fn test_watched_files_update_index() {
+        use tower_lsp::{LanguageServer, LspService};
+
+        let dir = tempfile::tempdir().unwrap();
+        let types_path = dir.path().join("types.proto");
+        let types_uri = Url::from_file_path(&types_path).unwrap();
+        let main_uri = Url::from_file_path(dir.path().join("main.proto")).unwrap();
+
+        let (service, _socket) = LspService::new(ProtobufLanguageServer::new);
+        let server = service.inner();
+        server
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: main_uri.clone(),
+                    language_id: "protobuf".to_string(),
+                    version: 1,
+                    text: "edition = \"2023\";\nimport \"types.proto\";\nmessage Person {\n  Address addr = 1;\n}\n"
+                        .to_string(),
+                },
+            })
+            .await;
+
+        let params = || GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: main_uri.clone(),
+                },
+                position: Position::new(3, 4),
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+        let notify = |typ| DidChangeWatchedFilesParams {
+            changes: vec![FileEvent {
+                uri: types_uri.clone(),
+                typ,
+            }],
+        };
+
+        // Not indexed yet
+        assert_eq!(server.goto_definition(params()).await.unwrap(), None);
+
+        std::fs::write(&types_path, "edition = \"2023\";\n\nmessage Address {}\n").unwrap();
+        server
+            .did_change_watched_files(notify(FileChangeType::CREATED))
+            .await;
+        assert_eq!(
+            server.goto_definition(params()).await.unwrap(),
+            Some(GotoDefinitionResponse::Scalar(Location::new(
+                types_uri.clone(),
+                Range::new(Position::new(2, 8), Position::new(2, 15)),
+            )))
+        );
+
+        std::fs::remove_file(&types_path).unwrap();
+        server
+            .did_change_watched_files(notify(FileChangeType::DELETED))
+            .await;
+        assert_eq!(server.goto_definition(params()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_definition_in_imported_file() {
+        use tower_lsp::{LanguageServer, LspService};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("proto");
+        std::fs::create_dir_all(root.join("common")).unwrap();
+        let types_path = root.join("common/types.proto");
+        std::fs::write(
+            &types_path,
+            "edition = \"2023\";\n\npackage common;\n\nmessage Address {}\n",
+        )
+        .unwrap();
+        let types_uri = Url::from_file_path(&types_path).unwrap();
+        let main_uri = Url::from_file_path(dir.path().join("app/main.proto")).unwrap();
+
+        let (service, _socket) = LspService::new(ProtobufLanguageServer::new);
+        let server = service.inner();
+        server
+            .did_change_configuration(DidChangeConfigurationParams {
+                settings: serde_json::json!({ "importRoots": [root] }),
+            })
+            .await;
+        server
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: main_uri.clone(),
+                    language_id: "protobuf".to_string(),
+                    version: 1,
+                    text: r#"edition = "2023";
+
+package app;
+
+import "common/types.proto";
+import "missing.proto";
+
+message Person {
+  Address home = 1;
+}
+
+service Api {
+  rpc Get(common.Address) returns (Person);
+}
+"#
+                    .to_string(),
+                },
+            })
+            .await;
+
+        let definition_at = |position| GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: main_uri.clone(),
+                },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        // The package-qualified reference resolves into the imported file
+        assert_eq!(
+            server
+                .goto_definition(definition_at(Position::new(12, 14)))
+                .await
+                .unwrap(),
+            Some(GotoDefinitionResponse::Scalar(Location::new(
+                types_uri,
+                Range::new(Position::new(4, 8), Position::new(4, 15)),
+            )))
+        );
+
+        // `Address` alone doesn't name `common.Address` from package `app`
+        assert_eq!(
+            server
+                .goto_definition(definition_at(Position::new(8, 4)))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_range_formatting_only_touches_selected_message() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_formatting, compute_range_formatting,
+        };
+
+        let content = "message First{int32   id=1;}\n\nmessage Second{\nstring  name=1;\n}\n";
+        let options = FormattingOptions {
+            tab_size: 2,
+            insert_spaces: true,
+            ..Default::default()
+        };
+
+        // Select part of the second message only
+        let range = Range::new(Position::new(3, 0), Position::new(3, 5));
+        let edits = compute_range_formatting(content, range, &options);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 0), Position::new(4, 1))
+        );
+
+        let lines: Vec<&str> = content.lines().collect();
+        let formatted = format!("{}\n{}\n{}\n", lines[0], lines[1], edits[0].new_text);
+        assert_eq!(
+            formatted,
+            "message First{int32   id=1;}\n\nmessage Second {\n  string name = 1;\n}\n"
+        );
+
+        // Full formatting reaches both messages
+        let edits = compute_formatting(content, &options);
+        assert_eq!(
+            edits[0].new_text,
+            "message First {\n  int32 id = 1;\n}\n\nmessage Second {\n  string name = 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_hover_on_edition_and_syntax_values() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let hover_text = |content: &str, position| match compute_hover(content, position) {
+            Some(Hover {
+                contents: HoverContents::Markup(markup),
+                range,
+            }) => (markup.value, range),
+            other => panic!("unexpected hover: {other:?}"),
+        };
+
+        let (text, range) = hover_text("edition = \"2023\";\n", Position::new(0, 12));
+        assert!(text.contains("Edition 2023"));
+        assert_eq!(
+            range,
+            Some(Range::new(Position::new(0, 10), Position::new(0, 16)))
+        );
+
+        let (text, _) = hover_text("syntax = \"proto3\";\n", Position::new(0, 12));
+        assert!(text.contains("Syntax proto3"));
+        assert!(text.contains("implicit presence"));
+
+        let (text, _) = hover_text("syntax = \"proto4\";\n", Position::new(0, 12));
+        assert!(text.contains("`proto2`, `proto3`"));
+    }
+
+    #[tokio::test]
+    async fn test_workspace_diagnostics_cover_open_documents() {
+        use tower_lsp::{LanguageServer, LspService};
+
+        let (service, _socket) = LspService::new(ProtobufLanguageServer::new);
+        let server = service.inner();
+
+        for (name, version, text) in [
+            (
+                "a",
+                1,
+                "edition = \"2023\";\nmessage A {\n  string a = 0;\n}\n",
+            ),
+            (
+                "b",
+                4,
+                "edition = \"2023\";\nmessage B {\n  string b = 1;\n  int32 c = 1;\n}\n",
+            ),
+        ] {
+            server
+                .did_open(DidOpenTextDocumentParams {
+                    text_document: TextDocumentItem {
+                        uri: Url::parse(&format!("file:///{name}.proto")).unwrap(),
+                        language_id: "protobuf".to_string(),
+                        version,
+                        text: text.to_string(),
+                    },
+                })
+                .await;
+        }
+
+        let report = server
+            .workspace_diagnostic(WorkspaceDiagnosticParams {
+                identifier: None,
+                previous_result_ids: Vec::new(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .await
+            .unwrap();
+
+        let WorkspaceDiagnosticReportResult::Report(report) = report else {
+            panic!("expected a full workspace report");
+        };
+        let entries: Vec<(String, Option<i64>, Vec<String>)> = report
+            .items
+            .into_iter()
+            .map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => (
+                    full.uri.path().to_string(),
+                    full.version,
+                    full.full_document_diagnostic_report
+                        .items
+                        .into_iter()
+                        .filter_map(|diagnostic| match diagnostic.code {
+                            Some(NumberOrString::String(code)) => Some(code),
+                            _ => None,
+                        })
+                        .collect(),
+                ),
+                other => panic!("unexpected report: {other:?}"),
+            })
+            .collect();
+
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "/a.proto".to_string(),
+                    Some(1),
+                    vec!["invalid-field-number".to_string()]
+                ),
+                (
+                    "/b.proto".to_string(),
+                    Some(4),
+                    vec![
+                        "duplicate-field-number".to_string(),
+                        "duplicate-field-number".to_string()
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rpc_type_completions_offer_messages_only() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_completions;
+
+        let content = "message Request {}\nmessage Response {}\nenum Kind {\n  KIND_UNSPECIFIED = 0;\n}\nservice Api {\n  rpc Foo (\n  rpc Bar(Request) returns (stream Re\n}\n";
+        let labels = |position| -> Vec<String> {
+            compute_completions(content, position)
+                .into_iter()
+                .map(|item| item.label)
+                .collect()
+        };
+
+        let in_request = labels(Position::new(6, 11));
+        assert_eq!(in_request, ["Request", "Response", "stream"]);
+        assert!(!in_request.iter().any(|label| label == "int32"));
+
+        // After `stream`, only the message names remain
+        assert_eq!(labels(Position::new(7, 37)), ["Request", "Response"]);
+    }
+
+    #[test]
+    fn test_edition_and_syntax_value_completions() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_completions;
+
+        let labels = |content: &str, character| -> Vec<String> {
+            compute_completions(content, Position::new(0, character))
+                .into_iter()
+                .map(|item| item.label)
+                .collect()
+        };
+
+        assert_eq!(labels("edition = \"\";\n", 11), ["2023"]);
+        assert_eq!(labels("syntax = \"pro", 13), ["proto2", "proto3"]);
+        // Past the closing quote is no longer inside the value
+        assert!(!labels("edition = \"2023\";", 17).contains(&"2023".to_string()));
+    }
+
+    #[test]
+    fn test_missing_enum_zero_quick_fix() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_code_actions, compute_diagnostics,
+        };
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = "syntax = \"proto3\";\n\nenum HttpStatus {\n  HTTP_STATUS_OK = 1;\n}\n";
+
+        let diagnostics: Vec<Diagnostic> = compute_diagnostics(content)
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("missing-enum-zero".to_string()))
+            })
+            .collect();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 5));
+
+        let actions = compute_code_actions(&uri, content, diagnostics[0].range, &diagnostics);
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "Add 'HTTP_STATUS_UNSPECIFIED = 0'");
+        assert_eq!(
+            action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri],
+            vec![TextEdit {
+                range: Range::new(Position::new(3, 2), Position::new(3, 2)),
+                new_text: "HTTP_STATUS_UNSPECIFIED = 0;\n  ".to_string(),
+            }]
+        );
+
+        // The value's own indentation and line breaks are kept
+        let content =
+            "syntax = \"proto3\";\r\n\r\nenum HttpStatus {\r\n\tHTTP_STATUS_OK = 1;\r\n}\r\n";
+        let diagnostics: Vec<Diagnostic> = compute_diagnostics(content)
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("missing-enum-zero".to_string()))
+            })
+            .collect();
+        let actions = compute_code_actions(&uri, content, diagnostics[0].range, &diagnostics);
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(
+            action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri][0].new_text,
+            "HTTP_STATUS_UNSPECIFIED = 0;\r\n\t"
+        );
+
+        // Without the diagnostic in the request's context there's nothing to fix
+        assert!(compute_code_actions(&uri, content, diagnostics[0].range, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_hover_on_field_option() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = "syntax = \"proto3\";\nmessage Test {\n  string deprecated = 1 [deprecated = true];\n}\n";
+        let hover_text = |character| {
+            compute_hover(content, Position::new(2, character)).map(|hover| match hover.contents {
+                HoverContents::Markup(markup) => markup.value,
+                other => panic!("unexpected hover contents: {other:?}"),
+            })
+        };
+
+        let option = hover_text(28).unwrap();
+        assert!(option.contains("**deprecated** (field option)"));
+        assert!(option.contains("Marks the field as deprecated"));
+        assert!(option.contains("Value: bool"));
+
+        // The field's own name is outside the options block
+        assert!(!hover_text(12).unwrap().contains("field option"));
+    }
+
+    #[tokio::test]
+    async fn test_initial_index_reports_progress() {
+        use futures::{SinkExt, StreamExt};
+        use std::time::Duration;
+        use tower::{Service, ServiceExt};
+        use tower_lsp::jsonrpc::{Request, Response};
+        use tower_lsp::{LanguageServer, LspService};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("common")).unwrap();
+        std::fs::write(root.join("common/status.proto"), "enum Status {}\n").unwrap();
+        std::fs::write(root.join("types.proto"), "message Address {}\n").unwrap();
+
+        let (mut service, socket) = LspService::new(ProtobufLanguageServer::new);
+        let initialize = Request::build("initialize")
+            .params(serde_json::json!({
+                "capabilities": { "window": { "workDoneProgress": true } },
+                "rootUri": Url::from_file_path(&root).unwrap(),
+            }))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        // Play the client: accept every request and record the traffic
+        let (mut requests, mut responses) = socket.split();
+        let client = tokio::spawn(async move {
+            let mut messages = Vec::new();
+            while let Ok(Some(request)) =
+                tokio::time::timeout(Duration::from_millis(500), requests.next()).await
+            {
+                if let Some(id) = request.id() {
+                    let response = Response::from_ok(id.clone(), serde_json::Value::Null);
+                    responses.send(response).await.unwrap();
+                }
+                messages.push((request.method().to_string(), request.params().cloned()));
+            }
+            messages
+        });
+
+        service.inner().initialized(InitializedParams {}).await;
+        let messages = client.await.unwrap();
+
+        let position = |method: &str, kind: Option<&str>| {
+            messages.iter().position(|(m, params)| {
+                m == method
+                    && kind.is_none_or(|kind| {
+                        params.as_ref().unwrap()["value"]["kind"] == serde_json::json!(kind)
+                    })
+            })
+        };
+        let create = position("window/workDoneProgress/create", None).unwrap();
+        let begin = position("$/progress", Some("begin")).unwrap();
+        let end = position("$/progress", Some("end")).unwrap();
+        assert!(create < begin && begin < end);
+
+        let (_, begin_params) = &messages[begin];
+        assert_eq!(
+            begin_params.as_ref().unwrap()["value"]["title"],
+            "Indexing protobuf files"
+        );
+        let (_, end_params) = &messages[end];
+        assert_eq!(
+            end_params.as_ref().unwrap()["value"]["message"],
+            "Indexed 2 files"
+        );
+
+        // Files that were never opened are now available for lookups
+        let main_uri = Url::from_file_path(root.join("main.proto")).unwrap();
+        service
+            .inner()
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    main_uri.clone(),
+                    "protobuf".to_string(),
+                    1,
+                    "edition = \"2023\";\nimport \"types.proto\";\nmessage Person {\n  Address addr = 1;\n}\n"
+                        .to_string(),
+                ),
+            })
+            .await;
+        let definition = service
+            .inner()
+            .goto_definition(GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: main_uri },
+                    position: Position::new(3, 4),
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            definition,
+            Some(GotoDefinitionResponse::Scalar(Location::new(
+                Url::from_file_path(root.join("types.proto")).unwrap(),
+                Range::new(Position::new(0, 8), Position::new(0, 15)),
+            )))
+        );
+    }
+
+    #[test]
+    fn test_hover_joins_leading_doc_comments() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = r#"syntax = "proto3";
+
+message User {
+  // The user's display name, shown
+  // next to their avatar.
+  //
+  // Limited to 64 characters.
+  string name = 1; // required
+}
+"#;
+
+        let Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) = compute_hover(content, Position::new(7, 10))
+        else {
+            panic!("expected markdown hover");
+        };
+        assert!(markup.value.starts_with(
+            "**name**\n\nThe user's display name, shown next to their avatar.\n\n\
+             Limited to 64 characters.\n\nrequired\n\n"
+        ));
+
+        let content = r#"syntax = "proto3";
+
+// A person using the system.
+//
+// Created on sign-up.
+message User {
+  // What they may do
+  enum Role {
+    ROLE_UNSPECIFIED = 0;
+  }
+}
+
+// Looks up
+// users.
+service Users {}
+"#;
+        let hover = |line, character| match compute_hover(content, Position::new(line, character)) {
+            Some(Hover {
+                contents: HoverContents::Markup(markup),
+                range,
+            }) => (markup.value, range),
+            other => panic!("expected markdown hover, got {other:?}"),
+        };
+        assert_eq!(
+            hover(5, 9),
+            (
+                "**message User**\n\nA person using the system.\n\nCreated on sign-up.".to_string(),
+                Some(Range::new(Position::new(5, 8), Position::new(5, 12)))
+            )
+        );
+        assert_eq!(hover(7, 8).0, "**enum Role**\n\nWhat they may do");
+        assert_eq!(hover(14, 10).0, "**service Users**\n\nLooks up users.");
+    }
+
+    #[test]
+    fn test_parse_error_diagnostic_position() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_diagnostics;
+
+        let content =
+            "syntax = \"proto3\";\n\nmessage Test {\n  string name = 1;\n  int32 id = ;\n}\n";
+        let diagnostics = compute_diagnostics(content);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(4, 13), Position::new(4, 14))
+        );
+        assert!(diagnostics[0].message.starts_with("Parse error: "));
+    }
+
+    #[test]
+    fn test_organize_imports() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_organize_imports;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"edition = "2023";
+
+package demo;
+
+import "b.proto";
+import public "a.proto";
+import weak "c.proto";
+import "b.proto";
+
+message Test {}
+import "a.proto";
+"#;
+        let edit = compute_organize_imports(&uri, content).unwrap();
+        let mut edits = edit.changes.unwrap().remove(&uri).unwrap();
+
+        // Apply from the bottom up so earlier ranges stay valid
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        lines.push(String::new());
+        let mut text = lines.join("\n");
+        let offset = |text: &str, position: Position| {
+            text.split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum::<usize>()
+                + position.character as usize
+        };
+        for edit in edits {
+            let start = offset(&text, edit.range.start);
+            let end = offset(&text, edit.range.end);
+            text.replace_range(start..end, &edit.new_text);
+        }
+
+        assert_eq!(
+            text,
+            r#"edition = "2023";
+
+package demo;
+
+import public "a.proto";
+import "b.proto";
+import weak "c.proto";
+
+message Test {}
+"#
+        );
+
+        // Nothing left to do on the organized file
+        assert_eq!(compute_organize_imports(&uri, &text), None);
+
+        // CRLF files keep their line breaks
+        let crlf = "import \"b.proto\";\r\nimport \"a.proto\";\r\n";
+        let edit = compute_organize_imports(&uri, crlf).unwrap();
+        let edits = edit.changes.unwrap().remove(&uri).unwrap();
+        assert_eq!(
+            edits[0].new_text,
+            "import \"a.proto\";\r\nimport \"b.proto\";"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_workspace_folders_are_import_roots() {
+        use tower_lsp::{LanguageServer, LspService};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (app, shared) = (root.join("app"), root.join("shared"));
+        std::fs::create_dir_all(shared.join("common")).unwrap();
+        std::fs::create_dir_all(&app).unwrap();
+        let types_path = shared.join("common/types.proto");
+        std::fs::write(&types_path, "message Address {}\n").unwrap();
+
+        let folder = |path: &std::path::Path| WorkspaceFolder {
+            uri: Url::from_file_path(path).unwrap(),
+            name: path.file_name().unwrap().to_string_lossy().into_owned(),
+        };
+        let (service, _socket) = LspService::new(ProtobufLanguageServer::new);
+        let server = service.inner();
+        let result = server
+            .initialize(InitializeParams {
+                workspace_folders: Some(vec![folder(&app), folder(&shared)]),
+                ..InitializeParams::default()
+            })
+            .await
+            .unwrap();
+        let workspace = result.capabilities.workspace.unwrap();
+        assert_eq!(workspace.workspace_folders.unwrap().supported, Some(true));
+
+        // `common/types.proto` only resolves relative to the `shared` folder
+        let main_uri = Url::from_file_path(app.join("main.proto")).unwrap();
+        server
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    main_uri.clone(),
+                    "protobuf".to_string(),
+                    1,
+                    "edition = \"2023\";\nimport \"common/types.proto\";\n".to_string(),
+                ),
+            })
+            .await;
+        let links = || async {
+            server
+                .document_link(DocumentLinkParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: main_uri.clone(),
+                    },
+                    work_done_progress_params: WorkDoneProgressParams::default(),
+                    partial_result_params: PartialResultParams::default(),
+                })
+                .await
+                .unwrap()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|link| link.target.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(links().await, [Url::from_file_path(&types_path).unwrap()]);
+
+        server
+            .did_change_workspace_folders(DidChangeWorkspaceFoldersParams {
+                event: WorkspaceFoldersChangeEvent {
+                    added: Vec::new(),
+                    removed: vec![folder(&shared)],
+                },
+            })
+            .await;
+        assert!(links().await.is_empty());
+    }
+
+    #[test]
+    fn test_semantic_tokens_mark_custom_options() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_semantic_tokens, SEMANTIC_TOKEN_TYPES,
+        };
+
+        let content = "syntax = \"proto3\";\n// Options\nmessage Test {\n  string name = 1 [deprecated = true, (custom_option) = 2];\n}\n";
+
+        // Decode the relative encoding into (line, column, length, type)
+        let mut position = (0, 0);
+        let tokens: Vec<(u32, u32, u32, SemanticTokenType)> = compute_semantic_tokens(content)
+            .into_iter()
+            .map(|token| {
+                position = if token.delta_line == 0 {
+                    (position.0, position.1 + token.delta_start)
+                } else {
+                    (position.0 + token.delta_line, token.delta_start)
+                };
+                let token_type = SEMANTIC_TOKEN_TYPES[token.token_type as usize].clone();
+                (position.0, position.1, token.length, token_type)
+            })
+            .collect();
+
+        let type_at = |line, column| {
+            tokens
+                .iter()
+                .find(|token| (token.0, token.1) == (line, column))
+                .map(|token| (token.2, token.3.clone()))
+        };
+        assert_eq!(type_at(0, 0), Some((6, SemanticTokenType::KEYWORD)));
+        assert_eq!(type_at(1, 0), Some((10, SemanticTokenType::COMMENT)));
+        assert_eq!(type_at(3, 2), Some((6, SemanticTokenType::TYPE)));
+        assert_eq!(type_at(3, 19), Some((10, SemanticTokenType::PROPERTY)));
+        assert_eq!(type_at(3, 38), Some((15, SemanticTokenType::DECORATOR)));
+        assert_eq!(type_at(3, 56), Some((1, SemanticTokenType::NUMBER)));
+        // Plain identifiers such as the field name are left to the editor
+        assert_eq!(type_at(3, 9), None);
+    }
+
+    #[test]
+    fn test_message_body_keyword_completions() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_completions;
+
+        let content = r#"syntax = "proto3";
+
+message Test {
+  
+}
+"#;
+        let completions = compute_completions(content, Position::new(3, 2));
+        let reserved = completions
+            .iter()
+            .find(|c| c.label == "reserved")
+            .expect("reserved keyword");
+        assert_eq!(reserved.kind, Some(CompletionItemKind::KEYWORD));
+        assert_eq!(
+            reserved.detail.as_deref(),
+            Some("Reserve field numbers or names")
+        );
+        assert!(completions.iter().any(|c| c.label == "map"));
+
+        // Not offered outside a message
+        let completions = compute_completions(content, Position::new(1, 0));
+        assert!(!completions.iter().any(|c| c.label == "reserved"));
+    }
+
+    #[test]
+    fn test_hover_on_any_segment_of_qualified_name() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = r#"syntax = "proto3";
+
+import "google/protobuf/timestamp.proto";
+
+message Event {
+  google.protobuf.Timestamp created_at = 1;
+}
+"#;
+
+        // On `google`, `protobuf` and `Timestamp`
+        for character in [3, 12, 20] {
+            let hover = compute_hover(content, Position::new(5, character)).unwrap();
+            let HoverContents::Markup(markup) = hover.contents else {
+                panic!("unexpected hover contents");
+            };
+            assert!(
+                markup.value.starts_with("**google.protobuf.Timestamp**"),
+                "hover at column {character}: {}",
+                markup.value
+            );
+        }
+    }
+
+    #[test]
+    fn test_inlay_hint_tooltip_is_resolved_lazily() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_inlay_hint_resolve, compute_inlay_hints,
+        };
+
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string user_name = 1;
+  int32 id = 2;
+  string display = 3 [json_name = "label"];
+}
+"#;
+        let range = Range::new(Position::new(0, 0), Position::new(7, 0));
+        let hints = compute_inlay_hints(content, range);
+        let labels: Vec<_> = hints
+            .iter()
+            .map(|hint| match &hint.label {
+                InlayHintLabel::String(label) => label.as_str(),
+                InlayHintLabel::LabelParts(_) => panic!("unexpected label parts"),
+            })
+            .collect();
+        assert_eq!(labels, ["json: userName", "json: label"]);
+        assert_eq!(hints[0].position, Position::new(3, 18));
+        assert!(hints.iter().all(|hint| hint.tooltip.is_none()));
+
+        let resolved = compute_inlay_hint_resolve(hints[0].clone());
+        let Some(InlayHintTooltip::MarkupContent(tooltip)) = resolved.tooltip else {
+            panic!("expected a markdown tooltip");
+        };
+        assert_eq!(
+            tooltip.value,
+            "JSON name of field `user_name` in message `Test`, derived from the field name. Set `json_name` to override it."
+        );
+
+        // Hints outside the requested range are skipped
+        let range = Range::new(Position::new(5, 0), Position::new(6, 0));
+        assert_eq!(compute_inlay_hints(content, range).len(), 1);
+    }
+
+    #[test]
+    fn test_empty_declaration_lint() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_document_diagnostics;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message Request {}
+
+message Outer {
+  message Inner {}
+  Inner inner = 1;
+}
+
+enum Status {}
+
+service Api {}
+"#;
+        let codes = |settings: &Settings| -> Vec<(String, Option<DiagnosticSeverity>, u32)> {
+            compute_document_diagnostics(&uri, content, settings)
+                .into_iter()
+                .map(|d| {
+                    let Some(NumberOrString::String(code)) = d.code else {
+                        panic!("diagnostic without a code: {}", d.message);
+                    };
+                    (code, d.severity, d.range.start.line)
+                })
+                .collect()
+        };
+
+        // Only the empty enum is reported by default, as an error
+        assert_eq!(
+            codes(&Settings::default()),
+            [("empty-enum".to_string(), Some(DiagnosticSeverity::ERROR), 9)]
+        );
+
+        let settings = Settings {
+            lint_empty_declarations: true,
+            ..Settings::default()
+        };
+        let mut hints = codes(&settings);
+        hints.sort_by_key(|(_, _, line)| *line);
+        let hint = |line| {
+            (
+                "empty-declaration".to_string(),
+                Some(DiagnosticSeverity::HINT),
+                line,
+            )
+        };
+        assert_eq!(
+            hints,
+            [
+                hint(2),
+                hint(5),
+                ("empty-enum".to_string(), Some(DiagnosticSeverity::ERROR), 9),
+                hint(11),
+            ]
+        );
+
+        // The lint can still be turned off by its rule code
+        let settings = Settings {
+            lint_empty_declarations: true,
+            disabled_rules: ["empty-declaration".to_string()].into(),
+            ..Settings::default()
+        };
+        assert_eq!(codes(&settings).len(), 1);
+    }
+
+    #[test]
+    fn test_diagnostics_emit_timing_span() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_diagnostics;
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        /// Records the name of every span that is created.
+        struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+        impl<S: tracing::Subscriber> Layer<S> for SpanNames {
+            fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(attrs.metadata().name().to_string());
+            }
+        }
+
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            compute_diagnostics("syntax = \"proto3\";\n\nmessage Test {}\n");
+        });
+
+        let names = names.lock().unwrap();
+        assert!(names.contains(&"analyze".to_string()), "{names:?}");
+        assert!(
+            names.contains(&"compute_diagnostics".to_string()),
+            "{names:?}"
+        );
+    }
+
+    #[test]
+    fn test_sort_fields_by_number() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_code_actions;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message Test {
+  // The third field
+  string c = 3 [deprecated = true];
+  oneof choice {
+    int32 d = 4;
+  }
+  string a = 1; // first
+  string b = 2;
+}
+"#;
+        let cursor = Range::new(Position::new(3, 2), Position::new(3, 2));
+        let actions = compute_code_actions(&uri, content, cursor, &[]);
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "Sort fields by number");
+        assert_eq!(action.kind, Some(CodeActionKind::REFACTOR_REWRITE));
+
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits,
+            &[TextEdit {
+                range: Range::new(Position::new(3, 0), Position::new(9, 15)),
+                new_text: "  string a = 1; // first
+  oneof choice {
+    int32 d = 4;
+  }
+  string b = 2;
+  // The third field
+  string c = 3 [deprecated = true];"
+                    .to_string(),
+            }]
+        );
+
+        // CRLF files keep their line breaks
+        let crlf = "message Test {\r\n  string b = 2;\r\n  string a = 1;\r\n}\r\n";
+        let cursor = Range::new(Position::new(1, 2), Position::new(1, 2));
+        let actions = compute_code_actions(&uri, crlf, cursor, &[]);
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "  string a = 1;\r\n  string b = 2;");
+
+        // Nothing to offer once the fields are in order
+        let sorted = "message Test {\n  string a = 1;\n  string b = 2;\n}\n";
+        let cursor = Range::new(Position::new(1, 2), Position::new(1, 2));
+        assert!(compute_code_actions(&uri, sorted, cursor, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_call_hierarchy_resolves_nested_messages() {
+        use protobuf_edition_lsp::lsp_server::document_store::DocumentStore;
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_incoming_calls, compute_prepare_call_hierarchy,
+        };
+
+        let uri = Url::parse("file:///api.proto").unwrap();
+        let content = r#"syntax = "proto3";
+package api;
+
+message A {
+  message Inner {}
+}
+
+message B {
+  message Inner {}
+}
+
+service S {
+  rpc First(A.Inner) returns (B.Inner);
+  rpc Second(.api.B.Inner) returns (B.Inner);
+}
+"#;
+        let mut store = DocumentStore::new();
+        store.open_document(uri.clone(), content.to_string(), 1);
+        let callers = |position: Position| {
+            let items = compute_prepare_call_hierarchy(&uri, content, position).unwrap();
+            compute_incoming_calls(&store, &items[0])
+                .iter()
+                .map(|call| (call.from.name.clone(), call.from_ranges.len()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(callers(Position::new(4, 11)), [("First".to_string(), 1)]);
+        assert_eq!(
+            callers(Position::new(8, 11)),
+            [("First".to_string(), 1), ("Second".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_call_hierarchy_for_message() {
+        use protobuf_edition_lsp::lsp_server::document_store::DocumentStore;
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_incoming_calls, compute_prepare_call_hierarchy,
+        };
+
+        let uri = Url::parse("file:///api.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message User {
+  string id = 1;
+}
+
+message GetUserRequest {
+  string id = 1;
+}
+
+service UserService {
+  rpc GetUser(GetUserRequest) returns (User);
+  rpc UpdateUser(User) returns (User);
+  rpc Ping(GetUserRequest) returns (GetUserRequest);
+}
+"#;
+        // On the message's declaration
+        let items = compute_prepare_call_hierarchy(&uri, content, Position::new(2, 10)).unwrap();
+        let [item] = items.as_slice() else {
+            panic!("expected a single item, got {items:?}");
+        };
+        assert_eq!(item.name, "User");
+        assert_eq!(item.kind, SymbolKind::STRUCT);
+        assert_eq!(
+            item.selection_range,
+            Range::new(Position::new(2, 8), Position::new(2, 12))
+        );
+
+        // And on a reference to it
+        let from_reference =
+            compute_prepare_call_hierarchy(&uri, content, Position::new(11, 40)).unwrap();
+        assert_eq!(from_reference[0].name, "User");
+
+        let mut store = DocumentStore::new();
+        store.open_document(uri.clone(), content.to_string(), 1);
+        let calls = compute_incoming_calls(&store, item);
+        let names: Vec<_> = calls
+            .iter()
+            .map(|call| (call.from.name.as_str(), call.from_ranges.len()))
+            .collect();
+        assert_eq!(names, [("GetUser", 1), ("UpdateUser", 2)]);
+        assert_eq!(calls[0].from.detail.as_deref(), Some("UserService"));
+        assert_eq!(
+            calls[0].from_ranges[0],
+            Range::new(Position::new(11, 39), Position::new(11, 43))
+        );
+    }
+
+    #[test]
+    fn test_unchanged_diagnostic_report() {
+        use protobuf_edition_lsp::lsp_server::document_store::DocumentStore;
+        use protobuf_edition_lsp::lsp_server::handlers::compute_document_diagnostic_report;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let mut store = DocumentStore::new();
+        store.open_document(uri.clone(), "message Test { int32 a = 0; }".to_string(), 1);
+        let settings = Settings::default();
+
+        let report = |store: &DocumentStore, previous: Option<&str>| {
+            let document = store.get_document(&uri).unwrap();
+            compute_document_diagnostic_report(&uri, document, &settings, previous)
+        };
+
+        let DocumentDiagnosticReport::Full(full) = report(&store, None) else {
+            panic!("expected a full report");
+        };
+        let result_id = full.full_document_diagnostic_report.result_id.unwrap();
+        assert!(!full.full_document_diagnostic_report.items.is_empty());
+
+        let DocumentDiagnosticReport::Unchanged(unchanged) = report(&store, Some(&result_id))
+        else {
+            panic!("expected an unchanged report");
+        };
+        assert_eq!(
+            unchanged.unchanged_document_diagnostic_report.result_id,
+            result_id
+        );
+
+        // Once the content changes, the old id no longer matches
+        store.update_document(uri.clone(), "message Test {}".to_string(), 2);
+        let DocumentDiagnosticReport::Full(full) = report(&store, Some(&result_id)) else {
+            panic!("expected a full report after an edit");
+        };
+        assert_ne!(
+            full.full_document_diagnostic_report.result_id,
+            Some(result_id)
+        );
+    }
+
+    #[test]
+    fn test_next_field_number_completion() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_completions;
+
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string a = 1 [default = 40];
+  oneof choice {
+    int32 b = 2;
+  }
+  message Nested {
+    int32 deep = 30;
+  }
+  string c = 
+}
+"#;
+        let completions = compute_completions(content, Position::new(10, 13));
+        let [item] = completions.as_slice() else {
+            panic!("expected a single completion, got {completions:?}");
+        };
+        assert_eq!(item.label, "3");
+        assert_eq!(item.insert_text.as_deref(), Some("3"));
+        assert_eq!(
+            item.detail.as_deref(),
+            Some("Next free field number in 'Test'")
+        );
+
+        // Reserved numbers count as used
+        let content = "message Test {\n  reserved 2, 4 to 6;\n  int32 a = 1;\n  bool b = ";
+        let completions = compute_completions(content, Position::new(3, 11));
+        assert_eq!(completions[0].label, "7");
+
+        // Extension ranges are skipped, and `max` ends a range
+        let content = "message Test {\n  int32 a = 1;\n  extensions 2 to 100;\n  bool b = ";
+        let completions = compute_completions(content, Position::new(3, 11));
+        assert_eq!(completions[0].label, "101");
+        let content = "message Test {\n  reserved 1 to max;\n  bool b = ";
+        let completions = compute_completions(content, Position::new(2, 11));
+        assert!(completions
+            .iter()
+            .all(|item| item.detail.as_deref() != Some("Next free field number in 'Test'")));
+
+        // Enum values are numbered differently
+        let content = "enum Kind {\n  KIND_UNSPECIFIED = 0;\n  KIND_A = ";
+        let completions = compute_completions(content, Position::new(2, 11));
+        assert!(completions.iter().all(|item| item.label != "1"));
+    }
+
+    #[test]
+    fn test_crlf_positions() {
+        use protobuf_edition_lsp::lsp_server::handlers::{
+            compute_formatting, compute_hover, compute_semantic_tokens,
+        };
+
+        let content = "syntax = \"proto3\";\r\n\r\n// A user of the system\r\nmessage User {\r\n  string name = 1; // display name\r\n}\r\n\r\nmessage Team {\r\n  User lead = 1;\r\n}\r\n";
+
+        // Columns on a line after CRLF line breaks
+        let hover = compute_hover(content, Position::new(4, 4)).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup hover");
+        };
+        assert!(markup.value.starts_with("**string**"), "{}", markup.value);
+
+        let hover = compute_hover(content, Position::new(4, 11)).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup hover");
+        };
+        assert!(markup.value.starts_with("**name**"), "{}", markup.value);
+        assert!(markup.value.contains("display name"));
+        assert!(!markup.value.contains('\r'));
+
+        // The trailing comment ends at the end of the line, not on its `\r`
+        let tokens = compute_semantic_tokens(content);
+        let comment = tokens
+            .iter()
+            .scan(0, |line, token| {
+                *line += token.delta_line;
+                Some((*line, token))
+            })
+            .find(|(line, token)| *line == 4 && token.length == 15)
+            .map(|(_, token)| token);
+        assert!(comment.is_some(), "{tokens:?}");
+
+        // Formatting keeps the file's line endings
+        let options = FormattingOptions {
+            tab_size: 2,
+            insert_spaces: true,
+            ..Default::default()
+        };
+        assert!(compute_formatting(content, &options).is_empty());
+        let edits = compute_formatting("message A{int32 id=1;}\r\n", &options);
+        assert_eq!(edits[0].new_text, "message A {\r\n  int32 id = 1;\r\n}\r\n");
+    }
+
+    #[test]
+    fn test_hover_range() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_hover;
+
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string name = 1;
+  google.protobuf.Timestamp created_at = 2 [deprecated = true];
+}
+"#;
+        let range = |line, character| compute_hover(content, Position::new(line, character))?.range;
+
+        // A scalar type
+        assert_eq!(
+            range(3, 4),
+            Some(Range::new(Position::new(3, 2), Position::new(3, 8)))
+        );
+        // The whole qualified path, from any of its segments
+        assert_eq!(
+            range(4, 10),
+            Some(Range::new(Position::new(4, 2), Position::new(4, 27)))
+        );
+        // A built-in field option
+        assert_eq!(
+            range(4, 46),
+            Some(Range::new(Position::new(4, 44), Position::new(4, 54)))
+        );
+        // A field name
+        assert_eq!(
+            range(3, 9),
+            Some(Range::new(Position::new(3, 9), Position::new(3, 13)))
+        );
+    }
+
+    #[test]
+    fn test_empty_service_lint() {
+        use protobuf_edition_lsp::lsp_server::handlers::compute_document_diagnostics;
+        use protobuf_edition_lsp::lsp_server::settings::Settings;
+
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let content = r#"syntax = "proto3";
+
+message Empty {}
+
+service Api {}
+
+service Users {
+  rpc Get(Empty) returns (Empty);
+}
+"#;
+        let codes = |settings: &Settings| -> Vec<(String, Option<DiagnosticSeverity>, u32)> {
+            compute_document_diagnostics(&uri, content, settings)
+                .into_iter()
+                .map(|d| {
+                    let Some(NumberOrString::String(code)) = d.code else {
+                        panic!("diagnostic without a code: {}", d.message);
+                    };
+                    (code, d.severity, d.range.start.line)
+                })
+                .collect()
+        };
+
+        // Off by default
+        assert!(codes(&Settings::default()).is_empty());
+
+        let settings = Settings {
+            require_methods: true,
+            ..Settings::default()
+        };
+        assert_eq!(
+            codes(&settings),
+            [(
+                "empty-service".to_string(),
+                Some(DiagnosticSeverity::WARNING),
+                4
+            )]
+        );
+        let diagnostics = compute_document_diagnostics(&uri, content, &settings);
+        assert_eq!(diagnostics[0].message, "Service 'Api' declares no methods");
+
+        // Empty services aren't hinted at a second time by the general lint
+        let settings = Settings {
+            require_methods: true,
+            lint_empty_declarations: true,
+            ..Settings::default()
+        };
+        let mut both = codes(&settings);
+        both.sort_by_key(|(_, _, line)| *line);
+        assert_eq!(
+            both,
+            [
+                (
+                    "empty-declaration".to_string(),
+                    Some(DiagnosticSeverity::HINT),
+                    2
+                ),
+                (
+                    "empty-service".to_string(),
+                    Some(DiagnosticSeverity::WARNING),
+                    4
+                ),
+            ]
+        );
+
+        // The rule can be switched off like any other
+        let settings = Settings::from_value(serde_json::json!({
+            "requireMethods": true,
+            "disabledRules": ["empty-service"],
+        }));
+        assert!(codes(&settings).is_empty());
+    }
 }
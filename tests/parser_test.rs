@@ -40,10 +40,9 @@ mod parser_tests {
         let result = parse_proto(content);
         assert!(result.is_ok());
         let parsed = result.unwrap();
-        assert!(parsed
-            .statements
-            .iter()
-            .any(|stmt| matches!(stmt, Statement::Package(pkg) if pkg == "com.example.myapp")));
+        assert!(parsed.statements.iter().any(
+            |stmt| matches!(&stmt.node, Statement::Package(pkg) if pkg == "com.example.myapp")
+        ));
     }
 
     #[test]
@@ -52,7 +51,7 @@ mod parser_tests {
         let result = parse_proto(content);
         assert!(result.is_ok());
         let parsed = result.unwrap();
-        assert!(parsed.statements.iter().any(|stmt| matches!(stmt, Statement::Import { path, .. } if path == "google/protobuf/timestamp.proto")));
+        assert!(parsed.statements.iter().any(|stmt| matches!(&stmt.node, Statement::Import { path, .. } if path == "google/protobuf/timestamp.proto")));
     }
 
     #[test]
@@ -69,7 +68,7 @@ message Person {
         let parsed = result.unwrap();
 
         let message = parsed.statements.iter().find_map(|stmt| {
-            if let Statement::Message(msg) = stmt {
+            if let Statement::Message(msg) = &stmt.node {
                 Some(msg)
             } else {
                 None
@@ -108,7 +107,7 @@ enum Status {
         let parsed = result.unwrap();
 
         let enum_def = parsed.statements.iter().find_map(|stmt| {
-            if let Statement::Enum(e) = stmt {
+            if let Statement::Enum(e) = &stmt.node {
                 Some(e)
             } else {
                 None
@@ -136,7 +135,7 @@ service Greeter {
         let parsed = result.unwrap();
 
         let service = parsed.statements.iter().find_map(|stmt| {
-            if let Statement::Service(svc) = stmt {
+            if let Statement::Service(svc) = &stmt.node {
                 Some(svc)
             } else {
                 None
@@ -168,7 +167,7 @@ message TestMessage {
         let parsed = result.unwrap();
 
         let message = parsed.statements.iter().find_map(|stmt| {
-            if let Statement::Message(msg) = stmt {
+            if let Statement::Message(msg) = &stmt.node {
                 Some(msg)
             } else {
                 None
@@ -195,7 +194,7 @@ message TestMessage {
         let parsed = result.unwrap();
 
         let message = parsed.statements.iter().find_map(|stmt| {
-            if let Statement::Message(msg) = stmt {
+            if let Statement::Message(msg) = &stmt.node {
                 Some(msg)
             } else {
                 None
@@ -240,7 +239,7 @@ message Outer {
         let parsed = result.unwrap();
 
         let outer = parsed.statements.iter().find_map(|stmt| {
-            if let Statement::Message(msg) = stmt {
+            if let Statement::Message(msg) = &stmt.node {
                 Some(msg)
             } else {
                 None
@@ -277,4 +276,2124 @@ message Test {
             .iter()
             .any(|e| e.message.to_lowercase().contains("duplicate field number")));
     }
+
+    #[test]
+    fn test_parse_proto2_group() {
+        let content = r#"
+syntax = "proto2";
+
+message SearchResponse {
+  repeated group Result = 1 {
+    required string url = 2;
+    optional string title = 3;
+  }
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+
+        let message = parsed.statements.iter().find_map(|stmt| {
+            if let Statement::Message(msg) = &stmt.node {
+                Some(msg)
+            } else {
+                None
+            }
+        });
+        let message = message.unwrap();
+
+        assert_eq!(message.fields.len(), 1);
+        assert_eq!(message.fields[0].name, "result");
+        assert_eq!(message.fields[0].field_type, "Result");
+        assert_eq!(message.fields[0].number, 1);
+        assert_eq!(message.fields[0].label, Some(FieldLabel::Repeated));
+        assert!(message.fields[0].is_group);
+
+        assert_eq!(message.nested_messages.len(), 1);
+        let group = &message.nested_messages[0];
+        assert_eq!(group.name, "Result");
+        assert_eq!(group.fields.len(), 2);
+        assert_eq!(group.fields[0].name, "url");
+        assert_eq!(group.fields[1].name, "title");
+
+        assert!(validate_proto(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_error_group_outside_proto2() {
+        let content = r#"
+syntax = "proto3";
+
+message SearchResponse {
+  repeated group Result = 1 {
+    string url = 2;
+  }
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        let error = errors
+            .iter()
+            .find(|e| e.code == "group-requires-proto2" && e.message.contains("Result"))
+            .unwrap();
+        assert_eq!((error.span.start.line, error.span.start.column), (4, 2));
+    }
+
+    #[test]
+    fn test_group_without_syntax_defaults_to_proto2() {
+        let content = r#"message M {
+  optional group G = 1 {
+    optional string url = 2;
+  }
+  group H = 3 {}
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected a message");
+        };
+        assert!(message.fields.iter().all(|field| field.is_group));
+        assert_eq!(message.nested_messages.len(), 2);
+
+        let codes: Vec<_> = validate_proto(&parsed)
+            .iter()
+            .map(|error| error.code)
+            .collect();
+        assert_eq!(codes, ["missing-syntax"]);
+    }
+
+    #[test]
+    fn test_error_syntax_and_edition_both_present() {
+        let content = r#"
+syntax = "proto3";
+edition = "2023";
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert!(errors
+            .iter()
+            .any(|e| e.code == "syntax-and-edition" && e.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_warning_missing_syntax() {
+        let content = r#"
+message Test {
+  string name = 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "missing-syntax");
+        assert_eq!(errors[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_syntax_declared_first() {
+        let content = r#"
+syntax = "proto3";
+package foo;
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_error_package_before_syntax() {
+        let content = r#"
+package foo;
+syntax = "proto3";
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        let error = errors.iter().find(|e| e.code == "syntax-not-first");
+        assert!(error.is_some());
+        assert_eq!(error.unwrap().span.start.line, 2);
+    }
+
+    #[test]
+    fn test_default_json_name() {
+        assert_eq!(default_json_name("foo_bar"), "fooBar");
+        assert_eq!(default_json_name("foo_bar_baz"), "fooBarBaz");
+        assert_eq!(default_json_name("name"), "name");
+    }
+
+    #[test]
+    fn test_error_json_name_conflict() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  string foo_bar = 1;
+  string fooBar = 2;
+  string baz = 3 [json_name = "qux"];
+  string qux = 4;
+  string empty = 5 [json_name = ""];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+
+        let conflicts: Vec<_> = errors
+            .iter()
+            .filter(|e| e.code == "json-name-conflict")
+            .collect();
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts[0].message.contains("'fooBar'"));
+        assert!(conflicts[1].message.contains("'qux'"));
+        assert!(errors.iter().any(|e| e.code == "empty-json-name"));
+    }
+
+    #[test]
+    fn test_parse_reserved() {
+        let content = r#"
+message Test {
+  reserved 2, 15, 9 to 11, 40 to max;
+  reserved "foo", "bar";
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected a message");
+        };
+
+        let ranges: Vec<(i64, i64)> = message
+            .reserved_ranges
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect();
+        assert_eq!(ranges, vec![(2, 2), (15, 15), (9, 11), (40, 536_870_911)]);
+
+        let names: Vec<&str> = message
+            .reserved_names
+            .iter()
+            .map(|reserved| reserved.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn test_reserved_is_contextual() {
+        let content = r#"edition = "2023";
+
+message reserved {
+  int32 reserved = 1;
+  reserved other = 2;
+  reserved 3, 4;
+  reserved old, older;
+}
+
+enum Kind {
+  reserved = 0;
+  reserved 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        assert!(validate_proto(&parsed).is_empty());
+
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected a message");
+        };
+        assert_eq!(message.name, "reserved");
+        let fields: Vec<(&str, &str)> = message
+            .fields
+            .iter()
+            .map(|field| (field.field_type.as_str(), field.name.as_str()))
+            .collect();
+        assert_eq!(fields, vec![("int32", "reserved"), ("reserved", "other")]);
+        assert_eq!(message.reserved_ranges.len(), 2);
+        assert_eq!(message.reserved_names.len(), 2);
+
+        let Statement::Enum(enum_def) = &parsed.statements[1].node else {
+            panic!("expected an enum");
+        };
+        assert_eq!(enum_def.values[0].name, "reserved");
+        assert_eq!(enum_def.reserved_ranges.len(), 1);
+    }
+
+    #[test]
+    fn test_error_field_uses_reserved_name() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  reserved "foo";
+  string foo = 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "reserved-field-name");
+        assert!(errors[0].message.contains("'foo'"));
+        assert!(errors[0].message.contains("reserved"));
+    }
+
+    #[test]
+    fn test_error_field_uses_reserved_number() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  reserved 5 to 10;
+  string name = 7;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "field-uses-reserved-number");
+        assert!(errors[0].message.contains("'name'"));
+    }
+
+    #[test]
+    fn test_field_number_and_syntax_error_positions() {
+        let content = r#"syntax = "proto4";
+
+message Test {
+  string zero = 0;
+  string internal = 19000;
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        let position = |code: &str| {
+            let error = errors.iter().find(|e| e.code == code).unwrap();
+            (error.span.start.line, error.span.start.column)
+        };
+        assert_eq!(position("invalid-syntax"), (0, 9));
+        assert_eq!(position("invalid-field-number"), (3, 2));
+        assert_eq!(position("reserved-field-number"), (4, 2));
+    }
+
+    #[test]
+    fn test_field_avoiding_reserved_is_valid() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  reserved 5 to 10;
+  reserved "foo";
+  string name = 11;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        assert!(validate_proto(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_parse_map_field() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  map<string, int32> counts = 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        let field = &message.fields[0];
+        assert_eq!(field.name, "counts");
+        assert_eq!(field.field_type, "map<string, int32>");
+        let map_type = field.map_type.as_ref().unwrap();
+        assert_eq!(map_type.key_type, "string");
+        assert_eq!(map_type.value_type, "int32");
+        assert!(validate_proto(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_error_invalid_map_key() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  map<bytes, string> values = 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-map-key");
+        assert!(errors[0].message.contains("'values'"));
+        assert!(errors[0].message.contains("'bytes'"));
+    }
+
+    #[test]
+    fn test_error_nested_map_value() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  map<string, map<string, int32>> nested = 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-map-value");
+        assert!(errors[0].message.contains("'nested'"));
+    }
+
+    #[test]
+    fn test_error_labeled_map_field() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  optional map<string, int32> counts = 1;
+}
+"#;
+        let err = parse_proto(content).unwrap_err();
+        let err = err.downcast_ref::<PositionedParseError>().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid syntax: Map field 'counts' cannot be 'optional'; map fields are implicitly repeated"
+        );
+        assert_eq!(
+            (
+                err.span.start.line,
+                err.span.start.column,
+                err.span.end.column
+            ),
+            (4, 2, 10)
+        );
+    }
+
+    #[test]
+    fn test_error_labeled_oneof_field() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  oneof choice {
+    repeated string names = 1;
+  }
+}
+"#;
+        let err = parse_proto(content).unwrap_err();
+        let err = err.downcast_ref::<PositionedParseError>().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid syntax: Fields in oneof 'choice' cannot be 'repeated'"
+        );
+        assert_eq!(
+            (
+                err.span.start.line,
+                err.span.start.column,
+                err.span.end.column
+            ),
+            (5, 4, 12)
+        );
+    }
+
+    #[test]
+    fn test_parse_streaming_combinations() {
+        let content = r#"
+syntax = "proto3";
+
+service Streams {
+  rpc Unary(Request) returns (Response);
+  rpc ClientStream(stream Request) returns (Response);
+  rpc ServerStream(Request) returns (stream Response);
+  rpc Bidi(stream Request) returns (stream Response);
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Service(service) = &parsed.statements[0].node else {
+            panic!("expected service");
+        };
+
+        let streaming: Vec<_> = service
+            .methods
+            .iter()
+            .map(|method| {
+                (
+                    method.name.as_str(),
+                    method.client_streaming,
+                    method.server_streaming,
+                )
+            })
+            .collect();
+        assert_eq!(
+            streaming,
+            vec![
+                ("Unary", false, false),
+                ("ClientStream", true, false),
+                ("ServerStream", false, true),
+                ("Bidi", true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_misplaced_stream() {
+        for content in [
+            "service S { rpc M(Request stream) returns (Response); }",
+            "service S { rpc M(Request) returns stream (Response); }",
+        ] {
+            let error = parse_proto(content).unwrap_err().to_string();
+            assert!(
+                error.contains("'stream'") && error.contains("right after '('"),
+                "unexpected error for {content:?}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_message_inside_service() {
+        let content = "service S { message X {} }";
+        let error = parse_proto(content).unwrap_err();
+        let error = error.downcast_ref::<PositionedParseError>().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Invalid syntax: unexpected Message: only rpc and option are allowed inside a service"
+        );
+        assert_eq!((error.span.start.line, error.span.start.column), (0, 12));
+    }
+
+    #[test]
+    fn test_parse_qualified_option_names() {
+        let content = r#"
+edition = "2023";
+
+option features.field_presence = IMPLICIT;
+
+message Test {
+  option (foo.bar).baz = true;
+  string name = 1 [(gogoproto.nullable) = false, (.my.ext).(nested.opt) = 1];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        assert!(parsed.statements.iter().any(|statement| matches!(
+            &statement.node,
+            Statement::Option { name, .. } if name == "features.field_presence"
+        )));
+
+        let message = parsed
+            .statements
+            .iter()
+            .find_map(|statement| match &statement.node {
+                Statement::Message(message) => Some(message),
+                _ => None,
+            })
+            .unwrap();
+        assert!(message.options.contains_key("(foo.bar).baz"));
+        assert_eq!(
+            message.fields[0].options.get("(gogoproto.nullable)"),
+            Some(&OptionValue::Bool(false))
+        );
+        assert!(message.fields[0]
+            .options
+            .contains_key("(.my.ext).(nested.opt)"));
+    }
+
+    #[test]
+    fn test_error_field_in_extension_range() {
+        let content = r#"
+syntax = "proto2";
+
+message Test {
+  extensions 100 to 200;
+  optional string name = 150;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        assert_eq!(message.extension_ranges.len(), 1);
+        assert_eq!(message.extension_ranges[0].start, 100);
+        assert_eq!(message.extension_ranges[0].end, 200);
+
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "extension-range-conflict");
+        assert!(errors[0].message.contains("'name'"));
+        assert!(errors[0].message.contains("150"));
+        assert!(errors[0].message.contains("100 to 200"));
+    }
+
+    #[test]
+    fn test_extensions_is_contextual() {
+        let content = r#"
+syntax = "proto2";
+
+message extensions {
+  optional extensions e = 1;
+  extensions extensions2 = 2;
+  extensions 100 to 200;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        let fields: Vec<(&str, &str)> = message
+            .fields
+            .iter()
+            .map(|field| (field.field_type.as_str(), field.name.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![("extensions", "e"), ("extensions", "extensions2")]
+        );
+        assert_eq!(message.extension_ranges.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_package_names() {
+        let package_errors = |package: &str| {
+            let content = format!("syntax = \"proto3\";\n\npackage {package};\n");
+            let parsed = parse_proto(&content).unwrap();
+            validate_proto(&parsed)
+        };
+
+        assert!(package_errors("com.example").is_empty());
+
+        let errors = package_errors("Com.Example");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "package-name-case");
+        assert_eq!(errors[0].severity, Severity::Warning);
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (2, 0)
+        );
+
+        let errors = package_errors("a..b");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-package-name");
+        assert_eq!(errors[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_error_plus_without_digits() {
+        for value in ["+foo", "+inf"] {
+            let content =
+                format!("syntax = \"proto3\";\n\nenum Status {{\n  STATUS_A = {value};\n}}\n");
+            let err = parse_proto(&content).unwrap_err();
+            let err = err.downcast_ref::<PositionedParseError>().unwrap();
+            assert_eq!(err.to_string(), format!("Invalid number: {value}"));
+            assert_eq!((err.span.start.line, err.span.start.column), (3, 13));
+        }
+    }
+
+    #[test]
+    fn test_signed_numbers() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  string name = +5;
+}
+
+enum Status {
+  STATUS_UNSPECIFIED = 0;
+  STATUS_NEGATIVE = -1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        assert_eq!(message.fields[0].number, 5);
+
+        let Statement::Enum(enum_def) = &parsed.statements[1].node else {
+            panic!("expected enum");
+        };
+        assert_eq!(enum_def.values[1].number, -1);
+        assert!(validate_proto(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_error_negative_field_number() {
+        let content = "message Test { string name = -1; }";
+        let error = parse_proto(content).unwrap_err().to_string();
+        assert!(error.contains("field number must be positive"));
+    }
+
+    #[test]
+    fn test_same_named_nested_messages_have_separate_numbers() {
+        let content = r#"
+syntax = "proto3";
+
+message First {
+  message Inner {
+    string name = 1;
+  }
+}
+
+message Second {
+  message Inner {
+    string name = 1;
+  }
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+    }
+
+    #[test]
+    fn test_import_modifiers() {
+        let import_errors = |modifier: &str| {
+            let content = format!("syntax = \"proto3\";\nimport {modifier}\"other.proto\";\n");
+            validate_proto(&parse_proto(&content).unwrap())
+        };
+
+        assert!(import_errors("").is_empty());
+        assert!(import_errors("public ").is_empty());
+
+        let errors = import_errors("weak ");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "weak-import");
+        assert_eq!(errors[0].severity, Severity::Warning);
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (1, 0)
+        );
+
+        let both = ProtoFile {
+            syntax: Some("proto3".to_string()),
+            edition: None,
+            syntax_span: None,
+            syntax_value_span: None,
+            statements: vec![Spanned {
+                node: Statement::Import {
+                    path: "other.proto".to_string(),
+                    path_span: Span::default(),
+                    public: true,
+                    weak: true,
+                },
+                span: Span::default(),
+            }],
+        };
+        let errors = validate_proto(&both);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-import-modifier");
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("message Test {\n  string name = 1;\n}\n").unwrap();
+
+        let kinds: Vec<&Token> = tokens.iter().map(|token| &token.node).collect();
+        assert_eq!(
+            kinds,
+            [
+                &Token::Message,
+                &Token::Identifier("Test".to_string()),
+                &Token::LeftBrace,
+                &Token::Identifier("string".to_string()),
+                &Token::Identifier("name".to_string()),
+                &Token::Equals,
+                &Token::NumberLiteral("1".to_string()),
+                &Token::Semicolon,
+                &Token::RightBrace,
+            ]
+        );
+
+        let name = &tokens[4].span;
+        assert_eq!((name.start.line, name.start.column), (1, 9));
+        assert_eq!((name.end.line, name.end.column), (1, 13));
+
+        assert!(tokenize("message /* unterminated").is_err());
+    }
+
+    #[test]
+    fn test_enum_value_range() {
+        let enum_with = |value: &str| {
+            parse_proto(&format!(
+                "syntax = \"proto3\";\nenum Status {{\n  UNSPECIFIED = 0;\n  X = {value};\n}}\n"
+            ))
+        };
+
+        let err = enum_with("3000000000").unwrap_err().to_string();
+        assert!(err.contains("enum value 3000000000 out of range for int32"));
+
+        let err = enum_with("-3000000000").unwrap_err().to_string();
+        assert!(err.contains("out of range for int32"));
+
+        let err = enum_with("1.5").unwrap_err().to_string();
+        assert!(err.contains("Invalid number: 1.5"));
+
+        let parsed = enum_with("-1").unwrap();
+        let Statement::Enum(enum_def) = &parsed.statements[0].node else {
+            panic!("expected enum");
+        };
+        assert_eq!(enum_def.values[1].number, -1);
+    }
+
+    #[test]
+    fn test_scalar_type_typo() {
+        let content = r#"syntax = "proto3";
+
+message strnig {}
+
+message Test {
+  strnig declared = 1;
+  in32 count = 2;
+  Bool flag = 3;
+  map<string, uitn64> totals = 4;
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        let typos: Vec<_> = errors
+            .iter()
+            .filter(|error| error.code == "scalar-type-typo")
+            .collect();
+        assert_eq!(typos.len(), 2);
+
+        assert!(typos[0].message.contains("did you mean 'int32'?"));
+        assert_eq!(
+            (typos[0].span.start.line, typos[0].span.start.column),
+            (6, 2)
+        );
+        let replacement = typos[0].replacement.as_ref().unwrap();
+        assert_eq!(replacement.text, "int32");
+        assert_eq!(replacement.span.end.column, 6);
+
+        assert!(typos[1].message.contains("did you mean 'uint64'?"));
+
+        assert_eq!(suggest_scalar_type("strnig"), Some("string"));
+        assert_eq!(suggest_scalar_type("string"), None);
+        assert_eq!(suggest_scalar_type("address"), None);
+    }
+
+    #[test]
+    fn test_idempotency_level() {
+        let method_errors = |level: &str| {
+            let content = format!(
+                "syntax = \"proto3\";\nmessage Req {{}}\nservice Api {{\n  rpc Get(Req) returns (Req) {{\n    option idempotency_level = {level};\n  }}\n}}\n"
+            );
+            validate_proto(&parse_proto(&content).unwrap())
+        };
+
+        for level in ["IDEMPOTENCY_UNKNOWN", "NO_SIDE_EFFECTS", "IDEMPOTENT"] {
+            assert!(method_errors(level).is_empty(), "{level}");
+        }
+
+        let errors = method_errors("SAFE");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-idempotency-level");
+        assert!(errors[0].message.contains("SAFE"));
+        assert!(errors[0].message.contains("'Get'"));
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (3, 6)
+        );
+    }
+
+    #[test]
+    fn test_trailing_comments() {
+        let content = r#"syntax = "proto3";
+
+message User {
+  int32 id = 1; // unique id
+  // Leading comment, not trailing
+  string name = 2;
+  string email = 3; /* primary address */
+}
+
+enum Role {
+  ROLE_UNSPECIFIED = 0; // default
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        let comments: Vec<Option<&str>> = message
+            .fields
+            .iter()
+            .map(|field| field.trailing_comments.as_deref())
+            .collect();
+        assert_eq!(comments, [Some("unique id"), None, Some("primary address")]);
+
+        let Statement::Enum(enum_def) = &parsed.statements[1].node else {
+            panic!("expected enum");
+        };
+        assert_eq!(
+            enum_def.values[0].trailing_comments.as_deref(),
+            Some("default")
+        );
+    }
+
+    #[test]
+    fn test_contextual_map_and_stream() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  map counts = 1;
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "map-missing-types");
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (3, 2)
+        );
+
+        // A type actually named `map` is still allowed
+        let content =
+            "syntax = \"proto3\";\nmessage map {}\nmessage Test {\n  map counts = 1;\n}\n";
+        assert!(validate_proto(&parse_proto(content).unwrap()).is_empty());
+
+        let err = parse_proto("syntax = \"proto3\";\nstream Foo;\n").unwrap_err();
+        let err = err.downcast_ref::<PositionedParseError>().unwrap();
+        assert!(err
+            .to_string()
+            .contains("'stream' is only allowed in an rpc"));
+        assert_eq!((err.span.start.line, err.span.start.column), (1, 0));
+
+        let err = parse_proto("message Test {\n  stream int32 id = 1;\n}\n").unwrap_err();
+        let err = err.downcast_ref::<PositionedParseError>().unwrap();
+        assert!(err.to_string().contains("'stream'"));
+        assert_eq!((err.span.start.line, err.span.start.column), (1, 2));
+    }
+
+    #[test]
+    fn test_qualified_type_names() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  common.Address home = 1;
+  .common.Address work = 2;
+}
+
+service Api {
+  rpc Get(common.Address) returns (.common.Address);
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        assert_eq!(message.fields[0].field_type, "common.Address");
+        assert_eq!(message.fields[0].type_span.end.column, 16);
+        assert_eq!(message.fields[1].field_type, ".common.Address");
+
+        let Statement::Service(service) = &parsed.statements[1].node else {
+            panic!("expected service");
+        };
+        let method = &service.methods[0];
+        assert_eq!(method.request_type, "common.Address");
+        assert_eq!(
+            (
+                method.request_type_span.start.column,
+                method.request_type_span.end.column
+            ),
+            (10, 24)
+        );
+        assert_eq!(method.response_type, ".common.Address");
+    }
+
+    #[test]
+    fn test_format_proto() {
+        let content = r#"syntax="proto3";
+package  demo;
+
+
+// Leading comment
+message User{
+  string name=1;   // trailing
+  map<string,int32> scores = 2 [ deprecated=true ];
+    repeated .demo.User friends = 3;
+  message Empty{ }
+}
+service Api{rpc Get( User )returns( stream User );}
+"#;
+        let expected = r#"syntax = "proto3";
+package demo;
+
+// Leading comment
+message User {
+  string name = 1; // trailing
+  map<string, int32> scores = 2 [deprecated = true];
+  repeated .demo.User friends = 3;
+  message Empty {}
+}
+service Api {
+  rpc Get(User) returns (stream User);
+}
+"#;
+        let formatted = format_proto(content, "  ").unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_proto(&formatted, "  ").unwrap(), expected);
+
+        assert!(format_proto("message User {", "  ").is_err());
+    }
+
+    #[test]
+    fn test_json_name_conflict_with_oneof_field() {
+        let content = r#"
+syntax = "proto3";
+
+message Test {
+  string my_field = 1;
+  oneof choice {
+    string myField = 2;
+  }
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+
+        let conflict = errors
+            .iter()
+            .find(|e| e.code == "json-name-conflict")
+            .expect("expected a JSON name conflict");
+        assert!(conflict.message.contains("'myField'"));
+        assert_eq!(
+            (conflict.span.start.line, conflict.span.start.column),
+            (6, 11)
+        );
+        assert_eq!(conflict.related.len(), 1);
+        assert_eq!(conflict.related[0].span.start.line, 4);
+        assert_eq!(conflict.related[0].span.start.column, 9);
+    }
+
+    #[test]
+    fn test_parse_large_file() {
+        let mut content = String::from("edition = \"2023\";\n\n");
+        for i in 0..5_000 {
+            content.push_str(&format!(
+                "// Message number {i} — with a non-ASCII comment\nmessage M{i} {{\n  string name = 1;\n  repeated int64 ids = 2;\n}}\n\n"
+            ));
+        }
+
+        let parsed = parse_proto(&content).unwrap();
+        assert_eq!(parsed.statements.len(), 5_000);
+
+        let Statement::Message(last) = &parsed.statements.last().unwrap().node else {
+            panic!("expected a message");
+        };
+        assert_eq!(last.name, "M4999");
+        assert_eq!(last.span.start.line, 2 + 4_999 * 6 + 1);
+    }
+
+    #[test]
+    fn test_reserved_range_validation() {
+        let content = r#"
+message Test {
+  reserved 1 to 5;
+  reserved 3 to 8, 20;
+  reserved 10 to 5;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+
+        let overlaps: Vec<_> = errors
+            .iter()
+            .filter(|e| e.code == "overlapping-reserved-range")
+            .collect();
+        assert_eq!(overlaps.len(), 1);
+        assert!(overlaps[0].message.contains("3 to 8"));
+        assert_eq!(
+            (overlaps[0].span.start.line, overlaps[0].span.start.column),
+            (3, 11)
+        );
+        assert_eq!(overlaps[0].related[0].span.start.line, 2);
+
+        let inverted: Vec<_> = errors
+            .iter()
+            .filter(|e| e.code == "invalid-reserved-range")
+            .collect();
+        assert_eq!(inverted.len(), 1);
+        assert!(inverted[0].message.contains("10 to 5"));
+    }
+
+    #[test]
+    fn test_rpc_types_must_be_messages() {
+        let content = r#"
+syntax = "proto3";
+
+message Request {
+  enum Kind {
+    KIND_UNSPECIFIED = 0;
+  }
+}
+enum Status {
+  STATUS_UNSPECIFIED = 0;
+}
+
+service Api {
+  rpc Scalar(int32) returns (stream string);
+  rpc Enums(Status) returns (Request.Kind);
+  rpc Valid(stream Request) returns (Request);
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors: Vec<_> = validate_proto(&parsed)
+            .into_iter()
+            .filter(|e| e.code == "invalid-rpc-type")
+            .map(|e| (e.span.start.line, e.message))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    13,
+                    "Request type of rpc 'Scalar' must be a message, but 'int32' is a scalar type"
+                        .to_string()
+                ),
+                (
+                    13,
+                    "Response type of rpc 'Scalar' must be a message, but 'string' is a scalar type"
+                        .to_string()
+                ),
+                (
+                    14,
+                    "Request type of rpc 'Enums' must be a message, but 'Status' is an enum"
+                        .to_string()
+                ),
+                (
+                    14,
+                    "Response type of rpc 'Enums' must be a message, but 'Request.Kind' is an enum"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let content = r#"message Test {
+  map<string, int32> counts = 1 [deprecated = true];
+  repeated int64 ids = 2;
+  oneof choice {
+    string name = 3;
+  }
+}
+enum Kind {
+  KIND_UNSPECIFIED = 0;
+}
+service Api {
+  rpc Get(Test) returns (Test);
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let span = |start: (usize, usize), end: (usize, usize)| Span {
+            start: Position {
+                line: start.0,
+                column: start.1,
+            },
+            end: Position {
+                line: end.0,
+                column: end.1,
+            },
+        };
+
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected a message");
+        };
+        // From the type (or label) through the trailing semicolon
+        assert_eq!(message.fields[0].span, span((1, 2), (1, 52)));
+        assert_eq!(message.fields[1].span, span((2, 2), (2, 25)));
+        assert_eq!(message.oneofs[0].span, span((3, 2), (5, 3)));
+        assert_eq!(message.oneofs[0].fields[0].span, span((4, 4), (4, 20)));
+        assert_eq!(message.span, span((0, 0), (6, 1)));
+
+        let Statement::Enum(enum_def) = &parsed.statements[1].node else {
+            panic!("expected an enum");
+        };
+        assert_eq!(enum_def.values[0].span, span((8, 2), (8, 23)));
+
+        let Statement::Service(service) = &parsed.statements[2].node else {
+            panic!("expected a service");
+        };
+        assert_eq!(service.methods[0].span, span((11, 2), (11, 31)));
+        let statement_spans: Vec<Span> = parsed
+            .statements
+            .iter()
+            .map(|statement| statement.span)
+            .collect();
+        assert_eq!(
+            statement_spans,
+            [
+                span((0, 0), (6, 1)),
+                span((7, 0), (9, 1)),
+                span((10, 0), (12, 1))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_packed_option() {
+        let content = r#"
+syntax = "proto3";
+
+enum Kind {
+  KIND_UNSPECIFIED = 0;
+}
+message Test {
+  repeated int32 x = 1 [packed = true];
+  repeated Kind kinds = 2 [packed = true];
+  int32 y = 3 [packed = true];
+  repeated string s = 4 [packed = true];
+  repeated Test children = 5 [packed = true];
+  repeated string unpacked = 6 [packed = false];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors: Vec<_> = validate_proto(&parsed)
+            .into_iter()
+            .filter(|e| e.code == "invalid-packed-option")
+            .map(|e| e.message)
+            .collect();
+
+        assert_eq!(
+            errors,
+            [
+                "Field 'y' in message 'Test' cannot use 'packed': it is not repeated",
+                "Field 's' in message 'Test' cannot use 'packed': only repeated numeric, bool, and enum fields can be packed",
+                "Field 'children' in message 'Test' cannot use 'packed': only repeated numeric, bool, and enum fields can be packed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_names_shadowing_scalars() {
+        let conflicts = |content: &str| -> Vec<String> {
+            validate_proto(&parse_proto(content).unwrap())
+                .into_iter()
+                .filter(|e| e.code == "reserved-type-name")
+                .map(|e| e.message)
+                .collect()
+        };
+
+        assert_eq!(
+            conflicts(
+                "message string {}\nmessage Outer {\n  enum int32 {\n    ZERO = 0;\n  }\n}\n"
+            ),
+            [
+                "Message 'string' conflicts with the built-in type 'string'",
+                "Enum 'int32' conflicts with the built-in type 'int32'",
+            ]
+        );
+        assert!(conflicts("message Foo {}\nenum Bar {\n  ZERO = 0;\n}\n").is_empty());
+    }
+
+    #[test]
+    fn test_message_literal_option_values() {
+        let content = r#"syntax = "proto3";
+
+option (my.file_opt) = {
+  key: "value"
+  nested { id: 1, enabled: true }
+  ids: [1, 2]
+  [ext.field]: FOO
+};
+
+message Test {
+  option (my.message_opt) = <name: "test">;
+  string name = 1 [(my.field_opt) = { min: -1 }];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+
+        let Statement::Option { name, value } = &parsed.statements[0].node else {
+            panic!("expected an option");
+        };
+        assert_eq!(name, "(my.file_opt)");
+        assert_eq!(
+            without_spans(value),
+            literal(vec![
+                ("key", OptionValue::String("value".to_string())),
+                (
+                    "nested",
+                    literal(vec![
+                        ("id", OptionValue::Number(1.0)),
+                        ("enabled", OptionValue::Bool(true)),
+                    ])
+                ),
+                ("ids", OptionValue::Number(1.0)),
+                ("ids", OptionValue::Number(2.0)),
+                ("[ext.field]", OptionValue::Identifier("FOO".to_string())),
+            ])
+        );
+        let OptionValue::Message(fields) = value else {
+            panic!("expected a message literal");
+        };
+        assert_eq!(
+            (fields[1].span.start.line, fields[1].span.start.column),
+            (4, 2)
+        );
+        assert_eq!(
+            (fields[1].span.end.line, fields[1].span.end.column),
+            (4, 33)
+        );
+
+        let Statement::Message(message) = &parsed.statements[1].node else {
+            panic!("expected a message");
+        };
+        assert_eq!(
+            without_spans(&message.options["(my.message_opt)"]),
+            literal(vec![("name", OptionValue::String("test".to_string()))])
+        );
+        assert_eq!(
+            without_spans(&message.fields[0].options["(my.field_opt)"]),
+            literal(vec![("min", OptionValue::Number(-1.0))])
+        );
+
+        // The formatter keeps literals intact and is stable on them
+        let formatted = format_proto(content, "  ").unwrap();
+        assert!(formatted.contains("  nested {\n    id: 1, enabled: true\n  }\n"));
+        assert!(formatted.contains("string name = 1 [(my.field_opt) = {\n    min: -1\n  }];"));
+        assert_eq!(format_proto(&formatted, "  ").unwrap(), formatted);
+        assert!(parse_proto(&formatted).is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_field_options() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string name = 1 [deprecated = true, json_name = "n", deprecated = false];
+  string other = 2 [deprecated = true, (my.tag) = "a", (my.tag) = "b"];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected a message");
+        };
+        let names: Vec<&str> = message.fields[0]
+            .option_names
+            .iter()
+            .map(|option| option.name.as_str())
+            .collect();
+        assert_eq!(names, ["deprecated", "json_name", "deprecated"]);
+
+        let errors: Vec<_> = validate_proto(&parsed)
+            .into_iter()
+            .filter(|error| error.code == "duplicate-option")
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "Option 'deprecated' is set more than once on field 'name' in message 'Test'"
+        );
+        assert_eq!(errors[0].span.start.line, 3);
+        assert_eq!(errors[0].span.start.column, 55);
+        assert_eq!(errors[0].related[0].span.start.column, 19);
+    }
+
+    #[test]
+    fn test_leading_comments() {
+        let content = r#"syntax = "proto3"; // not a doc comment
+// Detached by the blank line below
+
+service Users {
+  // Looks up a user.
+  /* By id. */
+  rpc Get(Request) returns (Response);
+}
+
+message Request {
+  int32 id = 1; // trailing
+  // Only the name
+  repeated string name = 2;
+}
+
+enum Role {
+  // The default
+  ROLE_UNSPECIFIED = 0;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Service(service) = &parsed.statements[0].node else {
+            panic!("expected service");
+        };
+        assert_eq!(
+            service.methods[0].leading_comments.as_deref(),
+            Some("Looks up a user.\nBy id.")
+        );
+
+        let Statement::Message(message) = &parsed.statements[1].node else {
+            panic!("expected message");
+        };
+        let comments: Vec<Option<&str>> = message
+            .fields
+            .iter()
+            .map(|field| field.leading_comments.as_deref())
+            .collect();
+        assert_eq!(comments, [None, Some("Only the name")]);
+
+        let Statement::Enum(enum_def) = &parsed.statements[2].node else {
+            panic!("expected enum");
+        };
+        assert_eq!(
+            enum_def.values[0].leading_comments.as_deref(),
+            Some("The default")
+        );
+        assert_eq!(service.leading_comments, None);
+
+        let content = r#"edition = "2023";
+
+// A user
+message User {
+  // Nested
+  message Name {}
+  // How to reach them
+  oneof contact {
+    string email = 1;
+  }
+  // Their role
+  enum Role {
+    ROLE_UNSPECIFIED = 0;
+  }
+}
+
+// User lookups
+service Users {}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected message");
+        };
+        assert_eq!(message.leading_comments.as_deref(), Some("A user"));
+        assert_eq!(
+            message.nested_messages[0].leading_comments.as_deref(),
+            Some("Nested")
+        );
+        assert_eq!(
+            message.oneofs[0].leading_comments.as_deref(),
+            Some("How to reach them")
+        );
+        assert_eq!(
+            message.nested_enums[0].leading_comments.as_deref(),
+            Some("Their role")
+        );
+        let Statement::Service(service) = &parsed.statements[1].node else {
+            panic!("expected service");
+        };
+        assert_eq!(service.leading_comments.as_deref(), Some("User lookups"));
+    }
+
+    #[test]
+    fn test_duplicate_number_across_oneof() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string name = 1;
+  oneof choice {
+    int32 id = 1;
+    string email = 2;
+  }
+  string other = 3;
+}
+"#;
+        let errors: Vec<_> = validate_proto(&parse_proto(content).unwrap())
+            .into_iter()
+            .filter(|error| error.code == "duplicate-field-number")
+            .collect();
+        assert_eq!(errors.len(), 2);
+
+        // The direct field links to the oneof field and vice versa
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (3, 2)
+        );
+        assert_eq!(errors[0].related[0].span.start.line, 5);
+        assert_eq!(errors[0].related[0].message, "also used by 'id'");
+        assert_eq!(
+            (errors[1].span.start.line, errors[1].span.start.column),
+            (5, 4)
+        );
+        assert_eq!(errors[1].related[0].span.start.line, 3);
+        assert_eq!(errors[1].related[0].message, "first used here");
+        assert_eq!(
+            errors[1].message,
+            "Duplicate field number 1 in message 'Test'"
+        );
+    }
+
+    #[test]
+    fn test_method_names_differing_only_by_case() {
+        let content = r#"syntax = "proto3";
+
+message Request {}
+message Response {}
+
+service Users {
+  rpc GetUser(Request) returns (Response);
+  rpc getUser(Request) returns (Response);
+  rpc GetUsers(Request) returns (Response);
+  rpc GetUser(Request) returns (Response);
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        let codes: Vec<(&str, usize)> = errors
+            .iter()
+            .map(|error| (error.code, error.span.start.line))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("method-name-case-conflict", 7),
+                ("duplicate-method-name", 9)
+            ]
+        );
+
+        let warning = &errors[0];
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(
+            warning.message,
+            "Method 'getUser' differs from 'GetUser' in service 'Users' only by case"
+        );
+        assert_eq!(warning.related[0].span.start.line, 6);
+    }
+
+    #[test]
+    fn test_parse_errors_carry_positions() {
+        let position = |content: &str| {
+            let error = parse_proto(content).unwrap_err();
+            let error = error.downcast_ref::<PositionedParseError>().unwrap();
+            (error.span.start.line, error.span.start.column)
+        };
+
+        // At the unexpected token
+        assert_eq!(position("message Test {\n  string = 1;\n}\n"), (1, 9));
+        // Where the lexer stopped
+        assert_eq!(
+            position("message Test {\n  string name = 1 ^;\n}\n"),
+            (1, 18)
+        );
+        assert_eq!(position("message Test {\n  string name = 1;\n"), (2, 0));
+    }
+
+    #[test]
+    fn test_option_value_types() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string a = 1 [deprecated = "yes"];
+  string b = 2 [json_name = 5];
+  repeated int32 c = 3 [packed = TRUE];
+  string d = 4 [deprecated = true, json_name = "dee"];
+  repeated int32 e = 5 [packed = false];
+}
+"#;
+        let errors: Vec<(usize, usize, String)> = validate_proto(&parse_proto(content).unwrap())
+            .into_iter()
+            .filter(|error| error.code == "invalid-option-value")
+            .map(|error| {
+                (
+                    error.span.start.line,
+                    error.span.start.column,
+                    error.message,
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    3,
+                    16,
+                    "Option 'deprecated' on field 'a' in message 'Test' must be a bool, found \"yes\""
+                        .to_string()
+                ),
+                (
+                    4,
+                    16,
+                    "Option 'json_name' on field 'b' in message 'Test' must be a string, found 5"
+                        .to_string()
+                ),
+                (
+                    5,
+                    24,
+                    "Option 'packed' on field 'c' in message 'Test' must be a bool, found TRUE"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_field_in_oneof() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  oneof value {
+    map<string, int32> counts = 1;
+    string name = 2;
+  }
+  oneof other {
+    int32 id = 3;
+  }
+  map<string, int32> totals = 4;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "map-in-oneof");
+        assert_eq!(
+            errors[0].message,
+            "Map field 'counts' is not allowed in oneof 'value' of message 'Test'"
+        );
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (4, 4)
+        );
+    }
+
+    #[test]
+    fn test_reserved_names_must_be_identifiers() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  reserved "1foo";
+  reserved "foo";
+  string name = 1;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-reserved-name");
+        assert_eq!(
+            errors[0].message,
+            "Reserved name '1foo' in message 'Test' is not a valid identifier"
+        );
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (3, 11)
+        );
+    }
+
+    #[test]
+    fn test_unsupported_and_misspelled_editions() {
+        let parsed = parse_proto("edition = \"2024\";\n").unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "unsupported-edition");
+        assert_eq!(
+            errors[0].message,
+            "Unsupported edition '2024'. Supported editions: 2023."
+        );
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (0, 0)
+        );
+
+        let parsed = parse_proto("edition = \"2O23\";\n").unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "invalid-edition");
+        assert_eq!(
+            errors[0].message,
+            "Invalid edition '2O23'; did you mean '2023'?"
+        );
+    }
+
+    #[test]
+    fn test_label_before_qualified_type() {
+        let content = r#"syntax = "proto2";
+
+message Test {
+  repeated foo.Bar x = 1;
+  optional .google.protobuf.Any y = 2;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Message(message) = &parsed.statements[0].node else {
+            panic!("expected a message");
+        };
+
+        let x = &message.fields[0];
+        assert_eq!(x.label, Some(FieldLabel::Repeated));
+        assert_eq!(x.field_type, "foo.Bar");
+        assert_eq!((x.type_span.start.column, x.type_span.end.column), (11, 18));
+        assert_eq!(x.span.start.column, 2);
+
+        let y = &message.fields[1];
+        assert_eq!(y.label, Some(FieldLabel::Optional));
+        assert_eq!(y.field_type, ".google.protobuf.Any");
+        assert_eq!(y.name, "y");
+        assert_eq!(y.number, 2);
+    }
+
+    #[test]
+    fn test_infinite_and_nan_option_values() {
+        let content = r#"syntax = "proto3";
+
+option (positive) = inf;
+option (negative) = -inf;
+option (missing) = nan;
+option (offset) = -1.5;
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let option = |name: &str| {
+            let value = parsed
+                .statements
+                .iter()
+                .find_map(|statement| match &statement.node {
+                    Statement::Option {
+                        name: option_name,
+                        value,
+                    } if option_name == name => Some(value),
+                    _ => None,
+                });
+            match value {
+                Some(OptionValue::Number(n)) => *n,
+                other => panic!("expected a number for {name}, got {other:?}"),
+            }
+        };
+        assert_eq!(option("(positive)"), f64::INFINITY);
+        assert_eq!(option("(negative)"), f64::NEG_INFINITY);
+        assert!(option("(missing)").is_nan());
+        assert_eq!(option("(offset)"), -1.5);
+
+        assert!(parse_proto("option (bad) = -infinite;").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_package() {
+        let content = r#"syntax = "proto3";
+
+package foo.v1;
+package bar.v1;
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "duplicate-package");
+        assert_eq!(
+            errors[0].message,
+            "Duplicate package declaration 'bar.v1'; a file can declare only one package"
+        );
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (3, 0)
+        );
+        assert_eq!(errors[0].related[0].span.start.line, 2);
+
+        let parsed = parse_proto("syntax = \"proto3\";\n\npackage foo.v1;\n").unwrap();
+        assert!(validate_proto(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_field_name() {
+        let content = r#"syntax = "proto3";
+
+message Test {
+  string x = 1;
+  oneof choice {
+    int32 x = 2;
+  }
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "duplicate-field-name");
+        assert_eq!(
+            errors[0].message,
+            "Duplicate field name 'x' in message 'Test'"
+        );
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (5, 10)
+        );
+        assert_eq!(errors[0].related[0].span.start.line, 3);
+
+        let content =
+            "syntax = \"proto3\";\n\nmessage Test {\n  string x = 1;\n  int32 y = 2;\n}\n";
+        assert!(validate_proto(&parse_proto(content).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_enum_reserved() {
+        let content = r#"syntax = "proto3";
+
+enum Status {
+  reserved 1, 2;
+  reserved 10 to max;
+  reserved "LEGACY";
+  STATUS_UNKNOWN = 0;
+  STATUS_ACTIVE = 3;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let Statement::Enum(enum_def) = &parsed.statements[0].node else {
+            panic!("expected an enum");
+        };
+        let ranges: Vec<(i64, i64)> = enum_def
+            .reserved_ranges
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect();
+        assert_eq!(ranges, [(1, 1), (2, 2), (10, i32::MAX as i64)]);
+        assert_eq!(enum_def.reserved_names[0].name, "LEGACY");
+        assert_eq!(enum_def.values.len(), 2);
+        assert!(validate_proto(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_enum_value_uses_reserved_number_or_name() {
+        let content = r#"syntax = "proto3";
+
+enum Status {
+  reserved 1, 2;
+  reserved "LEGACY";
+  STATUS_UNKNOWN = 0;
+  STATUS_ACTIVE = 2;
+  LEGACY = 3;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].code, "reserved-enum-number");
+        assert_eq!(
+            errors[0].message,
+            "Enum value 'STATUS_ACTIVE' in enum 'Status' uses number 2, which is reserved (2)"
+        );
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (6, 2)
+        );
+        assert_eq!(errors[1].code, "reserved-enum-name");
+        assert_eq!(
+            errors[1].message,
+            "Enum value 'LEGACY' in enum 'Status' uses a reserved name"
+        );
+    }
+
+    #[test]
+    fn test_feature_literal_values() {
+        let content = r#"edition = "2023";
+
+message Test {
+  string name = 1 [features = { field_presence: EXPLICIT }];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        assert!(validate_proto(&parsed).is_empty());
+
+        let content = r#"edition = "2023";
+
+option features = { enum_type: CLOSED, colour: RED };
+
+message Test {
+  string name = 1 [features = { field_presence: MAYBE }];
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].code, "unknown-feature");
+        assert_eq!(errors[0].message, "Unknown feature 'colour'");
+        assert_eq!(
+            (errors[0].span.start.line, errors[0].span.start.column),
+            (2, 39)
+        );
+        assert_eq!(errors[1].code, "invalid-feature-value");
+        assert_eq!(
+            errors[1].message,
+            "Invalid value 'MAYBE' for feature 'field_presence'; expected one of EXPLICIT, IMPLICIT, LEGACY_REQUIRED"
+        );
+        assert_eq!(
+            (errors[1].span.start.line, errors[1].span.start.column),
+            (5, 32)
+        );
+    }
+
+    #[test]
+    fn test_enum_options() {
+        let content = r#"syntax = "proto3";
+
+enum Status {
+  option allow_alias = true;
+  option deprecated = false;
+  STATUS_UNSPECIFIED = 0;
+  STATUS_STARTED = 1;
+  STATUS_RUNNING = 1;
+}
+"#;
+        assert!(validate_proto(&parse_proto(content).unwrap()).is_empty());
+
+        let content = r#"syntax = "proto3";
+
+enum Status {
+  option allow_alias = true;
+  option allow_alias = "x";
+  option packed = true;
+  STATUS_UNSPECIFIED = 0;
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        let summary: Vec<_> = errors
+            .iter()
+            .map(|error| {
+                (
+                    error.code,
+                    error.severity,
+                    error.span.start.line,
+                    error.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "duplicate-option",
+                    Severity::Error,
+                    4,
+                    "Option 'allow_alias' is set more than once on enum 'Status'"
+                ),
+                (
+                    "unknown-option",
+                    Severity::Warning,
+                    5,
+                    "Unknown option 'packed' on enum 'Status'"
+                ),
+                (
+                    "invalid-option-value",
+                    Severity::Error,
+                    4,
+                    "Option 'allow_alias' on enum 'Status' must be a bool, found \"x\""
+                ),
+            ]
+        );
+
+        // Unknown options are still reported when set twice
+        let content = r#"syntax = "proto3";
+
+enum Status {
+  option foo = 1;
+  option foo = 2;
+  STATUS_UNSPECIFIED = 0;
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        let summary: Vec<_> = errors
+            .iter()
+            .map(|error| (error.code, error.span.start.line))
+            .collect();
+        assert_eq!(summary, [("unknown-option", 3), ("duplicate-option", 4)]);
+        assert_eq!(errors[1].related[0].span.start.line, 3);
+    }
+
+    #[test]
+    fn test_group_is_contextual() {
+        // Outside proto2, `group` can name fields and types
+        let content = r#"syntax = "proto3";
+
+message group {}
+
+message Test {
+  string group = 1;
+  group parent = 2;
+  repeated group children = 3;
+}
+"#;
+        let parsed = parse_proto(content).unwrap();
+        assert!(validate_proto(&parsed).is_empty());
+        let Some(Statement::Message(message)) =
+            parsed.statements.get(1).map(|statement| &statement.node)
+        else {
+            panic!("expected message Test");
+        };
+        let fields: Vec<_> = message
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name.as_str(),
+                    field.field_type.as_str(),
+                    field.is_group,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("group", "string", false),
+                ("parent", "group", false),
+                ("children", "group", false),
+            ]
+        );
+        assert!(message.nested_messages.is_empty());
+
+        // A group declaration is still recognized, and rejected
+        let content = r#"syntax = "proto3";
+
+message Test {
+  group Result = 1 {
+    string url = 2;
+  }
+}
+"#;
+        let errors = validate_proto(&parse_proto(content).unwrap());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "group-requires-proto2");
+        assert_eq!(
+            errors[0].message,
+            "Group 'Result' in message 'Test' is only supported in proto2"
+        );
+    }
+
+    #[test]
+    fn test_import_paths() {
+        let errors = |content: &str| validate_proto(&parse_proto(content).unwrap());
+
+        assert!(errors("syntax = \"proto3\";\nimport \"foo.proto\";\n").is_empty());
+
+        let missing = errors("syntax = \"proto3\";\nimport \"foo\";\n");
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].code, "import-missing-extension");
+        assert_eq!(missing[0].severity, Severity::Warning);
+        assert_eq!(missing[0].message, "Import 'foo' doesn't end in '.proto'");
+        let replacement = missing[0].replacement.as_ref().unwrap();
+        assert_eq!(replacement.text, "\"foo.proto\"");
+        assert_eq!(
+            (replacement.span.start.column, replacement.span.end.column),
+            (7, 12)
+        );
+
+        let empty = errors("syntax = \"proto3\";\nimport \"\";\n");
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty[0].code, "empty-import-path");
+        assert_eq!(empty[0].severity, Severity::Error);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_parse_to_json() {
+        let json = parse_to_json(
+            "syntax = \"proto3\";\nmessage Test {\n  repeated string names = 1;\n}\n",
+        )
+        .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["syntax"], "proto3");
+        let message = &value["statements"][0]["Message"];
+        assert_eq!(message["name"], "Test");
+        let field = &message["fields"][0];
+        assert_eq!(field["name"], "names");
+        assert_eq!(field["field_type"], "string");
+        assert_eq!(field["number"], 1);
+        assert_eq!(field["label"], "Repeated");
+        assert_eq!(field["name_span"]["start"]["line"], 2);
+
+        assert!(parse_to_json("message {").is_err());
+    }
+
+    #[test]
+    fn test_nesting_depth_limit() {
+        let nested = |depth: usize| {
+            let mut content = "syntax = \"proto3\";\n".to_string();
+            for level in 0..depth {
+                content.push_str(&format!("message M{level} {{\n"));
+            }
+            content.push_str(&"}\n".repeat(depth));
+            content
+        };
+
+        let parsed = parse_proto(&nested(DEFAULT_MAX_NESTING_DEPTH)).unwrap();
+        let errors = validate_proto(&parsed);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        // A raised limit is honoured by the validator as well
+        let parsed = parse_proto_with_max_depth(&nested(110), 110).unwrap();
+        assert!(validate_proto_with_max_depth(&parsed, 110).is_empty());
+
+        // Validating with a lower limit reports where it stops
+        let errors = validate_proto(&parsed);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "nesting-too-deep");
+        assert_eq!(
+            errors[0].message,
+            "Message 'M100' is nested more than 100 levels deep and was not validated"
+        );
+        assert_eq!(errors[0].span.start.line, 101);
+
+        // Far past the limit fails cleanly instead of overflowing the stack
+        let error = parse_proto(&nested(100_000)).unwrap_err();
+        let error = error.downcast_ref::<PositionedParseError>().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Invalid syntax: nesting too deep: more than 100 levels"
+        );
+        assert_eq!(error.span.start.line, 101);
+
+        // Message literals count too
+        let literal = format!(
+            "option (deep) = {}{{}}{};\n",
+            "{ a ".repeat(9),
+            "}".repeat(9)
+        );
+        assert!(parse_proto_with_max_depth(&literal, 10).is_ok());
+        assert!(parse_proto_with_max_depth(&literal, 9).is_err());
+    }
+
+    #[test]
+    fn test_trailing_comma_in_field_options() {
+        let field = |content: &str| {
+            let parsed = parse_proto(content).unwrap();
+            let Some(Statement::Message(message)) =
+                parsed.statements.first().map(|statement| &statement.node)
+            else {
+                panic!("expected a message");
+            };
+            message.fields[0].clone()
+        };
+
+        let trailing = field("message Test {\n  int32 id = 1 [deprecated = true,];\n}\n");
+        assert_eq!(
+            trailing.options.get("deprecated"),
+            Some(&OptionValue::Bool(true))
+        );
+        assert_eq!(trailing.option_names.len(), 1);
+
+        let several = field("message Test {\n  int32 id = 1 [a = 1, b = 2];\n}\n");
+        let names: Vec<_> = several
+            .option_names
+            .iter()
+            .map(|option| option.name.as_str())
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+
+        // Still only one trailing comma, and not an empty list
+        assert!(parse_proto("message Test {\n  int32 id = 1 [a = 1,,];\n}\n").is_err());
+        assert!(parse_proto("message Test {\n  int32 id = 1 [,];\n}\n").is_err());
+    }
+
+    /// A message literal with default spans, for comparing against
+    /// [`without_spans`].
+    fn literal(fields: Vec<(&str, OptionValue)>) -> OptionValue {
+        OptionValue::Message(
+            fields
+                .into_iter()
+                .map(|(name, value)| LiteralField {
+                    name: name.to_string(),
+                    value,
+                    span: Span::default(),
+                })
+                .collect(),
+        )
+    }
+
+    fn without_spans(value: &OptionValue) -> OptionValue {
+        match value {
+            OptionValue::Message(fields) => OptionValue::Message(
+                fields
+                    .iter()
+                    .map(|field| LiteralField {
+                        name: field.name.clone(),
+                        value: without_spans(&field.value),
+                        span: Span::default(),
+                    })
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
 }